@@ -0,0 +1,448 @@
+use std::sync::Arc;
+
+use crate::canvas::{CanvasState, EditTarget};
+use crate::color::Color;
+use crate::document::Document;
+use crate::history::ActionKind;
+use crate::resample::collect_rows;
+use crate::selection::Selection;
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum AdjustmentKind {
+    BrightnessContrast,
+    HueSaturation,
+    Levels,
+    Curves,
+    Invert,
+    Desaturate,
+    Posterize,
+    Threshold,
+}
+
+impl AdjustmentKind {
+    pub fn all() -> [AdjustmentKind; 8] {
+        [
+            AdjustmentKind::BrightnessContrast,
+            AdjustmentKind::HueSaturation,
+            AdjustmentKind::Levels,
+            AdjustmentKind::Curves,
+            AdjustmentKind::Invert,
+            AdjustmentKind::Desaturate,
+            AdjustmentKind::Posterize,
+            AdjustmentKind::Threshold,
+        ]
+    }
+
+    /// Whether the adjustment has settings to choose before applying it.
+    pub fn has_settings(self) -> bool {
+        !matches!(self, AdjustmentKind::Invert | AdjustmentKind::Desaturate)
+    }
+}
+
+/// Tone curve through control points, mapping input values to outputs.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Curve {
+    /// Sorted by input value, with distinct inputs.
+    points: Vec<(u8, u8)>,
+}
+
+impl Default for Curve {
+    fn default() -> Self {
+        Self { points: vec![(0, 0), (255, 255)] }
+    }
+}
+
+impl Curve {
+    pub fn points(&self) -> &[(u8, u8)] {
+        &self.points
+    }
+
+    /// Adds a point, replacing the one with the same input. Returns its index.
+    pub fn add_point(&mut self, input: u8, output: u8) -> usize {
+        match self.points.binary_search_by_key(&input, |&(x, _)| x) {
+            Ok(i) => {
+                self.points[i].1 = output;
+                i
+            },
+            Err(i) => {
+                self.points.insert(i, (input, output));
+                i
+            },
+        }
+    }
+
+    /// Moves a point, keeping it between its neighbours.
+    pub fn move_point(&mut self, index: usize, input: u8, output: u8) {
+        let Some(&(current, _)) = self.points.get(index) else {
+            return;
+        };
+        let min = if index > 0 { self.points[index - 1].0.saturating_add(1) } else { 0 };
+        let max = self.points.get(index + 1).map_or(255, |&(x, _)| x.saturating_sub(1));
+        let input = if min <= max { input.clamp(min, max) } else { current };
+        self.points[index] = (input, output);
+    }
+
+    /// Removes a point, the curve always keeps two.
+    pub fn remove_point(&mut self, index: usize) {
+        if self.points.len() > 2 && index < self.points.len() {
+            self.points.remove(index);
+        }
+    }
+
+    /// Output for every input value, following a monotone cubic spline so
+    /// that the curve never overshoots its points.
+    pub fn table(&self) -> [u8; 256] {
+        let xs: Vec<f32> = self.points.iter().map(|&(x, _)| x as f32).collect();
+        let ys: Vec<f32> = self.points.iter().map(|&(_, y)| y as f32).collect();
+        let n = xs.len();
+        let mut table = [0; 256];
+        if n < 2 {
+            let value = ys.first().copied().unwrap_or(0.0) as u8;
+            return [value; 256];
+        }
+
+        // Fritsch-Carlson tangents.
+        let slopes: Vec<f32> = (0..n - 1).map(|k| (ys[k + 1] - ys[k]) / (xs[k + 1] - xs[k])).collect();
+        let mut tangents = vec![0.0; n];
+        tangents[0] = slopes[0];
+        tangents[n - 1] = slopes[n - 2];
+        for k in 1..n - 1 {
+            if slopes[k - 1] * slopes[k] > 0.0 {
+                tangents[k] = (slopes[k - 1] + slopes[k]) / 2.0;
+            }
+        }
+        for k in 0..n - 1 {
+            if slopes[k] == 0.0 {
+                tangents[k] = 0.0;
+                tangents[k + 1] = 0.0;
+                continue;
+            }
+            let (a, b) = (tangents[k] / slopes[k], tangents[k + 1] / slopes[k]);
+            let length = a.hypot(b);
+            if length > 3.0 {
+                tangents[k] = 3.0 / length * a * slopes[k];
+                tangents[k + 1] = 3.0 / length * b * slopes[k];
+            }
+        }
+
+        let mut segment = 0;
+        for (value, output) in table.iter_mut().enumerate() {
+            let x = value as f32;
+            *output = if x <= xs[0] {
+                ys[0]
+            } else if x >= xs[n - 1] {
+                ys[n - 1]
+            } else {
+                while x > xs[segment + 1] {
+                    segment += 1;
+                }
+                let h = xs[segment + 1] - xs[segment];
+                let t = (x - xs[segment]) / h;
+                let (t2, t3) = (t * t, t * t * t);
+                (2.0 * t3 - 3.0 * t2 + 1.0) * ys[segment]
+                    + (t3 - 2.0 * t2 + t) * h * tangents[segment]
+                    + (-2.0 * t3 + 3.0 * t2) * ys[segment + 1]
+                    + (t3 - t2) * h * tangents[segment + 1]
+            }
+            .round()
+            .clamp(0.0, 255.0) as u8;
+        }
+        table
+    }
+}
+
+/// Curves applied to all color channels, then to each one.
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct Curves {
+    pub master: Curve,
+    pub red: Curve,
+    pub green: Curve,
+    pub blue: Curve,
+}
+
+/// Color adjustment of a layer. Transparency is left as it is.
+#[derive(Clone, PartialEq, Debug)]
+pub enum Adjustment {
+    /// Both from -100 to 100.
+    BrightnessContrast { brightness: f32, contrast: f32 },
+    /// Hue shift in degrees, saturation and lightness from -100 to 100.
+    HueSaturation { hue: f32, saturation: f32, lightness: f32 },
+    /// Input values from `input_black` to `input_white` are stretched to
+    /// `output_black` to `output_white`, with `gamma` bending the midtones.
+    Levels { input_black: u8, input_white: u8, gamma: f32, output_black: u8, output_white: u8 },
+    Curves(Curves),
+    Invert,
+    Desaturate,
+    /// Number of values kept in each channel, from 2.
+    Posterize { levels: u8 },
+    /// Pixels at least this bright become white, the others black.
+    Threshold { level: u8 },
+}
+
+impl Adjustment {
+    /// The adjustment of `kind` with settings that leave the image as it is,
+    /// where there are such settings.
+    pub fn new(kind: AdjustmentKind) -> Self {
+        match kind {
+            AdjustmentKind::BrightnessContrast => Adjustment::BrightnessContrast { brightness: 0.0, contrast: 0.0 },
+            AdjustmentKind::HueSaturation => Adjustment::HueSaturation { hue: 0.0, saturation: 0.0, lightness: 0.0 },
+            AdjustmentKind::Levels => Adjustment::Levels {
+                input_black: 0,
+                input_white: 255,
+                gamma: 1.0,
+                output_black: 0,
+                output_white: 255,
+            },
+            AdjustmentKind::Curves => Adjustment::Curves(Curves::default()),
+            AdjustmentKind::Invert => Adjustment::Invert,
+            AdjustmentKind::Desaturate => Adjustment::Desaturate,
+            AdjustmentKind::Posterize => Adjustment::Posterize { levels: 4 },
+            AdjustmentKind::Threshold => Adjustment::Threshold { level: 128 },
+        }
+    }
+
+    pub fn kind(&self) -> AdjustmentKind {
+        match self {
+            Adjustment::BrightnessContrast { .. } => AdjustmentKind::BrightnessContrast,
+            Adjustment::HueSaturation { .. } => AdjustmentKind::HueSaturation,
+            Adjustment::Levels { .. } => AdjustmentKind::Levels,
+            Adjustment::Curves(_) => AdjustmentKind::Curves,
+            Adjustment::Invert => AdjustmentKind::Invert,
+            Adjustment::Desaturate => AdjustmentKind::Desaturate,
+            Adjustment::Posterize { .. } => AdjustmentKind::Posterize,
+            Adjustment::Threshold { .. } => AdjustmentKind::Threshold,
+        }
+    }
+
+    /// Per-channel tables, or the color conversion, ready to run on pixels.
+    fn prepare(&self) -> Mapping {
+        let table = |map: &dyn Fn(f32) -> f32| {
+            let mut table = [0; 256];
+            for (value, output) in table.iter_mut().enumerate() {
+                *output = (map(value as f32 / 255.0) * 255.0).round().clamp(0.0, 255.0) as u8;
+            }
+            Box::new([table; 3])
+        };
+
+        match self {
+            Adjustment::BrightnessContrast { brightness, contrast } => {
+                let brightness = brightness / 100.0;
+                let slant = ((contrast / 100.0 + 1.0) * std::f32::consts::FRAC_PI_4).tan();
+                Mapping::Tables(table(&|value| {
+                    let value = if brightness < 0.0 { value * (1.0 + brightness) } else { value + (1.0 - value) * brightness };
+                    (value - 0.5) * slant + 0.5
+                }))
+            },
+            Adjustment::HueSaturation { hue, saturation, lightness } => {
+                Mapping::HueSaturation(*hue, saturation / 100.0, lightness / 100.0)
+            },
+            Adjustment::Levels { input_black, input_white, gamma, output_black, output_white } => {
+                let (input_black, input_white) = (*input_black as f32 / 255.0, *input_white as f32 / 255.0);
+                let (output_black, output_white) = (*output_black as f32 / 255.0, *output_white as f32 / 255.0);
+                let range = (input_white - input_black).max(1.0 / 255.0);
+                let exponent = 1.0 / gamma.max(0.01);
+                Mapping::Tables(table(&|value| {
+                    let value = ((value - input_black) / range).clamp(0.0, 1.0).powf(exponent);
+                    output_black + (output_white - output_black) * value
+                }))
+            },
+            Adjustment::Curves(curves) => {
+                let master = curves.master.table();
+                let mut tables = [curves.red.table(), curves.green.table(), curves.blue.table()];
+                for channel in &mut tables {
+                    *channel = master.map(|value| channel[value as usize]);
+                }
+                Mapping::Tables(Box::new(tables))
+            },
+            Adjustment::Invert => Mapping::Tables(table(&|value| 1.0 - value)),
+            Adjustment::Desaturate => Mapping::Desaturate,
+            Adjustment::Posterize { levels } => {
+                let steps = (*levels).max(2) as f32 - 1.0;
+                Mapping::Tables(table(&|value| (value * steps).round() / steps))
+            },
+            Adjustment::Threshold { level } => Mapping::Threshold(*level),
+        }
+    }
+}
+
+enum Mapping {
+    Tables(Box<[[u8; 256]; 3]>),
+    HueSaturation(f32, f32, f32),
+    Desaturate,
+    Threshold(u8),
+}
+
+impl Mapping {
+    fn apply(&self, color: Color) -> Color {
+        let [r, g, b, a] = color.to_array();
+        match self {
+            Mapping::Tables(tables) => Color::from_rgba_unmultiplied(tables[0][r as usize], tables[1][g as usize], tables[2][b as usize], a),
+            Mapping::HueSaturation(hue, saturation, lightness) => {
+                let (h, s, l) = rgb_to_hsl(r, g, b);
+                let s = (s * (1.0 + saturation)).clamp(0.0, 1.0);
+                let l = if *lightness < 0.0 { l * (1.0 + lightness) } else { l + (1.0 - l) * lightness };
+                let [r, g, b] = hsl_to_rgb((h + hue).rem_euclid(360.0), s, l);
+                Color::from_rgba_unmultiplied(r, g, b, a)
+            },
+            Mapping::Desaturate => Color::from_gray(luminance(color)).with_alpha(a),
+            Mapping::Threshold(level) => {
+                let value = if luminance(color) >= *level { 255 } else { 0 };
+                Color::from_gray(value).with_alpha(a)
+            },
+        }
+    }
+}
+
+/// Perceived brightness of the color, ignoring its transparency.
+pub fn luminance(color: Color) -> u8 {
+    (0.3 * color.r() as f32 + 0.59 * color.g() as f32 + 0.11 * color.b() as f32).round() as u8
+}
+
+/// Hue in degrees, saturation and lightness from 0 to 1.
+fn rgb_to_hsl(r: u8, g: u8, b: u8) -> (f32, f32, f32) {
+    let (r, g, b) = (r as f32 / 255.0, g as f32 / 255.0, b as f32 / 255.0);
+    let (max, min) = (r.max(g).max(b), r.min(g).min(b));
+    let l = (max + min) / 2.0;
+    let delta = max - min;
+    if delta == 0.0 {
+        return (0.0, 0.0, l);
+    }
+    let s = delta / (1.0 - (2.0 * l - 1.0).abs());
+    let h = if max == r {
+        60.0 * ((g - b) / delta).rem_euclid(6.0)
+    } else if max == g {
+        60.0 * ((b - r) / delta + 2.0)
+    } else {
+        60.0 * ((r - g) / delta + 4.0)
+    };
+    (h, s, l)
+}
+
+fn hsl_to_rgb(h: f32, s: f32, l: f32) -> [u8; 3] {
+    let chroma = (1.0 - (2.0 * l - 1.0).abs()) * s;
+    let x = chroma * (1.0 - ((h / 60.0).rem_euclid(2.0) - 1.0).abs());
+    let (r, g, b) = match (h / 60.0) as u32 {
+        0 => (chroma, x, 0.0),
+        1 => (x, chroma, 0.0),
+        2 => (0.0, chroma, x),
+        3 => (0.0, x, chroma),
+        4 => (x, 0.0, chroma),
+        _ => (chroma, 0.0, x),
+    };
+    let m = l - chroma / 2.0;
+    [r, g, b].map(|value| ((value + m) * 255.0).round().clamp(0.0, 255.0) as u8)
+}
+
+/// Number of pixels of each value, over the non-empty pixels of a layer.
+pub struct Histogram {
+    pub red: [u32; 256],
+    pub green: [u32; 256],
+    pub blue: [u32; 256],
+    pub luminance: [u32; 256],
+}
+
+impl CanvasState {
+    pub fn histogram(&self, index: usize, target: EditTarget) -> Histogram {
+        let mut histogram = Histogram { red: [0; 256], green: [0; 256], blue: [0; 256], luminance: [0; 256] };
+        for y in 0..self.height {
+            for x in 0..self.width {
+                if let Some(color) = self.get_from_target(index, target, x, y) {
+                    histogram.red[color.r() as usize] += 1;
+                    histogram.green[color.g() as usize] += 1;
+                    histogram.blue[color.b() as usize] += 1;
+                    histogram.luminance[luminance(color) as usize] += 1;
+                }
+            }
+        }
+        histogram
+    }
+
+    /// Replaces the pixels or the mask of a layer by `operation` applied to
+    /// all of them, masks going through it as grays. Only pixels inside
+    /// `selection` change when there is one, and the locks of the layer are
+    /// kept. Returns `false` when nothing changed.
+    pub(crate) fn filter_target(
+        &mut self,
+        index: usize,
+        target: EditTarget,
+        selection: Option<&Selection>,
+        operation: impl FnOnce(&[Option<Color>]) -> Vec<Option<Color>>,
+    ) -> bool {
+        let Some(layer) = self.layers.get(index) else {
+            return false;
+        };
+        let width = self.width;
+        let selected = |x: usize, y: usize| selection.is_none_or(|selection| selection.contains(x, y));
+
+        match target {
+            EditTarget::Pixels if !layer.is_group() => {
+                let data = &layer.data;
+                let filtered = operation(data);
+                let result = collect_rows(width, self.height, |x, y| {
+                    let i = y * width + x;
+                    if selected(x, y) { layer.constrain_edit(data[i], filtered[i]) } else { data[i] }
+                });
+                if result == **data {
+                    return false;
+                }
+                self.layers[index].data = Arc::new(result);
+            },
+            EditTarget::Mask => {
+                let Some(mask) = &layer.mask else {
+                    return false;
+                };
+                let gray: Vec<Option<Color>> = mask.data.iter().map(|&value| Some(Color::from_gray(value))).collect();
+                let filtered = operation(&gray);
+                let result = collect_rows(width, self.height, |x, y| {
+                    let i = y * width + x;
+                    if selected(x, y) { filtered[i].map_or(255, luminance) } else { mask.data[i] }
+                });
+                if result == mask.data {
+                    return false;
+                }
+                if let Some(mask) = &mut self.layers[index].mask {
+                    mask.data = result;
+                }
+            },
+            EditTarget::Pixels => return false,
+        }
+        true
+    }
+
+    /// Adjusts the pixels or the mask of a layer, only inside `selection`
+    /// when there is one. Returns `false` when nothing changed.
+    pub fn adjust_layer(&mut self, index: usize, target: EditTarget, adjustment: &Adjustment, selection: Option<&Selection>) -> bool {
+        let mapping = adjustment.prepare();
+        let (width, height) = (self.width, self.height);
+        self.filter_target(index, target, selection, |data| {
+            collect_rows(width, height, |x, y| data[y * width + x].map(|color| mapping.apply(color)))
+        })
+    }
+}
+
+impl Document {
+    /// Adjusts the active layer, or its mask while it is edited, within the
+    /// selection. A single undoable action.
+    pub fn adjust(&mut self, adjustment: &Adjustment) -> bool {
+        if !self.canvas.active_layer_editable() {
+            return false;
+        }
+        let (index, target) = (self.canvas.active_layer_index, self.canvas.active_target());
+        let selection = self.selection.take();
+        let changed = self.edit_layers(ActionKind::Adjustment(adjustment.kind()), |canvas| {
+            canvas.adjust_layer(index, target, adjustment, selection.as_ref())
+        });
+        self.selection = selection;
+        changed
+    }
+
+    /// The canvas as it would look once adjusted, for live previews.
+    pub fn preview_adjustment(&self, adjustment: &Adjustment) -> CanvasState {
+        let mut preview = self.canvas.clone();
+        if self.canvas.active_layer_editable() {
+            let target = self.canvas.active_target();
+            preview.adjust_layer(self.canvas.active_layer_index, target, adjustment, self.selection.as_ref());
+        }
+        preview
+    }
+}
@@ -0,0 +1,160 @@
+use crate::file_format::FileError;
+
+use super::hose::{HoseSelection, ImageHose};
+use super::tip::BrushTip;
+
+/// Signature of `.gbr` headers from version 2 on.
+const GBR_MAGIC: &[u8; 4] = b"GIMP";
+/// Largest side accepted, to refuse corrupt sizes before allocating.
+const MAX_SIDE: usize = 8192;
+
+fn invalid(reason: &str) -> FileError {
+    FileError::InvalidBrush(reason.to_string())
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> Result<usize, FileError> {
+    bytes
+        .get(offset..offset + 4)
+        .map(|field| u32::from_be_bytes([field[0], field[1], field[2], field[3]]) as usize)
+        .ok_or_else(|| invalid("truncated header"))
+}
+
+/// Reads the `.gbr` brush at the start of `bytes`. Returns the tip, its
+/// spacing in percent of the tip width and the bytes it takes. Color brushes
+/// keep only their transparency, as every brush paints with the current
+/// color.
+pub fn read_gbr(bytes: &[u8]) -> Result<(BrushTip, f32, usize), FileError> {
+    let header_size = read_u32(bytes, 0)?;
+    let version = read_u32(bytes, 4)?;
+    let width = read_u32(bytes, 8)?;
+    let height = read_u32(bytes, 12)?;
+    let depth = read_u32(bytes, 16)?;
+
+    let (name_start, spacing) = match version {
+        1 => (20, 25),
+        2 => {
+            if bytes.get(20..24) != Some(GBR_MAGIC) {
+                return Err(invalid("missing GIMP signature"));
+            }
+            (28, read_u32(bytes, 24)?)
+        },
+        _ => return Err(invalid("unsupported version")),
+    };
+    if header_size < name_start || width == 0 || height == 0 || width > MAX_SIDE || height > MAX_SIDE {
+        return Err(invalid("bad header"));
+    }
+    if depth != 1 && depth != 4 {
+        return Err(invalid("unsupported pixel depth"));
+    }
+
+    let name = bytes.get(name_start..header_size).ok_or_else(|| invalid("truncated header"))?;
+    let name = String::from_utf8_lossy(name).trim_end_matches('\0').to_string();
+
+    let end = header_size + width * height * depth;
+    let pixels = bytes.get(header_size..end).ok_or_else(|| invalid("truncated pixels"))?;
+    let alpha = match depth {
+        1 => pixels.to_vec(),
+        _ => pixels.chunks_exact(4).map(|pixel| pixel[3]).collect(),
+    };
+    let tip = BrushTip::new(name, width, height, alpha).ok_or_else(|| invalid("bad header"))?;
+    Ok((tip, spacing as f32, end))
+}
+
+/// Reads a `.gih` image hose: a line with its name, a line with the number
+/// of cells and how they are picked, then each cell as a `.gbr` brush.
+/// Returns the hose and the spacing of its first cell.
+pub fn read_gih(bytes: &[u8]) -> Result<(ImageHose, f32), FileError> {
+    let mut lines = bytes.splitn(3, |&byte| byte == b'\n');
+    let name = lines.next().map(String::from_utf8_lossy).unwrap_or_default().trim().to_string();
+    let parameters = lines.next().ok_or_else(|| invalid("truncated header"))?;
+    let parameters = String::from_utf8_lossy(parameters);
+    let mut cells_data = lines.next().ok_or_else(|| invalid("no cells"))?;
+
+    let mut words = parameters.split_whitespace();
+    let cell_count: usize = words.next().and_then(|count| count.parse().ok()).ok_or_else(|| invalid("bad cell count"))?;
+    let options: Vec<(&str, &str)> = words.filter_map(|word| word.split_once(':')).collect();
+    let option = |key: &str| options.iter().find(|(name, _)| *name == key).map(|&(_, value)| value);
+
+    let dimensions = option("dim").and_then(|dim| dim.parse().ok()).unwrap_or(1usize).clamp(1, 4);
+    let mut ranks = Vec::new();
+    let mut selection = Vec::new();
+    for dimension in 0..dimensions {
+        let rank = option(&format!("rank{}", dimension)).and_then(|rank| rank.parse().ok());
+        ranks.push(rank.unwrap_or(if dimensions == 1 { cell_count } else { 1 }).max(1));
+        selection.push(option(&format!("sel{}", dimension)).map_or(HoseSelection::Incremental, HoseSelection::from_gimp));
+    }
+
+    let mut cells = Vec::with_capacity(cell_count.min(1024));
+    let mut spacing = 25.0;
+    for index in 0..cell_count {
+        let (cell, cell_spacing, length) = read_gbr(cells_data)?;
+        if index == 0 {
+            spacing = cell_spacing;
+        }
+        cells.push(cell);
+        cells_data = &cells_data[length..];
+    }
+
+    let hose = ImageHose::new(name, cells, ranks, selection).ok_or_else(|| invalid("no cells"))?;
+    Ok((hose, spacing))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A version 2 `.gbr` brush of `depth` bytes per pixel.
+    fn gbr(name: &str, width: u32, height: u32, depth: u32, spacing: u32, pixels: &[u8]) -> Vec<u8> {
+        let header_size = 28 + name.len() as u32 + 1;
+        let mut bytes = Vec::new();
+        for field in [header_size, 2, width, height, depth] {
+            bytes.extend(field.to_be_bytes());
+        }
+        bytes.extend(GBR_MAGIC);
+        bytes.extend(spacing.to_be_bytes());
+        bytes.extend(name.as_bytes());
+        bytes.push(0);
+        bytes.extend(pixels);
+        bytes
+    }
+
+    #[test]
+    fn reads_grayscale_and_color_brushes() {
+        let bytes = gbr("Dots", 2, 1, 1, 40, &[0, 255]);
+        let (tip, spacing, length) = read_gbr(&bytes).unwrap();
+        assert_eq!((tip.name(), tip.width(), tip.height(), tip.alpha()), ("Dots", 2, 1, &[0, 255][..]));
+        assert_eq!((spacing, length), (40.0, bytes.len()));
+
+        let (tip, _, _) = read_gbr(&gbr("Color", 1, 2, 4, 10, &[255, 0, 0, 10, 0, 0, 255, 200])).unwrap();
+        assert_eq!(tip.alpha(), [10, 200]);
+    }
+
+    #[test]
+    fn rejects_broken_brushes() {
+        let bytes = gbr("Short", 2, 2, 1, 10, &[1, 2, 3]);
+        assert!(read_gbr(&bytes).is_err());
+        assert!(read_gbr(&bytes[..10]).is_err());
+        assert!(read_gbr(&gbr("Deep", 1, 1, 3, 10, &[1, 2, 3])).is_err());
+
+        let mut unsigned = gbr("Unsigned", 1, 1, 1, 10, &[1]);
+        unsigned[20..24].copy_from_slice(b"XXXX");
+        assert!(read_gbr(&unsigned).is_err());
+    }
+
+    #[test]
+    fn reads_image_hoses_cell_by_cell() {
+        let mut bytes = b"Leaves\n3 ncells:3 dim:1 rank0:3 sel0:random\n".to_vec();
+        for (i, spacing) in [(1, 30), (2, 50), (3, 70)] {
+            bytes.extend(gbr("cell", 1, 1, 1, spacing, &[i * 50]));
+        }
+        let (hose, spacing) = read_gih(&bytes).unwrap();
+        assert_eq!(hose.name(), "Leaves");
+        assert_eq!(spacing, 30.0);
+        assert_eq!(hose.ranks(), [3]);
+        assert_eq!(hose.selection(), [HoseSelection::Random]);
+        assert_eq!(hose.cells().iter().map(|cell| cell.alpha()[0]).collect::<Vec<_>>(), [50, 100, 150]);
+
+        bytes.truncate(bytes.len() - 5);
+        assert!(read_gih(&bytes).is_err());
+    }
+}
@@ -0,0 +1,135 @@
+use std::f32::consts::PI;
+
+use serde::{Serialize, Deserialize};
+
+use super::tip::{content_hash, BrushTip, InvalidTip, TipId};
+
+/// How an image hose picks its cell along one of its dimensions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum HoseSelection {
+    /// One cell after the other, over and over.
+    Incremental,
+    Random,
+    /// Following the stroke direction.
+    Angular,
+    Pressure,
+}
+
+impl HoseSelection {
+    /// Reads the GIMP name of the selection mode. Modes depending on input
+    /// Rustique does not track, like tilt, cycle through the cells instead.
+    pub fn from_gimp(name: &str) -> Self {
+        match name {
+            "random" => HoseSelection::Random,
+            "angular" => HoseSelection::Angular,
+            "pressure" => HoseSelection::Pressure,
+            _ => HoseSelection::Incremental,
+        }
+    }
+}
+
+/// What an image hose picks its cell from, at each dab.
+pub struct HoseInput {
+    /// Dabs laid down so far.
+    pub step: usize,
+    /// Stroke direction, in radians.
+    pub angle: f32,
+    pub pressure: f32,
+}
+
+/// A brush made of several tips, one of which is picked at each dab, as GIMP
+/// image hoses do. The cells form a grid of `ranks` along each dimension,
+/// and each dimension has its own way of picking.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(try_from = "HoseData", into = "HoseData")]
+pub struct ImageHose {
+    id: TipId,
+    name: String,
+    cells: Vec<BrushTip>,
+    ranks: Vec<usize>,
+    selection: Vec<HoseSelection>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+struct HoseData {
+    name: String,
+    cells: Vec<BrushTip>,
+    ranks: Vec<usize>,
+    selection: Vec<HoseSelection>,
+}
+
+impl TryFrom<HoseData> for ImageHose {
+    type Error = InvalidTip;
+
+    fn try_from(data: HoseData) -> Result<Self, InvalidTip> {
+        ImageHose::new(data.name, data.cells, data.ranks, data.selection).ok_or(InvalidTip)
+    }
+}
+
+impl From<ImageHose> for HoseData {
+    fn from(hose: ImageHose) -> Self {
+        Self {
+            name: hose.name,
+            cells: hose.cells,
+            ranks: hose.ranks,
+            selection: hose.selection,
+        }
+    }
+}
+
+impl ImageHose {
+    /// `None` without cells, or when `ranks` and `selection` do not describe
+    /// the same dimensions.
+    pub fn new(name: String, cells: Vec<BrushTip>, ranks: Vec<usize>, selection: Vec<HoseSelection>) -> Option<Self> {
+        if cells.is_empty() || ranks.is_empty() || ranks.len() != selection.len() || ranks.contains(&0) {
+            return None;
+        }
+        let cell_ids: Vec<u8> = cells.iter().flat_map(|cell| cell.id().0.to_le_bytes()).collect();
+        Some(Self {
+            id: TipId(content_hash(cells.len(), ranks.len(), &cell_ids)),
+            name,
+            cells,
+            ranks,
+            selection,
+        })
+    }
+
+    pub fn id(&self) -> TipId {
+        self.id
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn cells(&self) -> &[BrushTip] {
+        &self.cells
+    }
+
+    pub fn ranks(&self) -> &[usize] {
+        &self.ranks
+    }
+
+    pub fn selection(&self) -> &[HoseSelection] {
+        &self.selection
+    }
+
+    /// Index of the cell to paint with. `random` is called once per
+    /// randomly picked dimension.
+    pub fn select(&self, input: &HoseInput, mut random: impl FnMut() -> u64) -> usize {
+        let mut index = 0;
+        for (&rank, selection) in self.ranks.iter().zip(&self.selection) {
+            let choice = match selection {
+                HoseSelection::Incremental => input.step % rank,
+                HoseSelection::Random => (random() % rank as u64) as usize,
+                HoseSelection::Angular => {
+                    let turns = (input.angle / (2.0 * PI)).rem_euclid(1.0);
+                    (turns * rank as f32).round() as usize % rank
+                },
+                HoseSelection::Pressure => ((input.pressure.clamp(0.0, 1.0) * rank as f32) as usize).min(rank - 1),
+            };
+            index = index * rank + choice;
+        }
+        index.min(self.cells.len() - 1)
+    }
+}
@@ -0,0 +1,141 @@
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::file_format::FileError;
+
+use super::gimp::{read_gbr, read_gih};
+use super::hose::ImageHose;
+use super::preset::PresetPack;
+use super::tip::{file_stem, BrushTip, TipId};
+
+/// Extensions of the files custom brushes are read from.
+pub const BRUSH_FILE_EXTENSIONS: [&str; 3] = ["png", "gbr", "gih"];
+
+/// The shape of a custom brush.
+#[derive(Clone)]
+pub enum CustomTip {
+    Bitmap(BrushTip),
+    Hose(ImageHose),
+}
+
+impl CustomTip {
+    pub fn id(&self) -> TipId {
+        match self {
+            CustomTip::Bitmap(tip) => tip.id(),
+            CustomTip::Hose(hose) => hose.id(),
+        }
+    }
+}
+
+/// A custom brush read from a file.
+pub struct ImportedBrush {
+    pub tip: CustomTip,
+    /// Distance between dabs asked by the file, in percent of the tip width.
+    pub spacing: Option<f32>,
+}
+
+/// Reads a custom brush from an image, a GIMP brush or a GIMP image hose.
+pub fn load_brush_file<P: AsRef<Path>>(path: P) -> Result<ImportedBrush, FileError> {
+    let path = path.as_ref();
+    let extension = path.extension().and_then(|ext| ext.to_str()).map(str::to_lowercase);
+    match extension.as_deref() {
+        Some("gbr") => {
+            let bytes = fs::read(path).map_err(FileError::Read)?;
+            let (tip, spacing, _) = read_gbr(&bytes)?;
+            Ok(ImportedBrush {
+                tip: CustomTip::Bitmap(tip),
+                spacing: Some(spacing),
+            })
+        },
+        Some("gih") => {
+            let bytes = fs::read(path).map_err(FileError::Read)?;
+            let (hose, spacing) = read_gih(&bytes)?;
+            Ok(ImportedBrush {
+                tip: CustomTip::Hose(hose),
+                spacing: Some(spacing),
+            })
+        },
+        _ => Ok(ImportedBrush {
+            tip: CustomTip::Bitmap(BrushTip::load(path)?),
+            spacing: None,
+        }),
+    }
+}
+
+/// Folder holding the settings of Rustique for the current user, following
+/// the conventions of each platform.
+pub fn config_dir() -> Option<PathBuf> {
+    let base = if cfg!(windows) {
+        env::var_os("APPDATA").map(PathBuf::from)
+    } else if cfg!(target_os = "macos") {
+        env::var_os("HOME").map(|home| PathBuf::from(home).join("Library/Application Support"))
+    } else {
+        env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+    };
+    base.map(|base| base.join("rustique"))
+}
+
+/// Folder of the custom brushes kept from one session to the next.
+pub fn user_brush_dir() -> Option<PathBuf> {
+    config_dir().map(|dir| dir.join("brushes"))
+}
+
+/// File the presets of the user are kept in.
+pub fn user_presets_path() -> Option<PathBuf> {
+    config_dir().map(|dir| dir.join("brush_presets.json"))
+}
+
+/// Writes `pack` as the presets of the user, creating the configuration
+/// folder if needed.
+pub fn save_user_presets(pack: &PresetPack) -> Result<(), FileError> {
+    let Some(path) = user_presets_path() else {
+        return Ok(());
+    };
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).map_err(FileError::Create)?;
+    }
+    pack.save(path)
+}
+
+/// Every brush found in `dir`. Files that cannot be read as brushes are
+/// skipped, so that one broken file does not hide the others.
+pub fn load_brush_library(dir: &Path) -> Vec<ImportedBrush> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut paths: Vec<PathBuf> = entries.filter_map(|entry| entry.ok().map(|entry| entry.path())).collect();
+    paths.sort();
+    paths
+        .into_iter()
+        .filter(|path| {
+            path.extension()
+                .and_then(|ext| ext.to_str())
+                .is_some_and(|ext| BRUSH_FILE_EXTENSIONS.iter().any(|known| ext.eq_ignore_ascii_case(known)))
+        })
+        .filter_map(|path| load_brush_file(path).ok())
+        .collect()
+}
+
+/// Copies the brush file at `path` into the library folder `dir`, under a
+/// file name not taken yet. Returns the path of the copy.
+pub fn copy_to_library(path: &Path, dir: &Path) -> Result<PathBuf, FileError> {
+    fs::create_dir_all(dir).map_err(FileError::Create)?;
+    let stem: String = file_stem(path)
+        .chars()
+        .map(|c| if c.is_alphanumeric() || matches!(c, ' ' | '-' | '_') { c } else { '_' })
+        .collect();
+    let stem = if stem.trim().is_empty() { "brush".to_string() } else { stem };
+    let extension = path.extension().map(|ext| ext.to_string_lossy().to_lowercase()).unwrap_or_default();
+
+    let mut copy = dir.join(format!("{}.{}", stem, extension));
+    let mut number = 2;
+    while copy.exists() {
+        copy = dir.join(format!("{} {}.{}", stem, number, extension));
+        number += 1;
+    }
+    fs::copy(path, &copy).map_err(FileError::Write)?;
+    Ok(copy)
+}
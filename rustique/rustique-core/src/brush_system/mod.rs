@@ -0,0 +1,773 @@
+use std::collections::{BTreeSet, HashMap};
+use std::f32::consts::PI;
+use std::sync::Arc;
+
+use rayon::prelude::*;
+use serde::{Serialize, Deserialize};
+
+mod gimp;
+mod hose;
+mod library;
+mod preset;
+mod tip;
+
+pub use crate::compositing::BlendMode;
+pub use gimp::{read_gbr, read_gih};
+pub use hose::{HoseInput, HoseSelection, ImageHose};
+pub use library::{
+    config_dir, copy_to_library, load_brush_file, load_brush_library, save_user_presets, user_brush_dir,
+    user_presets_path, CustomTip, ImportedBrush, BRUSH_FILE_EXTENSIONS,
+};
+pub use preset::{BrushPreset, PresetPack};
+pub use tip::{BrushTip, InvalidTip, TipId};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum BrushType {
+    Round,
+    Flat,
+    Bright,
+    Filbert,
+    Fan,
+    Angle,
+    Mop,
+    Rigger,
+    /// Bitmap tip or image hose kept by the [`BrushManager`].
+    Custom(TipId),
+}
+
+impl BrushType {
+    /// The built-in tips.
+    pub fn all_types() -> Vec<BrushType> {
+        vec![
+            BrushType::Round,
+            BrushType::Flat,
+            BrushType::Bright,
+            BrushType::Filbert,
+            BrushType::Fan,
+            BrushType::Angle,
+            BrushType::Mop,
+            BrushType::Rigger,
+        ]
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct BrushProperties {
+    pub brush_type: BrushType,
+    pub size: f32,
+    pub stretch_factor: f32,
+    pub angle_sensitivity: f32,
+    pub pressure_sensitivity: f32,
+    pub blend_mode: BlendMode,
+    pub opacity: f32,
+    pub spacing: f32,
+    pub hardness: f32,
+    pub base_rotation: f32,
+    pub pressure_affects_size: bool,
+    pub pressure_affects_opacity: bool,
+    pub pressure_size_min: f32,
+    pub pressure_opacity_min: f32,
+}
+
+impl Default for BrushProperties {
+    fn default() -> Self {
+        Self {
+            brush_type: BrushType::Round,
+            size: 10.0,
+            stretch_factor: 1.0,
+            angle_sensitivity: 0.0,
+            pressure_sensitivity: 0.5,
+            blend_mode: BlendMode::Normal,
+            opacity: 1.0,
+            spacing: 0.05,
+            hardness: 1.0,
+            base_rotation: 0.0,
+            pressure_affects_size: true,
+            pressure_affects_opacity: true,
+            pressure_size_min: 0.2,
+            pressure_opacity_min: 0.1,
+        }
+    }
+}
+
+impl BrushProperties {
+    pub fn from_type(brush_type: BrushType) -> Self {
+        let mut properties = Self {
+            brush_type,
+            ..Self::default()
+        };
+        
+        match brush_type {
+            BrushType::Round => {
+                properties.stretch_factor = 1.0;
+                properties.angle_sensitivity = 0.0;
+                properties.hardness = 1.0;
+                properties.spacing = 0.05;
+            },
+            BrushType::Flat => {
+                properties.stretch_factor = 4.0;
+                properties.angle_sensitivity = 0.8;
+                properties.hardness = 1.0;
+                properties.spacing = 0.05;
+            },
+            BrushType::Bright => {
+                properties.stretch_factor = 3.0;
+                properties.angle_sensitivity = 0.7;
+                properties.hardness = 1.0;
+                properties.spacing = 0.05;
+            },
+            BrushType::Filbert => {
+                properties.stretch_factor = 2.5;
+                properties.angle_sensitivity = 0.6;
+                properties.hardness = 0.8;
+                properties.spacing = 0.05;
+            },
+            BrushType::Fan => {
+                properties.stretch_factor = 3.0;
+                properties.angle_sensitivity = 0.8; 
+                properties.hardness = 1.0;
+                properties.spacing = 0.08;
+            },
+            BrushType::Angle => {
+                properties.stretch_factor = 2.0;
+                properties.angle_sensitivity = 0.8;
+                properties.base_rotation = PI / 4.0;
+                properties.hardness = 1.0;
+                properties.spacing = 0.05;
+            },
+            BrushType::Mop => {
+                properties.stretch_factor = 1.2;
+                properties.angle_sensitivity = 0.1;
+                properties.hardness = 0.5;
+                properties.spacing = 0.03;
+            },
+            BrushType::Rigger => {
+                properties.stretch_factor = 0.5;
+                properties.angle_sensitivity = 0.2;
+                properties.hardness = 1.0;
+                properties.spacing = 0.02;
+            },
+            BrushType::Custom(_) => {
+                properties.stretch_factor = 1.0;
+                properties.angle_sensitivity = 0.0;
+                properties.hardness = 1.0;
+                properties.spacing = 0.25;
+            },
+        }
+        
+        properties
+    }
+}
+
+/// Subsamples per side of each brush mask pixel.
+const SUPERSAMPLING: usize = 4;
+/// Brush angles a mask is rendered at, over a full turn.
+const ANGLE_STEPS: f32 = 128.0;
+/// Memory the cached brush masks may take before the least recently used
+/// ones are dropped.
+const MASK_CACHE_BUDGET: usize = 64 * 1024 * 1024;
+/// Dab sizes up to this many pixels are kept exact. Larger ones are rounded
+/// to about one part in this many, so that dabs of nearly the same size
+/// share a mask.
+const EXACT_DAB_SIZE: usize = 32;
+
+impl BrushType {
+    /// Whether the shape turns with the stroke direction and base rotation.
+    fn is_oriented(self) -> bool {
+        !matches!(self, BrushType::Round | BrushType::Mop | BrushType::Fan)
+    }
+    
+    /// Distance of the point `(rx, ry)` from the middle of the brush shape,
+    /// scaled so that the edge of the shape lies at 1.0. The shape is turned
+    /// by the angle of `cos_a` and `sin_a`.
+    fn shape_distance(self, rx: f32, ry: f32, cos_a: f32, sin_a: f32) -> f32 {
+        let rx_rot = rx * cos_a - ry * sin_a;
+        let ry_rot = rx * sin_a + ry * cos_a;
+        // Rectangle of half sides `half_x` and `half_y` centered on `(cx, 0)`.
+        let rectangle = |cx: f32, half_x: f32, half_y: f32| ((rx_rot - cx).abs() / half_x).max(ry_rot.abs() / half_y);
+        
+        match self {
+            BrushType::Round | BrushType::Mop => rx.hypot(ry),
+            BrushType::Flat => rectangle(0.0, 0.2, 1.0),
+            BrushType::Bright => rectangle(0.0, 0.3, 0.8),
+            BrushType::Filbert => (rx_rot / 0.6).hypot(ry_rot),
+            BrushType::Fan => {
+                // Five bristles spread over a half circle.
+                let segment_width = PI * 0.9 / 5.0;
+                let angle_from_center = ry.atan2(rx) + PI;
+                let normalized_angle = (angle_from_center % (PI * 2.0)) - PI * 0.55;
+                let bristle_distance = (0..5)
+                    .map(|i| (normalized_angle - i as f32 * segment_width).abs())
+                    .fold(f32::INFINITY, f32::min);
+                rx.hypot(ry).max(bristle_distance / (segment_width * 0.4))
+            },
+            BrushType::Angle => rectangle(-0.2, 0.6, 0.7),
+            BrushType::Rigger => rectangle(0.0, 0.08, 0.9),
+            // Only reached when the bitmap of the tip is missing.
+            BrushType::Custom(_) => rx.hypot(ry),
+        }
+    }
+}
+
+/// Brush opacity at `distance` from the middle of its shape: full up to
+/// `hardness`, then fading smoothly to nothing at the edge.
+fn falloff(distance: f32, hardness: f32) -> f32 {
+    if distance >= 1.0 {
+        return 0.0;
+    }
+    if distance <= hardness {
+        return 1.0;
+    }
+    let t = (distance - hardness) / (1.0 - hardness);
+    1.0 - t * t * (3.0 - 2.0 * t)
+}
+
+/// What a brush mask depends on, rounded so that nearby dabs share masks.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+struct MaskKey {
+    brush_type: BrushType,
+    size: usize,
+    /// In steps of a full turn divided by `ANGLE_STEPS`.
+    angle: i32,
+    /// In percent.
+    hardness: u8,
+}
+
+impl MaskKey {
+    /// Coverage of the brush over a `size` by `size` grid, each pixel averaged
+    /// over subsamples so that the shape edges are anti-aliased. `tip` is the
+    /// bitmap of custom brushes.
+    fn render(&self, tip: Option<&BrushTip>) -> Vec<f32> {
+        let size = self.size;
+        let mut mask = vec![0.0; size * size];
+        let center = size as f32 / 2.0;
+        let radius = center;
+        let (sin_a, cos_a) = (self.angle as f32 * 2.0 * PI / ANGLE_STEPS).sin_cos();
+        let hardness = self.hardness as f32 / 100.0;
+        let step = 1.0 / SUPERSAMPLING as f32;
+        let sampler = tip.map(|tip| tip.sampler(size, SUPERSAMPLING));
+        
+        mask.par_chunks_mut(size.max(1)).enumerate().for_each(|(y, row)| {
+            for (x, value) in row.iter_mut().enumerate() {
+                let mut coverage = 0.0;
+                for sy in 0..SUPERSAMPLING {
+                    for sx in 0..SUPERSAMPLING {
+                        let rx = (x as f32 + (sx as f32 + 0.5) * step - center) / radius;
+                        let ry = (y as f32 + (sy as f32 + 0.5) * step - center) / radius;
+                        coverage += match &sampler {
+                            Some(sampler) => sampler.coverage(rx * cos_a - ry * sin_a, rx * sin_a + ry * cos_a),
+                            None => falloff(self.brush_type.shape_distance(rx, ry, cos_a, sin_a), hardness),
+                        };
+                    }
+                }
+                *value = coverage * step * step;
+            }
+        });
+        
+        mask
+    }
+}
+
+/// Masks already rendered. The least recently used ones are forgotten when
+/// they grow past `MASK_CACHE_BUDGET`.
+#[derive(Default)]
+struct MaskCache {
+    /// Each mask with the use count at which it was last handed out.
+    masks: HashMap<MaskKey, (Arc<Vec<f32>>, u64)>,
+    bytes: usize,
+    uses: u64,
+}
+
+impl MaskCache {
+    fn get(&mut self, key: MaskKey, tip: Option<&BrushTip>) -> Arc<Vec<f32>> {
+        self.uses += 1;
+        if let Some((mask, last_use)) = self.masks.get_mut(&key) {
+            *last_use = self.uses;
+            return Arc::clone(mask);
+        }
+        let mask = Arc::new(key.render(tip));
+        let bytes = mask.len() * std::mem::size_of::<f32>();
+        while self.bytes + bytes > MASK_CACHE_BUDGET && !self.masks.is_empty() {
+            self.evict_least_recent();
+        }
+        self.bytes += bytes;
+        self.masks.insert(key, (Arc::clone(&mask), self.uses));
+        mask
+    }
+
+    fn evict_least_recent(&mut self) {
+        let oldest = self.masks.iter().min_by_key(|(_, (_, last_use))| *last_use).map(|(key, _)| *key);
+        if let Some((mask, _)) = oldest.and_then(|key| self.masks.remove(&key)) {
+            self.bytes -= mask.len() * std::mem::size_of::<f32>();
+        }
+    }
+}
+
+/// `size` rounded to a few percent once it is past `EXACT_DAB_SIZE`, kept
+/// odd so that the dab stays centered.
+pub(crate) fn shared_dab_size(size: usize) -> usize {
+    if size <= EXACT_DAB_SIZE {
+        return size;
+    }
+    let step = size / EXACT_DAB_SIZE;
+    ((size + step / 2) / step * step) | 1
+}
+
+/// The bitmap `key` is drawn from, for custom brushes. Keys of image hoses
+/// name the cell to draw.
+fn key_tip<'a>(tips: &'a HashMap<TipId, BrushTip>, hoses: &'a HashMap<TipId, ImageHose>, key: MaskKey) -> Option<&'a BrushTip> {
+    match key.brush_type {
+        BrushType::Custom(id) => tips
+            .get(&id)
+            .or_else(|| hoses.values().flat_map(|hose| hose.cells()).find(|cell| cell.id() == id)),
+        _ => None,
+    }
+}
+
+/// A brush mask stamped centered on `(x, y)`, scaled by `opacity`.
+#[derive(Clone)]
+pub struct Dab {
+    pub x: i32,
+    pub y: i32,
+    pub size: usize,
+    pub mask: Arc<Vec<f32>>,
+    pub opacity: f32,
+}
+
+pub struct BrushManager {
+    pub brushes: Vec<BrushProperties>,
+    pub active_brush_index: usize,
+    /// Named settings saved by the user.
+    pub presets: Vec<BrushPreset>,
+    pub current_angle: f32,
+    pub last_position: Option<(f32, f32)>,
+    pub current_size: f32,
+    /// Bitmaps of the custom brushes.
+    tips: HashMap<TipId, BrushTip>,
+    hoses: HashMap<TipId, ImageHose>,
+    /// Spacing asked by the files custom brushes were read from.
+    file_spacing: HashMap<TipId, f32>,
+    /// Dabs laid down with an image hose, and the cell of the last one.
+    hose_step: usize,
+    hose_cell: usize,
+    /// State of the generator picking random hose cells.
+    random_state: u64,
+    mask_cache: MaskCache,
+}
+
+impl Default for BrushManager {
+    fn default() -> Self {
+        let mut brushes = Vec::new();
+        
+        for brush_type in BrushType::all_types() {
+            brushes.push(BrushProperties::from_type(brush_type));
+        }
+        
+        Self {
+            brushes,
+            active_brush_index: 0,
+            presets: Vec::new(),
+            current_angle: 0.0,
+            last_position: None,
+            current_size: 3.0,
+            tips: HashMap::new(),
+            hoses: HashMap::new(),
+            file_spacing: HashMap::new(),
+            hose_step: 0,
+            hose_cell: 0,
+            random_state: 0x9e37_79b9_7f4a_7c15,
+            mask_cache: MaskCache::default(),
+        }
+    }
+}
+
+impl BrushManager {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    
+    pub fn active_brush(&self) -> &BrushProperties {
+        &self.brushes[self.active_brush_index]
+    }
+    
+    pub fn active_brush_mut(&mut self) -> &mut BrushProperties {
+        &mut self.brushes[self.active_brush_index]
+    }
+    
+    pub fn tip(&self, id: TipId) -> Option<&BrushTip> {
+        self.tips.get(&id)
+    }
+    
+    pub fn hose(&self, id: TipId) -> Option<&ImageHose> {
+        self.hoses.get(&id)
+    }
+    
+    /// Whether a custom brush of tip or hose `id` is known.
+    pub fn has_tip(&self, id: TipId) -> bool {
+        self.tips.contains_key(&id) || self.hoses.contains_key(&id)
+    }
+    
+    /// Name and bitmap showing the custom brush of tip or hose `id`.
+    pub fn custom_preview(&self, id: TipId) -> Option<(&str, &BrushTip)> {
+        match self.tips.get(&id) {
+            Some(tip) => Some((tip.name(), tip)),
+            None => self.hoses.get(&id).map(|hose| (hose.name(), &hose.cells()[0])),
+        }
+    }
+    
+    /// The tips and hoses among `ids` that are loaded, to be embedded in a
+    /// document.
+    pub fn embedded_tips(&self, ids: &BTreeSet<TipId>) -> (Vec<BrushTip>, Vec<ImageHose>) {
+        let tips = ids.iter().filter_map(|id| self.tips.get(id).cloned()).collect();
+        let hoses = ids.iter().filter_map(|id| self.hoses.get(id).cloned()).collect();
+        (tips, hoses)
+    }
+    
+    /// Keeps `tip` and returns the index of the brush painting with it, adding
+    /// one when there is none yet.
+    pub fn add_tip(&mut self, tip: BrushTip) -> usize {
+        let id = tip.id();
+        self.tips.entry(id).or_insert(tip);
+        self.brush_index(BrushType::Custom(id))
+    }
+    
+    /// Same as [`BrushManager::add_tip`] for an image hose.
+    pub fn add_hose(&mut self, hose: ImageHose) -> usize {
+        let id = hose.id();
+        self.hoses.entry(id).or_insert(hose);
+        self.brush_index(BrushType::Custom(id))
+    }
+    
+    /// Adds a brush read from a file and returns its index. The spacing the
+    /// file asks for replaces the one of the brush.
+    pub fn import_brush(&mut self, brush: ImportedBrush) -> usize {
+        let id = brush.tip.id();
+        let index = match brush.tip {
+            CustomTip::Bitmap(tip) => self.add_tip(tip),
+            CustomTip::Hose(hose) => self.add_hose(hose),
+        };
+        if let Some(spacing) = brush.spacing {
+            // Files count in tip widths, brushes in radii.
+            let spacing = (spacing / 50.0).max(0.01);
+            self.file_spacing.insert(id, spacing);
+            self.brushes[index].spacing = spacing;
+        }
+        index
+    }
+    
+    /// Index of the brush of type `brush_type`, added when missing.
+    fn brush_index(&mut self, brush_type: BrushType) -> usize {
+        match self.brushes.iter().position(|brush| brush.brush_type == brush_type) {
+            Some(index) => index,
+            None => {
+                self.brushes.push(BrushProperties::from_type(brush_type));
+                self.brushes.len() - 1
+            },
+        }
+    }
+    
+    /// Settings a brush of type `brush_type` starts with.
+    fn stock_properties(&self, brush_type: BrushType) -> BrushProperties {
+        let mut properties = BrushProperties::from_type(brush_type);
+        if let BrushType::Custom(id) = brush_type
+            && let Some(&spacing) = self.file_spacing.get(&id)
+        {
+            properties.spacing = spacing;
+        }
+        properties
+    }
+    
+    /// Puts back the stock settings of the active brush and returns its stock
+    /// size.
+    pub fn reset_active_brush(&mut self) -> f32 {
+        let properties = self.stock_properties(self.active_brush().brush_type);
+        let size = properties.size;
+        *self.active_brush_mut() = properties;
+        size
+    }
+    
+    /// Saves the settings of the active brush and `size` as the preset
+    /// `name`, replacing any preset of that name.
+    pub fn save_preset(&mut self, name: String, size: f32) {
+        let mut properties = self.active_brush().clone();
+        properties.size = size;
+        let preset = BrushPreset { name, properties };
+        match self.presets.iter_mut().find(|existing| existing.name == preset.name) {
+            Some(existing) => *existing = preset,
+            None => self.presets.push(preset),
+        }
+    }
+    
+    /// Gives the brush of preset `index` its settings and makes it active.
+    /// Returns the size of the preset, or `None` when it paints with a custom
+    /// tip that is not loaded.
+    pub fn apply_preset(&mut self, index: usize) -> Option<f32> {
+        let properties = self.presets.get(index)?.properties.clone();
+        if let BrushType::Custom(id) = properties.brush_type
+            && !self.has_tip(id)
+        {
+            return None;
+        }
+        let size = properties.size;
+        let brush_index = self.brush_index(properties.brush_type);
+        self.brushes[brush_index] = properties;
+        self.active_brush_index = brush_index;
+        Some(size)
+    }
+    
+    /// All presets, with the custom tips they use.
+    pub fn preset_pack(&self) -> PresetPack {
+        let mut pack = PresetPack {
+            presets: self.presets.clone(),
+            ..PresetPack::default()
+        };
+        for preset in &self.presets {
+            let BrushType::Custom(id) = preset.properties.brush_type else {
+                continue;
+            };
+            if let Some(tip) = self.tips.get(&id)
+                && !pack.tips.iter().any(|packed| packed.id() == id)
+            {
+                pack.tips.push(tip.clone());
+            }
+            if let Some(hose) = self.hoses.get(&id)
+                && !pack.hoses.iter().any(|packed| packed.id() == id)
+            {
+                pack.hoses.push(hose.clone());
+            }
+        }
+        pack
+    }
+    
+    /// Adds the presets and tips of `pack`. Its presets replace those of the
+    /// same name.
+    pub fn add_preset_pack(&mut self, pack: PresetPack) {
+        for tip in pack.tips {
+            self.add_tip(tip);
+        }
+        for hose in pack.hoses {
+            self.add_hose(hose);
+        }
+        for preset in pack.presets {
+            match self.presets.iter_mut().find(|existing| existing.name == preset.name) {
+                Some(existing) => *existing = preset,
+                None => self.presets.push(preset),
+            }
+        }
+    }
+    
+    pub fn update_angle(&mut self, x: f32, y: f32) {
+        if let Some((prev_x, prev_y)) = self.last_position {
+            let dx = x - prev_x;
+            let dy = y - prev_y;
+            
+            if dx * dx + dy * dy > 0.25 {
+                let new_angle = dy.atan2(dx);
+                let angle_diff = new_angle - self.current_angle;
+                
+                let normalized_diff = if angle_diff > std::f32::consts::PI {
+                    angle_diff - 2.0 * std::f32::consts::PI
+                } else if angle_diff < -std::f32::consts::PI {
+                    angle_diff + 2.0 * std::f32::consts::PI
+                } else {
+                    angle_diff
+                };
+                
+                let smoothing_factor = 0.2;
+                self.current_angle += normalized_diff * smoothing_factor;
+            }
+        }
+        
+        self.last_position = Some((x, y));
+    }
+    
+    fn mask_key(&self, size: usize) -> MaskKey {
+        let active = self.active_brush();
+        // Hoses picking their cells by direction already draw it.
+        let picks_direction = match active.brush_type {
+            BrushType::Custom(id) => self.hoses.get(&id).is_some_and(|hose| hose.selection().contains(&HoseSelection::Angular)),
+            _ => false,
+        };
+        let angle = if active.brush_type.is_oriented() && !picks_direction {
+            let turns = (self.current_angle + active.base_rotation) / (2.0 * PI);
+            ((turns * ANGLE_STEPS).round() as i32).rem_euclid(ANGLE_STEPS as i32)
+        } else {
+            0
+        };
+        // Bitmap tips carry their own softness.
+        let hardness = match active.brush_type {
+            BrushType::Custom(_) => 100,
+            _ => (active.hardness.clamp(0.0, 1.0) * 100.0).round() as u8,
+        };
+        let brush_type = match active.brush_type {
+            BrushType::Custom(id) => match self.hoses.get(&id) {
+                Some(hose) => BrushType::Custom(hose.cells()[self.hose_cell.min(hose.cells().len() - 1)].id()),
+                None => active.brush_type,
+            },
+            brush_type => brush_type,
+        };
+        MaskKey {
+            brush_type,
+            size,
+            angle,
+            hardness,
+        }
+    }
+    
+    /// Coverage of the active brush over a `size` by `size` grid, at its
+    /// current angle.
+    pub fn generate_brush_mask(&self, size: usize) -> Vec<f32> {
+        let key = self.mask_key(size);
+        key.render(key_tip(&self.tips, &self.hoses, key))
+    }
+    
+    /// Same as [`BrushManager::generate_brush_mask`], rendered only the first
+    /// time it is needed.
+    pub fn brush_mask(&mut self, size: usize) -> Arc<Vec<f32>> {
+        let key = self.mask_key(size);
+        let tip = key_tip(&self.tips, &self.hoses, key);
+        self.mask_cache.get(key, tip)
+    }
+    
+    /// The dab centered on `(x, y)`, its size and opacity following the
+    /// pressure.
+    pub fn dab(&mut self, x: i32, y: i32, pressure: f32) -> Dab {
+        let active = self.active_brush();
+        let clamped_pressure = pressure.clamp(0.0, 1.0);
+        
+        let effective_size = if active.pressure_affects_size {
+            let size_factor = active.pressure_size_min + (1.0 - active.pressure_size_min) * clamped_pressure;
+            (self.current_size * size_factor).max(1.0) as usize * 2 + 1
+        } else {
+            self.current_size as usize * 2 + 1
+        };
+        let effective_size = shared_dab_size(effective_size);
+        
+        let effective_opacity = if active.pressure_affects_opacity {
+            active.pressure_opacity_min + (1.0 - active.pressure_opacity_min) * clamped_pressure
+        } else {
+            1.0
+        };
+        
+        self.update_angle(x as f32, y as f32);
+        self.next_hose_cell(clamped_pressure);
+        Dab {
+            x,
+            y,
+            size: effective_size,
+            mask: self.brush_mask(effective_size),
+            opacity: effective_opacity,
+        }
+    }
+    
+    /// Picks the cell of the next dab, when the active brush is an image hose.
+    fn next_hose_cell(&mut self, pressure: f32) {
+        let BrushType::Custom(id) = self.active_brush().brush_type else {
+            return;
+        };
+        let Some(hose) = self.hoses.get(&id) else {
+            return;
+        };
+        let input = HoseInput {
+            step: self.hose_step,
+            angle: self.current_angle,
+            pressure,
+        };
+        let random_state = &mut self.random_state;
+        self.hose_cell = hose.select(&input, || {
+            // xorshift64
+            *random_state ^= *random_state << 13;
+            *random_state ^= *random_state >> 7;
+            *random_state ^= *random_state << 17;
+            *random_state
+        });
+        self.hose_step = self.hose_step.wrapping_add(1);
+    }
+    
+    /// Dabs spaced along the line from `start` to `end`.
+    pub fn line_dabs(&mut self, start: (i32, i32), end: (i32, i32), pressure: f32) -> Vec<Dab> {
+        let (x0, y0) = start;
+        let (x1, y1) = end;
+        let dx = (x1 - x0).abs();
+        let dy = -(y1 - y0).abs();
+        let sx = if x0 < x1 { 1 } else { -1 };
+        let sy = if y0 < y1 { 1 } else { -1 };
+        let mut err = dx + dy;
+        
+        let mut x = x0;
+        let mut y = y0;
+        
+        let spacing = (self.active_brush().spacing * self.current_size).max(1.0);
+        let mut points = Vec::new();
+        points.push((x, y));
+        let mut accumulated_distance = 0.0;
+        
+        loop {
+            let old_x = x;
+            let old_y = y;
+            
+            let e2 = 2 * err;
+            if e2 >= dy {
+                if x == x1 { break; }
+                err += dy;
+                x += sx;
+            }
+            if e2 <= dx {
+                if y == y1 { break; }
+                err += dx;
+                y += sy;
+            }
+            
+            let segment_length = ((x - old_x).pow(2) + (y - old_y).pow(2)) as f32;
+            accumulated_distance += segment_length;
+            
+            if accumulated_distance >= spacing {
+                points.push((x, y));
+                accumulated_distance = 0.0;
+            }
+        }
+        
+        if points.last() != Some(&(x1, y1)) {
+            points.push((x1, y1));
+        }
+        
+        points.into_iter().map(|(px, py)| self.dab(px, py, pressure)).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shared_dab_sizes_stay_odd_and_close() {
+        for size in 1..=EXACT_DAB_SIZE {
+            assert_eq!(shared_dab_size(size), size);
+        }
+        for size in (EXACT_DAB_SIZE + 1..2000).step_by(2) {
+            let shared = shared_dab_size(size);
+            assert_eq!(shared % 2, 1);
+            assert!(shared.abs_diff(size) * 20 <= size, "{} became {}", size, shared);
+        }
+        assert_eq!(shared_dab_size(497), shared_dab_size(499));
+    }
+
+    #[test]
+    fn masks_are_cached_and_fade_at_the_edge() {
+        let mut brushes = BrushManager::new();
+        let mask = brushes.brush_mask(21);
+        assert!(Arc::ptr_eq(&mask, &brushes.brush_mask(21)));
+        assert_eq!(mask.len(), 21 * 21);
+        assert!((mask[10 * 21 + 10] - 1.0).abs() < 1e-6);
+        assert_eq!(mask[0], 0.0);
+    }
+
+    #[test]
+    fn reset_gives_back_the_stock_settings_and_size() {
+        let mut brushes = BrushManager::new();
+        brushes.active_brush_mut().opacity = 0.2;
+        brushes.active_brush_mut().size = 80.0;
+        assert_eq!(brushes.reset_active_brush(), BrushProperties::default().size);
+        assert_eq!(brushes.active_brush().opacity, 1.0);
+    }
+}
@@ -0,0 +1,43 @@
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+
+use serde::{Serialize, Deserialize};
+
+use crate::file_format::FileError;
+
+use super::hose::ImageHose;
+use super::tip::BrushTip;
+use super::BrushProperties;
+
+/// Brush settings saved under a name. `properties.size` holds the brush size
+/// in pixels.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct BrushPreset {
+    pub name: String,
+    pub properties: BrushProperties,
+}
+
+/// Presets written together to a JSON file, with the custom tips they paint
+/// with so that they work on any machine.
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct PresetPack {
+    pub presets: Vec<BrushPreset>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tips: Vec<BrushTip>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub hoses: Vec<ImageHose>,
+}
+
+impl PresetPack {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, FileError> {
+        let content = fs::read_to_string(path).map_err(FileError::Read)?;
+        serde_json::from_str(&content).map_err(FileError::InvalidPresets)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), FileError> {
+        let json = serde_json::to_string_pretty(self).map_err(FileError::Serialize)?;
+        let mut file = fs::File::create(path).map_err(FileError::Create)?;
+        file.write_all(json.as_bytes()).map_err(FileError::Write)
+    }
+}
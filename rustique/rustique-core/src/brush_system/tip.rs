@@ -0,0 +1,262 @@
+use std::fmt;
+use std::path::Path;
+use std::sync::OnceLock;
+
+use image::{DynamicImage, GrayImage};
+use serde::{Serialize, Deserialize};
+
+use crate::adjustments::luminance;
+use crate::color::Color;
+use crate::file_format::FileError;
+
+/// Identifies a custom tip by its bitmap, so that the same tip loaded twice,
+/// or embedded in a document, is only kept once.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct TipId(pub u64);
+
+/// A bitmap brush tip: how much paint each of its pixels lays down.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(try_from = "TipData", into = "TipData")]
+pub struct BrushTip {
+    id: TipId,
+    name: String,
+    width: usize,
+    height: usize,
+    /// Coverage of each pixel, row by row.
+    alpha: Vec<u8>,
+    /// Copies of the bitmap halved again and again, so that it can be drawn
+    /// much smaller without aliasing. Built the first time they are needed.
+    reduced: OnceLock<Vec<TipLevel>>,
+}
+
+/// How a tip is written in `.rustiq` files.
+#[derive(Clone, Serialize, Deserialize)]
+struct TipData {
+    name: String,
+    width: usize,
+    height: usize,
+    alpha: Vec<u8>,
+}
+
+#[derive(Debug)]
+pub struct InvalidTip;
+
+impl fmt::Display for InvalidTip {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid brush tip")
+    }
+}
+
+impl TryFrom<TipData> for BrushTip {
+    type Error = InvalidTip;
+
+    fn try_from(data: TipData) -> Result<Self, InvalidTip> {
+        BrushTip::new(data.name, data.width, data.height, data.alpha).ok_or(InvalidTip)
+    }
+}
+
+impl From<BrushTip> for TipData {
+    fn from(tip: BrushTip) -> Self {
+        Self {
+            name: tip.name,
+            width: tip.width,
+            height: tip.height,
+            alpha: tip.alpha,
+        }
+    }
+}
+
+#[derive(Clone)]
+struct TipLevel {
+    width: usize,
+    height: usize,
+    alpha: Vec<u8>,
+}
+
+impl TipLevel {
+    /// Averages each 2 by 2 block of pixels.
+    fn halved(width: usize, height: usize, alpha: &[u8]) -> Self {
+        let (half_width, half_height) = (width.div_ceil(2), height.div_ceil(2));
+        let mut reduced = Vec::with_capacity(half_width * half_height);
+        for y in 0..half_height {
+            for x in 0..half_width {
+                let mut sum = 0;
+                for (sx, sy) in [(0, 0), (1, 0), (0, 1), (1, 1)] {
+                    let (px, py) = ((2 * x + sx).min(width - 1), (2 * y + sy).min(height - 1));
+                    sum += alpha[py * width + px] as u32;
+                }
+                reduced.push(((sum + 2) / 4) as u8);
+            }
+        }
+        Self {
+            width: half_width,
+            height: half_height,
+            alpha: reduced,
+        }
+    }
+}
+
+impl BrushTip {
+    /// `None` when the tip is empty or `alpha` does not hold `width` by
+    /// `height` pixels.
+    pub fn new(name: String, width: usize, height: usize, alpha: Vec<u8>) -> Option<Self> {
+        if width == 0 || height == 0 || alpha.len() != width * height {
+            return None;
+        }
+        Some(Self {
+            id: TipId(content_hash(width, height, &alpha)),
+            name,
+            width,
+            height,
+            alpha,
+            reduced: OnceLock::new(),
+        })
+    }
+
+    /// Reads the tip from the transparency of an image, or from its darkness
+    /// when the image is fully opaque.
+    pub fn from_image(name: String, image: &DynamicImage) -> Option<Self> {
+        let rgba = image.to_rgba8();
+        let transparent = rgba.pixels().any(|pixel| pixel[3] < 255);
+        let alpha = rgba
+            .pixels()
+            .map(|pixel| if transparent { pixel[3] } else { 255 - luminance(Color::from_array(pixel.0)) })
+            .collect();
+        Self::new(name, rgba.width() as usize, rgba.height() as usize, alpha)
+    }
+
+    /// Loads a tip from an image file, named after the file.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, FileError> {
+        let path = path.as_ref();
+        let image = image::open(path).map_err(FileError::ImageDecode)?;
+        Self::from_image(file_stem(path), &image).ok_or_else(|| FileError::UnsupportedFormat(path.display().to_string()))
+    }
+
+    /// Writes the tip as a grayscale image, paint in black, which
+    /// [`BrushTip::load`] reads back unchanged.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), FileError> {
+        let inverted = self.alpha.iter().map(|&alpha| 255 - alpha).collect();
+        GrayImage::from_raw(self.width as u32, self.height as u32, inverted)
+            .expect("tip holds width * height pixels")
+            .save(path)
+            .map_err(FileError::ImageEncode)
+    }
+
+    pub fn id(&self) -> TipId {
+        self.id
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn alpha(&self) -> &[u8] {
+        &self.alpha
+    }
+
+    /// The tip reduced to at most `max_side` pixels per side by halving, or
+    /// its smallest version, as `(width, height, alpha)`.
+    pub fn thumbnail(&self, max_side: usize) -> (usize, usize, &[u8]) {
+        if self.width.max(self.height) <= max_side {
+            return (self.width, self.height, &self.alpha);
+        }
+        let levels = self.levels();
+        let level = levels
+            .iter()
+            .find(|level| level.width.max(level.height) <= max_side)
+            .or(levels.last());
+        match level {
+            Some(level) => (level.width, level.height, &level.alpha),
+            None => (self.width, self.height, &self.alpha),
+        }
+    }
+
+    fn levels(&self) -> &[TipLevel] {
+        self.reduced.get_or_init(|| {
+            let mut levels: Vec<TipLevel> = Vec::new();
+            let (mut width, mut height) = (self.width, self.height);
+            while width.max(height) > 1 {
+                let level = match levels.last() {
+                    Some(previous) => TipLevel::halved(width, height, &previous.alpha),
+                    None => TipLevel::halved(width, height, &self.alpha),
+                };
+                (width, height) = (level.width, level.height);
+                levels.push(level);
+            }
+            levels
+        })
+    }
+
+    /// Sampler of the tip scaled so that its longest side spans `size`
+    /// pixels, from the version of the bitmap closest to that scale.
+    pub(crate) fn sampler(&self, size: usize, supersampling: usize) -> TipSampler<'_> {
+        let wanted = (size * supersampling).max(1);
+        let mut sampler = TipSampler {
+            width: self.width,
+            height: self.height,
+            alpha: &self.alpha,
+        };
+        for level in self.levels() {
+            if level.width.max(level.height) < wanted {
+                break;
+            }
+            sampler = TipSampler {
+                width: level.width,
+                height: level.height,
+                alpha: &level.alpha,
+            };
+        }
+        sampler
+    }
+}
+
+pub(crate) struct TipSampler<'a> {
+    width: usize,
+    height: usize,
+    alpha: &'a [u8],
+}
+
+impl TipSampler<'_> {
+    /// Coverage at `(rx, ry)`, both from -1 to 1 across the longest side of
+    /// the tip, interpolated between its pixels.
+    pub(crate) fn coverage(&self, rx: f32, ry: f32) -> f32 {
+        let half = self.width.max(self.height) as f32 / 2.0;
+        let x = self.width as f32 / 2.0 + rx * half - 0.5;
+        let y = self.height as f32 / 2.0 + ry * half - 0.5;
+        let (x0, y0) = (x.floor(), y.floor());
+        let (fx, fy) = (x - x0, y - y0);
+        let pixel = |px: f32, py: f32| {
+            if px < 0.0 || py < 0.0 || px >= self.width as f32 || py >= self.height as f32 {
+                0.0
+            } else {
+                self.alpha[py as usize * self.width + px as usize] as f32
+            }
+        };
+        let top = pixel(x0, y0) * (1.0 - fx) + pixel(x0 + 1.0, y0) * fx;
+        let bottom = pixel(x0, y0 + 1.0) * (1.0 - fx) + pixel(x0 + 1.0, y0 + 1.0) * fx;
+        (top * (1.0 - fy) + bottom * fy) / 255.0
+    }
+}
+
+pub(crate) fn file_stem(path: &Path) -> String {
+    path.file_stem().map(|stem| stem.to_string_lossy().into_owned()).unwrap_or_default()
+}
+
+/// FNV-1a hash of the tip size and pixels.
+pub(crate) fn content_hash(width: usize, height: usize, alpha: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    let size = [(width as u64).to_le_bytes(), (height as u64).to_le_bytes()];
+    for &byte in size.iter().flatten().chain(alpha) {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+    hash
+}
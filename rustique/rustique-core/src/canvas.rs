@@ -0,0 +1,487 @@
+use std::ops::Range;
+use std::sync::Arc;
+
+use crate::color::Color;
+use crate::compositing::{composite_layer, BlendMode};
+use crate::layer::{Layer, LayerId, LayerKind, LayerLocks, LayerMask};
+
+/// What painting tools change on the active layer.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Default)]
+pub enum EditTarget {
+    #[default]
+    Pixels,
+    /// The layer mask, seen as opaque gray pixels.
+    Mask,
+}
+
+impl EditTarget {
+    /// The value `color` actually takes once written to this target.
+    pub fn normalize(self, color: Option<Color>) -> Option<Color> {
+        match self {
+            EditTarget::Pixels => color,
+            EditTarget::Mask => Some(Color::from_gray(LayerMask::value_from_color(color))),
+        }
+    }
+}
+
+/// The layer stack, ordered bottom to top. Groups are stored inline: a group
+/// layer sits right above its children, which have a greater `depth`.
+#[derive(Clone)]
+pub struct CanvasState {
+    pub width: usize,
+    pub height: usize,
+    pub layers: Vec<Layer>,
+    pub active_layer_index: usize,
+    /// Requested target; only honored when the active layer has a mask.
+    pub edit_target: EditTarget,
+}
+
+impl CanvasState {
+    pub fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            layers: vec![Layer::new("Background".to_string(), width, height)],
+            active_layer_index: 0,
+            edit_target: EditTarget::Pixels,
+        }
+    }
+
+    /// Composites the visible layers at `(x, y)` from the bottom up.
+    #[inline]
+    pub fn get(&self, x: usize, y: usize) -> Option<Color> {
+        if x >= self.width || y >= self.height {
+            return None;
+        }
+
+        let idx = y * self.width + x;
+        // Everything under the topmost opaque pixel is hidden, start there.
+        let start = self.layers
+            .iter()
+            .rposition(|layer| {
+                layer.depth == 0
+                    && layer.visible
+                    && layer.covers_below()
+                    && layer.apply_mask(layer.data.get(idx).copied().flatten(), idx).is_some_and(|color| color.a() == 255)
+            })
+            .unwrap_or(0);
+
+        self.composite_range(start..self.layers.len(), 0, idx)
+    }
+
+    /// Composites the layers of `range` nested at `depth`. A group is first
+    /// flattened on its own, then blended as a single layer. Clipped layers
+    /// take the alpha of the closest unclipped layer below them.
+    pub(crate) fn composite_range(&self, range: Range<usize>, depth: usize, idx: usize) -> Option<Color> {
+        let mut result = None;
+        let mut clip_alpha = 255;
+        let mut i = range.start;
+
+        while i < range.end {
+            let (node, pixel) = if self.layers[i].depth > depth {
+                let Some(offset) = self.layers[i..range.end].iter().position(|layer| layer.depth <= depth) else {
+                    break;
+                };
+                let header = i + offset;
+                let pixel = if self.layers[header].visible {
+                    self.composite_range(i..header, depth + 1, idx)
+                } else {
+                    None
+                };
+                (header, pixel)
+            } else {
+                (i, self.layers[i].data.get(idx).copied().flatten())
+            };
+
+            let layer = &self.layers[node];
+            let mut pixel = layer.apply_mask(pixel, idx);
+            if layer.clipped {
+                pixel = pixel
+                    .map(|color| color.with_alpha((color.a() as u32 * clip_alpha as u32 / 255) as u8))
+                    .filter(|color| color.a() > 0);
+            } else {
+                clip_alpha = pixel.filter(|_| layer.visible).map_or(0, |color| color.a());
+            }
+
+            if layer.visible {
+                result = composite_layer(result, pixel, layer.opacity, layer.blend_mode);
+            }
+            i = node + 1;
+        }
+
+        result
+    }
+
+    #[inline]
+    pub fn get_from_layer(&self, layer_index: usize, x: usize, y: usize) -> Option<Color> {
+        if x < self.width && y < self.height && layer_index < self.layers.len() {
+            self.layers[layer_index].data.get(y * self.width + x).copied().flatten()
+        } else {
+            None
+        }
+    }
+
+    /// Reads the layer pixels or its mask. A mask value comes back as an
+    /// opaque gray color, `None` when the layer has no mask.
+    #[inline]
+    pub fn get_from_target(&self, layer_index: usize, target: EditTarget, x: usize, y: usize) -> Option<Color> {
+        match target {
+            EditTarget::Pixels => self.get_from_layer(layer_index, x, y),
+            EditTarget::Mask => {
+                if x >= self.width || y >= self.height {
+                    return None;
+                }
+                self.layers
+                    .get(layer_index)
+                    .and_then(|layer| layer.mask.as_ref())
+                    .and_then(|mask| mask.data.get(y * self.width + x))
+                    .map(|&value| Color::from_gray(value))
+            },
+        }
+    }
+
+    /// Reads what painting tools currently edit on the active layer.
+    #[inline]
+    pub fn get_from_active_layer(&self, x: usize, y: usize) -> Option<Color> {
+        self.get_from_target(self.active_layer_index, self.active_target(), x, y)
+    }
+
+    #[inline]
+    pub fn set_on_layer(&mut self, layer_index: usize, x: usize, y: usize, color: Option<Color>) {
+        if x < self.width && y < self.height && layer_index < self.layers.len() {
+            let idx = y * self.width + x;
+            if let Some(pixel) = Arc::make_mut(&mut self.layers[layer_index].data).get_mut(idx) {
+                *pixel = color;
+            }
+        }
+    }
+
+    #[inline]
+    pub fn set_on_target(&mut self, layer_index: usize, target: EditTarget, x: usize, y: usize, color: Option<Color>) {
+        match target {
+            EditTarget::Pixels => self.set_on_layer(layer_index, x, y, color),
+            EditTarget::Mask => {
+                if x >= self.width || y >= self.height {
+                    return;
+                }
+                let idx = y * self.width + x;
+                if let Some(value) = self.layers
+                    .get_mut(layer_index)
+                    .and_then(|layer| layer.mask.as_mut())
+                    .and_then(|mask| mask.data.get_mut(idx))
+                {
+                    *value = LayerMask::value_from_color(color);
+                }
+            },
+        }
+    }
+
+    /// Writes where painting tools currently edit on the active layer.
+    #[inline]
+    pub fn set(&mut self, x: usize, y: usize, color: Option<Color>) {
+        self.set_on_target(self.active_layer_index, self.active_target(), x, y, color);
+    }
+
+    /// The mask when it is requested and the active layer has one, the
+    /// layer pixels otherwise.
+    pub fn active_target(&self) -> EditTarget {
+        match self.edit_target {
+            EditTarget::Mask if self.active_layer().is_some_and(|layer| layer.mask.is_some()) => EditTarget::Mask,
+            _ => EditTarget::Pixels,
+        }
+    }
+
+    pub fn layer_index(&self, id: LayerId) -> Option<usize> {
+        self.layers.iter().position(|layer| layer.id == id)
+    }
+
+    pub fn active_layer(&self) -> Option<&Layer> {
+        self.layers.get(self.active_layer_index)
+    }
+
+    pub fn active_layer_visible(&self) -> bool {
+        self.is_layer_visible(self.active_layer_index)
+    }
+
+    /// Whether painting tools can change the active layer at all.
+    pub fn active_layer_editable(&self) -> bool {
+        let index = self.active_layer_index;
+        let Some(layer) = self.layers.get(index) else {
+            return true;
+        };
+        (!layer.is_group() || self.active_target() == EditTarget::Mask)
+            && self.is_layer_visible(index)
+            && !self.self_and_ancestors(index).any(|i| self.layers[i].locks.pixels)
+    }
+
+    /// Whether the layer and all the groups containing it are visible.
+    pub fn is_layer_visible(&self, index: usize) -> bool {
+        self.self_and_ancestors(index).all(|i| self.layers[i].visible)
+    }
+
+    /// Returns what an edit of `target` actually writes, once normalized for
+    /// the target and restricted by the locks of the layer and its groups.
+    pub fn constrain_edit(&self, layer_index: usize, target: EditTarget, old: Option<Color>, new: Option<Color>) -> Option<Color> {
+        self.self_and_ancestors(layer_index)
+            .fold(target.normalize(new), |new, i| self.layers[i].constrain_edit(old, new))
+    }
+
+    /// Index of the group directly containing the layer at `index`.
+    pub fn parent_of(&self, index: usize) -> Option<usize> {
+        let depth = self.layers.get(index)?.depth;
+        if depth == 0 {
+            return None;
+        }
+        self.layers[index + 1..]
+            .iter()
+            .position(|layer| layer.depth < depth)
+            .map(|offset| index + 1 + offset)
+    }
+
+    /// Enclosing groups of the layer at `index`, innermost first.
+    pub fn ancestors(&self, index: usize) -> impl Iterator<Item = usize> + '_ {
+        std::iter::successors(self.parent_of(index), move |&parent| self.parent_of(parent))
+    }
+
+    fn self_and_ancestors(&self, index: usize) -> impl Iterator<Item = usize> + '_ {
+        (index < self.layers.len())
+            .then_some(index)
+            .into_iter()
+            .chain(self.ancestors(index))
+    }
+
+    /// Indices covered by the layer at `index`, including all the children
+    /// when it is a group.
+    pub fn node_range(&self, index: usize) -> Range<usize> {
+        let layer = &self.layers[index];
+        if !layer.is_group() {
+            return index..index + 1;
+        }
+        let start = self.layers[..index]
+            .iter()
+            .rposition(|below| below.depth <= layer.depth)
+            .map_or(0, |i| i + 1);
+        start..index + 1
+    }
+
+    pub fn add_layer(&mut self, name: String) {
+        self.layers.push(Layer::new(name, self.width, self.height));
+        self.active_layer_index = self.layers.len() - 1;
+    }
+
+    /// Wraps the layer at `index`, or the whole group, into a new group.
+    pub fn group_layer(&mut self, index: usize, name: String) -> bool {
+        if index >= self.layers.len() {
+            return false;
+        }
+
+        let node = self.node_range(index);
+        let depth = self.layers[index].depth;
+        for layer in &mut self.layers[node.clone()] {
+            layer.depth += 1;
+        }
+        self.layers.insert(node.end, Layer {
+            depth,
+            ..Layer::new_group(name)
+        });
+        if self.active_layer_index >= node.end {
+            self.active_layer_index += 1;
+        }
+        true
+    }
+
+    /// Whether removing the layer at `index` leaves at least one raster layer.
+    pub fn can_remove_layer(&self, index: usize) -> bool {
+        if index >= self.layers.len() {
+            return false;
+        }
+
+        let node = self.node_range(index);
+        self.layers
+            .iter()
+            .enumerate()
+            .any(|(i, layer)| !node.contains(&i) && !layer.is_group())
+    }
+
+    /// Removes the layer at `index`, or a group with all its content. The
+    /// last raster layer cannot be removed.
+    pub fn remove_layer(&mut self, index: usize) -> bool {
+        if !self.can_remove_layer(index) {
+            return false;
+        }
+
+        let node = self.node_range(index);
+        self.layers.drain(node.clone());
+        if self.active_layer_index >= node.end {
+            self.active_layer_index -= node.len();
+        } else if self.active_layer_index >= node.start {
+            self.active_layer_index = node.start.min(self.layers.len() - 1);
+        }
+        true
+    }
+
+    /// Moves the layer at `index`, with its children for a group, one step
+    /// towards the bottom of the stack. It enters a group met on the way
+    /// and leaves its own group when it is already at the bottom of it.
+    pub fn move_layer_up(&mut self, index: usize) -> bool {
+        if index >= self.layers.len() {
+            return false;
+        }
+
+        let node = self.node_range(index);
+        let depth = self.layers[index].depth;
+        let Some(below) = node.start.checked_sub(1) else {
+            return false;
+        };
+
+        if self.layers[below].depth < depth {
+            self.shift_depth(node, -1);
+        } else if self.layers[below].is_group() {
+            self.rotate_layers(below..node.end, 1);
+            self.shift_depth(below..below + node.len(), 1);
+        } else {
+            self.rotate_layers(below..node.end, 1);
+        }
+        true
+    }
+
+    /// Moves the layer at `index`, with its children for a group, one step
+    /// towards the top of the stack, entering or leaving groups on the way.
+    pub fn move_layer_down(&mut self, index: usize) -> bool {
+        if index >= self.layers.len() {
+            return false;
+        }
+
+        let node = self.node_range(index);
+        let depth = self.layers[index].depth;
+        let above = node.end;
+        if above >= self.layers.len() {
+            return false;
+        }
+
+        let above_depth = self.layers[above].depth;
+        if above_depth < depth {
+            self.rotate_layers(node.start..above + 1, node.len());
+            self.shift_depth(node.start + 1..above + 1, -1);
+        } else if above_depth > depth || self.layers[above].is_group() {
+            self.shift_depth(node, 1);
+        } else {
+            self.rotate_layers(node.start..above + 1, node.len());
+        }
+        true
+    }
+
+    /// Rotates `span` left by `mid` while keeping the active layer selected.
+    fn rotate_layers(&mut self, span: Range<usize>, mid: usize) {
+        self.layers[span.clone()].rotate_left(mid);
+        if span.contains(&self.active_layer_index) {
+            let offset = self.active_layer_index - span.start;
+            self.active_layer_index = span.start + (offset + span.len() - mid) % span.len();
+        }
+    }
+
+    fn shift_depth(&mut self, range: Range<usize>, delta: isize) {
+        for layer in &mut self.layers[range] {
+            layer.depth = layer.depth.saturating_add_signed(delta);
+        }
+    }
+
+    pub fn toggle_layer_visibility(&mut self, index: usize) -> bool {
+        if let Some(layer) = self.layers.get_mut(index) {
+            layer.visible = !layer.visible;
+            true
+        } else {
+            false
+        }
+    }
+
+    pub fn set_layer_opacity(&mut self, index: usize, opacity: f32) -> bool {
+        if let Some(layer) = self.layers.get_mut(index) {
+            layer.opacity = opacity.clamp(0.0, 1.0);
+            true
+        } else {
+            false
+        }
+    }
+
+    pub fn set_layer_blend_mode(&mut self, index: usize, blend_mode: BlendMode) -> bool {
+        if let Some(layer) = self.layers.get_mut(index) {
+            layer.blend_mode = blend_mode;
+            true
+        } else {
+            false
+        }
+    }
+
+    pub fn set_layer_locks(&mut self, index: usize, locks: LayerLocks) -> bool {
+        if let Some(layer) = self.layers.get_mut(index) {
+            layer.locks = locks;
+            true
+        } else {
+            false
+        }
+    }
+
+    pub fn add_layer_mask(&mut self, index: usize) -> bool {
+        let (width, height) = (self.width, self.height);
+        match self.layers.get_mut(index) {
+            Some(layer) if layer.mask.is_none() => {
+                layer.mask = Some(LayerMask::new(width, height));
+                true
+            },
+            _ => false,
+        }
+    }
+
+    pub fn remove_layer_mask(&mut self, index: usize) -> bool {
+        self.layers
+            .get_mut(index)
+            .and_then(|layer| layer.mask.take())
+            .is_some()
+    }
+
+    pub fn toggle_layer_mask_enabled(&mut self, index: usize) -> bool {
+        if let Some(mask) = self.layers.get_mut(index).and_then(|layer| layer.mask.as_mut()) {
+            mask.enabled = !mask.enabled;
+            true
+        } else {
+            false
+        }
+    }
+
+    pub fn toggle_layer_clipping(&mut self, index: usize) -> bool {
+        if let Some(layer) = self.layers.get_mut(index) {
+            layer.clipped = !layer.clipped;
+            true
+        } else {
+            false
+        }
+    }
+
+    pub fn toggle_group_expanded(&mut self, index: usize) -> bool {
+        if let Some(Layer { kind: LayerKind::Group { expanded }, .. }) = self.layers.get_mut(index) {
+            *expanded = !*expanded;
+            true
+        } else {
+            false
+        }
+    }
+
+    pub fn set_active_layer(&mut self, index: usize) -> bool {
+        if index < self.layers.len() {
+            self.active_layer_index = index;
+            true
+        } else {
+            false
+        }
+    }
+
+    pub fn rename_layer(&mut self, index: usize, name: String) -> bool {
+        if let Some(layer) = self.layers.get_mut(index) {
+            layer.name = name;
+            true
+        } else {
+            false
+        }
+    }
+}
@@ -0,0 +1,193 @@
+use std::collections::HashMap;
+use std::mem::size_of;
+
+use crate::canvas::{CanvasState, EditTarget};
+use crate::color::Color;
+use crate::layer::LayerId;
+
+/// Side of the square tiles pixel changes are grouped in.
+const TILE_SIZE: usize = 64;
+
+type Run = (Option<Color>, u16);
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+struct TileKey {
+    layer_id: LayerId,
+    target: EditTarget,
+    tile_x: usize,
+    tile_y: usize,
+}
+
+impl TileKey {
+    fn origin(&self) -> (usize, usize) {
+        (self.tile_x * TILE_SIZE, self.tile_y * TILE_SIZE)
+    }
+}
+
+/// Value a pixel had before the action in progress, for every pixel it
+/// touched so far.
+struct PendingTile {
+    old: Vec<Option<Color>>,
+    touched: Vec<bool>,
+}
+
+impl PendingTile {
+    fn new() -> Self {
+        Self {
+            old: vec![None; TILE_SIZE * TILE_SIZE],
+            touched: vec![false; TILE_SIZE * TILE_SIZE],
+        }
+    }
+}
+
+/// Pixels changed by the action in progress.
+#[derive(Default)]
+pub(crate) struct PendingChanges {
+    tiles: HashMap<TileKey, PendingTile>,
+}
+
+impl PendingChanges {
+    pub fn is_empty(&self) -> bool {
+        self.tiles.is_empty()
+    }
+
+    /// Remembers `old` as the value of the pixel before the action, unless
+    /// the action already touched it.
+    pub fn record(&mut self, layer_id: LayerId, target: EditTarget, x: usize, y: usize, old: Option<Color>) {
+        let key = TileKey {
+            layer_id,
+            target,
+            tile_x: x / TILE_SIZE,
+            tile_y: y / TILE_SIZE,
+        };
+        let tile = self.tiles.entry(key).or_insert_with(PendingTile::new);
+        let local = (y % TILE_SIZE) * TILE_SIZE + x % TILE_SIZE;
+        if !tile.touched[local] {
+            tile.touched[local] = true;
+            tile.old[local] = old;
+        }
+    }
+
+    /// Encodes the action against the values now on `canvas`. Pixels that
+    /// ended up unchanged are dropped.
+    pub fn finish(self, canvas: &CanvasState) -> ChangeSet {
+        let mut tiles = Vec::with_capacity(self.tiles.len());
+        for (key, pending) in self.tiles {
+            let Some(index) = canvas.layer_index(key.layer_id) else {
+                continue;
+            };
+            let (origin_x, origin_y) = key.origin();
+
+            let mut tile = TileChange {
+                key,
+                spans: Vec::new(),
+                old: Vec::new(),
+                new: Vec::new(),
+            };
+            let mut span: Option<(u16, u16)> = None;
+            for local in 0..TILE_SIZE * TILE_SIZE {
+                let old = pending.old[local];
+                let new = pending.touched[local].then(|| {
+                    canvas.get_from_target(index, key.target, origin_x + local % TILE_SIZE, origin_y + local / TILE_SIZE)
+                });
+                match new {
+                    Some(new) if new != old => {
+                        match &mut span {
+                            Some((_, len)) => *len += 1,
+                            None => span = Some((local as u16, 1)),
+                        }
+                        push_run(&mut tile.old, old);
+                        push_run(&mut tile.new, new);
+                    },
+                    _ => tile.spans.extend(span.take()),
+                }
+            }
+            tile.spans.extend(span);
+
+            if !tile.spans.is_empty() {
+                tiles.push(tile);
+            }
+        }
+        ChangeSet { tiles }
+    }
+}
+
+fn push_run(runs: &mut Vec<Run>, color: Option<Color>) {
+    match runs.last_mut() {
+        Some((last, count)) if *last == color && *count < u16::MAX => *count += 1,
+        _ => runs.push((color, 1)),
+    }
+}
+
+/// Changed pixels of one tile: spans of consecutive pixels in row order, with
+/// their values before and after the action run-length encoded.
+struct TileChange {
+    key: TileKey,
+    spans: Vec<(u16, u16)>,
+    old: Vec<Run>,
+    new: Vec<Run>,
+}
+
+/// A finished pixel action, stored compactly: filling a large area with a
+/// single color takes a few bytes per tile.
+pub(crate) struct ChangeSet {
+    tiles: Vec<TileChange>,
+}
+
+impl ChangeSet {
+    pub fn is_empty(&self) -> bool {
+        self.tiles.is_empty()
+    }
+
+    /// Approximate memory held by the change set, in bytes.
+    pub fn heap_size(&self) -> usize {
+        self.tiles
+            .iter()
+            .map(|tile| {
+                size_of::<TileChange>()
+                    + tile.spans.len() * size_of::<(u16, u16)>()
+                    + (tile.old.len() + tile.new.len()) * size_of::<Run>()
+            })
+            .sum()
+    }
+
+    /// Writes back the values from before the action.
+    pub fn revert(&self, canvas: &mut CanvasState) {
+        self.write(canvas, |tile| &tile.old);
+    }
+
+    /// Writes the values from after the action.
+    pub fn apply(&self, canvas: &mut CanvasState) {
+        self.write(canvas, |tile| &tile.new);
+    }
+
+    fn write(&self, canvas: &mut CanvasState, side: impl Fn(&TileChange) -> &Vec<Run>) {
+        let mut cached: Option<(LayerId, usize)> = None;
+        for tile in &self.tiles {
+            let index = match cached {
+                Some((id, index)) if id == tile.key.layer_id => index,
+                _ => {
+                    let Some(index) = canvas.layer_index(tile.key.layer_id) else {
+                        continue;
+                    };
+                    cached = Some((tile.key.layer_id, index));
+                    index
+                },
+            };
+
+            let (origin_x, origin_y) = tile.key.origin();
+            let mut colors = side(tile)
+                .iter()
+                .flat_map(|&(color, count)| std::iter::repeat_n(color, count as usize));
+            for &(start, len) in &tile.spans {
+                for local in start as usize..(start + len) as usize {
+                    let Some(color) = colors.next() else {
+                        break;
+                    };
+                    let (x, y) = (origin_x + local % TILE_SIZE, origin_y + local / TILE_SIZE);
+                    canvas.set_on_target(index, tile.key.target, x, y, color);
+                }
+            }
+        }
+    }
+}
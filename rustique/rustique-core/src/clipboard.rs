@@ -0,0 +1,232 @@
+use crate::canvas::CanvasState;
+use crate::color::Color;
+use crate::compositing::source_over;
+use crate::document::Document;
+use crate::history::ActionKind;
+
+/// Pixels exchanged through the clipboard.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct ClipboardImage {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<Option<Color>>,
+    /// Canvas position the pixels were copied from, when they come from a
+    /// document. Pastes go back there, other images are centered.
+    pub origin: Option<(i32, i32)>,
+}
+
+impl ClipboardImage {
+    /// Reads straight RGBA bytes, fully transparent pixels are empty.
+    /// Returns `None` when `bytes` does not hold exactly `width * height`
+    /// pixels.
+    pub fn from_rgba(width: usize, height: usize, bytes: &[u8]) -> Option<Self> {
+        if width.checked_mul(height)?.checked_mul(4)? != bytes.len() {
+            return None;
+        }
+        let pixels = bytes
+            .chunks_exact(4)
+            .map(|rgba| Some(Color::from_rgba_unmultiplied(rgba[0], rgba[1], rgba[2], rgba[3])).filter(|color| color.a() > 0))
+            .collect();
+        Some(Self {
+            width,
+            height,
+            pixels,
+            origin: None,
+        })
+    }
+
+    pub fn to_rgba(&self) -> Vec<u8> {
+        self.pixels
+            .iter()
+            .flat_map(|pixel| pixel.unwrap_or(Color::TRANSPARENT).to_array())
+            .collect()
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> Option<Color> {
+        if x >= self.width || y >= self.height {
+            return None;
+        }
+        self.pixels[y * self.width + x]
+    }
+
+    /// Whether both hold the same pixels, wherever they come from.
+    pub fn same_pixels(&self, other: &ClipboardImage) -> bool {
+        self.width == other.width && self.height == other.height && self.pixels == other.pixels
+    }
+}
+
+/// Pasted pixels hovering above the canvas until they are committed to the
+/// active layer.
+#[derive(Clone, Debug)]
+pub struct FloatingSelection {
+    pub image: ClipboardImage,
+    pub x: i32,
+    pub y: i32,
+}
+
+impl FloatingSelection {
+    pub fn get(&self, x: usize, y: usize) -> Option<Color> {
+        let (local_x, local_y) = (x as i32 - self.x, y as i32 - self.y);
+        if local_x < 0 || local_y < 0 {
+            return None;
+        }
+        self.image.get(local_x as usize, local_y as usize)
+    }
+
+    /// Canvas pixels covered by the floating image.
+    fn covered(&self, canvas: &CanvasState) -> impl Iterator<Item = (usize, usize, Color)> + '_ {
+        let (width, height) = (canvas.width as i32, canvas.height as i32);
+        (0..self.image.height).flat_map(move |y| {
+            (0..self.image.width).filter_map(move |x| {
+                let (cx, cy) = (self.x + x as i32, self.y + y as i32);
+                let color = self.image.get(x, y)?;
+                (cx >= 0 && cy >= 0 && cx < width && cy < height).then_some((cx as usize, cy as usize, color))
+            })
+        })
+    }
+}
+
+/// Clipboard commands. Copies read the active layer, or its mask while it
+/// is edited, limited to the selection.
+impl Document {
+    pub fn copy(&self) -> Option<ClipboardImage> {
+        let layer = self.canvas.active_layer()?;
+        if layer.is_group() {
+            return None;
+        }
+
+        let (x0, y0, width, height) = match &self.selection {
+            Some(selection) => selection.bounds()?,
+            None => (0, 0, self.canvas.width, self.canvas.height),
+        };
+        let mut pixels = Vec::with_capacity(width * height);
+        for y in y0..y0 + height {
+            for x in x0..x0 + width {
+                pixels.push(if self.is_selected(x, y) { self.canvas.get_from_active_layer(x, y) } else { None });
+            }
+        }
+        Some(ClipboardImage {
+            width,
+            height,
+            pixels,
+            origin: Some((x0 as i32, y0 as i32)),
+        })
+    }
+
+    /// Copies, then clears the copied pixels as a single undoable action.
+    pub fn cut(&mut self) -> Option<ClipboardImage> {
+        if !self.canvas.active_layer_editable() {
+            return None;
+        }
+        let image = self.copy()?;
+
+        self.save_state();
+        let (x0, y0) = image.origin.unwrap_or_default();
+        for y in 0..image.height {
+            for x in 0..image.width {
+                if image.get(x, y).is_some() {
+                    let empty = self.canvas.active_target().normalize(None);
+                    self.record_change(ActionKind::Cut, x0 as usize + x, y0 as usize + y, empty);
+                }
+            }
+        }
+        self.save_state();
+        Some(image)
+    }
+
+    /// Where `image` lands when pasted: where it was copied from, or the
+    /// center of the canvas.
+    fn paste_position(&self, image: &ClipboardImage) -> (i32, i32) {
+        image.origin.unwrap_or((
+            (self.canvas.width as i32 - image.width as i32) / 2,
+            (self.canvas.height as i32 - image.height as i32) / 2,
+        ))
+    }
+
+    /// Makes `image` the floating selection, committing the previous one.
+    /// The selection is dropped so the pasted pixels are not clipped.
+    pub fn paste(&mut self, image: ClipboardImage) {
+        self.commit_floating();
+        let (x, y) = self.paste_position(&image);
+        self.selection = None;
+        self.floating = Some(FloatingSelection { image, x, y });
+    }
+
+    /// Pastes `image` on a new layer on top of the stack.
+    pub fn paste_as_layer(&mut self, image: ClipboardImage, name: String) -> bool {
+        self.commit_floating();
+        let (x, y) = self.paste_position(&image);
+        let floating = FloatingSelection { image, x, y };
+        self.edit_layers(ActionKind::PasteAsLayer, |canvas| {
+            canvas.add_layer(name);
+            let index = canvas.active_layer_index;
+            let covered: Vec<_> = floating.covered(canvas).collect();
+            for (x, y, color) in covered {
+                canvas.set_on_layer(index, x, y, Some(color));
+            }
+            true
+        })
+    }
+
+    pub fn move_floating(&mut self, dx: i32, dy: i32) {
+        if let Some(floating) = &mut self.floating {
+            floating.x += dx;
+            floating.y += dy;
+        }
+    }
+
+    /// Paints the floating selection onto the active layer as one undoable
+    /// action. It stays floating when the layer cannot be edited.
+    pub fn commit_floating(&mut self) -> bool {
+        if self.floating.is_none() || !self.canvas.active_layer_editable() {
+            return false;
+        }
+        let Some(floating) = self.floating.take() else {
+            return false;
+        };
+
+        self.save_state();
+        let target = self.canvas.active_target();
+        let covered: Vec<_> = floating.covered(&self.canvas).collect();
+        for (x, y, color) in covered {
+            let current = self.canvas.get_from_active_layer(x, y).unwrap_or(Color::TRANSPARENT);
+            let new_color = target.normalize(Some(source_over(color, current)));
+            self.record_change(ActionKind::Paste, x, y, new_color);
+        }
+        self.save_state()
+    }
+
+    pub fn cancel_floating(&mut self) -> bool {
+        self.floating.take().is_some()
+    }
+
+    /// Displayed color at `(x, y)`: the composited layers with the floating
+    /// selection on top.
+    pub fn display_pixel(&self, x: usize, y: usize) -> Option<Color> {
+        let below = self.canvas.get(x, y);
+        match self.floating.as_ref().and_then(|floating| floating.get(x, y)) {
+            Some(color) => Some(source_over(color, below.unwrap_or(Color::TRANSPARENT))),
+            None => below,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rgba_round_trips_with_empty_transparent_pixels() {
+        let bytes = [255, 0, 0, 255, 9, 9, 9, 0];
+        let image = ClipboardImage::from_rgba(2, 1, &bytes).unwrap();
+        assert_eq!(image.pixels, [Some(Color::from_rgb(255, 0, 0)), None]);
+        assert_eq!(image.to_rgba(), [255, 0, 0, 255, 0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn rgba_of_the_wrong_size_is_refused() {
+        assert!(ClipboardImage::from_rgba(2, 2, &[0; 12]).is_none());
+        assert!(ClipboardImage::from_rgba(1, 1, &[0; 5]).is_none());
+        assert!(ClipboardImage::from_rgba(usize::MAX, 2, &[]).is_none());
+    }
+}
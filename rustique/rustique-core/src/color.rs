@@ -0,0 +1,51 @@
+use serde::{Serialize, Deserialize};
+
+/// Straight (non-premultiplied) sRGBA color used for every pixel of a document.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+pub struct Color([u8; 4]);
+
+impl Color {
+    pub const TRANSPARENT: Color = Color([0, 0, 0, 0]);
+    pub const BLACK: Color = Color([0, 0, 0, 255]);
+    pub const WHITE: Color = Color([255, 255, 255, 255]);
+
+    pub const fn from_rgb(r: u8, g: u8, b: u8) -> Self {
+        Self([r, g, b, 255])
+    }
+
+    pub const fn from_rgba_unmultiplied(r: u8, g: u8, b: u8, a: u8) -> Self {
+        Self([r, g, b, a])
+    }
+
+    pub const fn from_gray(l: u8) -> Self {
+        Self([l, l, l, 255])
+    }
+
+    pub const fn from_array(rgba: [u8; 4]) -> Self {
+        Self(rgba)
+    }
+
+    pub const fn to_array(self) -> [u8; 4] {
+        self.0
+    }
+
+    pub const fn r(self) -> u8 {
+        self.0[0]
+    }
+
+    pub const fn g(self) -> u8 {
+        self.0[1]
+    }
+
+    pub const fn b(self) -> u8 {
+        self.0[2]
+    }
+
+    pub const fn a(self) -> u8 {
+        self.0[3]
+    }
+
+    pub const fn with_alpha(self, a: u8) -> Self {
+        Self([self.0[0], self.0[1], self.0[2], a])
+    }
+}
@@ -0,0 +1,324 @@
+use serde::{Serialize, Deserialize};
+
+use crate::color::Color;
+
+/// How a source pixel is mixed with the destination it is painted on.
+/// Formulas follow the W3C "Compositing and Blending" specification.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, Debug, Default)]
+pub enum BlendMode {
+    #[default]
+    Normal,
+    Add,
+    Multiply,
+    Screen,
+    Overlay,
+    Darken,
+    Lighten,
+    ColorDodge,
+    ColorBurn,
+    SoftLight,
+    Hue,
+    Saturation,
+    Color,
+    Luminosity,
+    /// Paints only where the destination is transparent, as if underneath it.
+    Behind,
+}
+
+impl BlendMode {
+    pub fn all() -> Vec<BlendMode> {
+        vec![
+            BlendMode::Normal,
+            BlendMode::Behind,
+            BlendMode::Darken,
+            BlendMode::Multiply,
+            BlendMode::ColorBurn,
+            BlendMode::Lighten,
+            BlendMode::Screen,
+            BlendMode::ColorDodge,
+            BlendMode::Add,
+            BlendMode::Overlay,
+            BlendMode::SoftLight,
+            BlendMode::Hue,
+            BlendMode::Saturation,
+            BlendMode::Color,
+            BlendMode::Luminosity,
+        ]
+    }
+
+    /// Modes offered for layers. `Behind` only makes sense while painting.
+    pub fn layer_modes() -> Vec<BlendMode> {
+        Self::all()
+            .into_iter()
+            .filter(|mode| *mode != BlendMode::Behind)
+            .collect()
+    }
+}
+
+/// Porter-Duff "source over": paints `src` on top of `dst`.
+pub fn source_over(src: Color, dst: Color) -> Color {
+    let src_a = src.a() as f32 / 255.0;
+    if src_a >= 1.0 || dst.a() == 0 {
+        return src;
+    }
+    if src_a <= 0.0 {
+        return dst;
+    }
+
+    let dst_a = dst.a() as f32 / 255.0;
+    let out_a = src_a + dst_a * (1.0 - src_a);
+    let channel = |s: u8, d: u8| {
+        let value = (s as f32 * src_a + d as f32 * dst_a * (1.0 - src_a)) / out_a;
+        value.round().clamp(0.0, 255.0) as u8
+    };
+
+    Color::from_rgba_unmultiplied(
+        channel(src.r(), dst.r()),
+        channel(src.g(), dst.g()),
+        channel(src.b(), dst.b()),
+        (out_a * 255.0).round() as u8,
+    )
+}
+
+/// Paints `src` over `dst` using `mode` to mix the colors where both overlap.
+pub fn blend(src: Color, dst: Color, mode: BlendMode) -> Color {
+    match mode {
+        BlendMode::Normal => return source_over(src, dst),
+        BlendMode::Behind => return source_over(dst, src),
+        _ => {}
+    }
+
+    if dst.a() == 0 || src.a() == 0 {
+        return source_over(src, dst);
+    }
+
+    let src_a = src.a() as f32 / 255.0;
+    let dst_a = dst.a() as f32 / 255.0;
+    let cs = to_unit(src);
+    let cb = to_unit(dst);
+    let mixed = blend_rgb(mode, cb, cs);
+
+    let out_a = src_a + dst_a * (1.0 - src_a);
+    let channel = |i: usize| {
+        let value = src_a * (1.0 - dst_a) * cs[i]
+            + src_a * dst_a * mixed[i]
+            + (1.0 - src_a) * dst_a * cb[i];
+        ((value / out_a) * 255.0).round().clamp(0.0, 255.0) as u8
+    };
+
+    Color::from_rgba_unmultiplied(channel(0), channel(1), channel(2), (out_a * 255.0).round() as u8)
+}
+
+/// Paints one layer pixel with its layer `opacity` and `mode` over `dst`.
+/// Fully transparent results are reported as `None`.
+pub fn composite_layer(dst: Option<Color>, src: Option<Color>, opacity: f32, mode: BlendMode) -> Option<Color> {
+    let Some(src) = src else {
+        return dst;
+    };
+    let src = if opacity < 1.0 {
+        src.with_alpha((src.a() as f32 * opacity.max(0.0)).round() as u8)
+    } else {
+        src
+    };
+    Some(blend(src, dst.unwrap_or(Color::TRANSPARENT), mode)).filter(|color| color.a() > 0)
+}
+
+fn to_unit(color: Color) -> [f32; 3] {
+    [
+        color.r() as f32 / 255.0,
+        color.g() as f32 / 255.0,
+        color.b() as f32 / 255.0,
+    ]
+}
+
+/// Mixing function `B(Cb, Cs)` of the specification, on opaque colors.
+fn blend_rgb(mode: BlendMode, cb: [f32; 3], cs: [f32; 3]) -> [f32; 3] {
+    match mode {
+        BlendMode::Hue => set_lum(set_sat(cs, sat(cb)), lum(cb)),
+        BlendMode::Saturation => set_lum(set_sat(cb, sat(cs)), lum(cb)),
+        BlendMode::Color => set_lum(cs, lum(cb)),
+        BlendMode::Luminosity => set_lum(cb, lum(cs)),
+        _ => [
+            blend_channel(mode, cb[0], cs[0]),
+            blend_channel(mode, cb[1], cs[1]),
+            blend_channel(mode, cb[2], cs[2]),
+        ],
+    }
+}
+
+fn blend_channel(mode: BlendMode, cb: f32, cs: f32) -> f32 {
+    match mode {
+        BlendMode::Add => (cb + cs).min(1.0),
+        BlendMode::Multiply => cb * cs,
+        BlendMode::Screen => screen(cb, cs),
+        BlendMode::Overlay => hard_light(cs, cb),
+        BlendMode::Darken => cb.min(cs),
+        BlendMode::Lighten => cb.max(cs),
+        BlendMode::ColorDodge => {
+            if cb <= 0.0 {
+                0.0
+            } else if cs >= 1.0 {
+                1.0
+            } else {
+                (cb / (1.0 - cs)).min(1.0)
+            }
+        },
+        BlendMode::ColorBurn => {
+            if cb >= 1.0 {
+                1.0
+            } else if cs <= 0.0 {
+                0.0
+            } else {
+                1.0 - ((1.0 - cb) / cs).min(1.0)
+            }
+        },
+        BlendMode::SoftLight => {
+            if cs <= 0.5 {
+                cb - (1.0 - 2.0 * cs) * cb * (1.0 - cb)
+            } else {
+                let d = if cb <= 0.25 {
+                    ((16.0 * cb - 12.0) * cb + 4.0) * cb
+                } else {
+                    cb.sqrt()
+                };
+                cb + (2.0 * cs - 1.0) * (d - cb)
+            }
+        },
+        _ => cs,
+    }
+}
+
+fn screen(cb: f32, cs: f32) -> f32 {
+    cb + cs - cb * cs
+}
+
+fn hard_light(cb: f32, cs: f32) -> f32 {
+    if cs <= 0.5 {
+        cb * 2.0 * cs
+    } else {
+        screen(cb, 2.0 * cs - 1.0)
+    }
+}
+
+fn lum(c: [f32; 3]) -> f32 {
+    0.3 * c[0] + 0.59 * c[1] + 0.11 * c[2]
+}
+
+fn clip_color(c: [f32; 3]) -> [f32; 3] {
+    let l = lum(c);
+    let n = c[0].min(c[1]).min(c[2]);
+    let x = c[0].max(c[1]).max(c[2]);
+    let mut out = c;
+
+    if n < 0.0 {
+        for value in &mut out {
+            *value = l + (*value - l) * l / (l - n);
+        }
+    }
+    if x > 1.0 {
+        for value in &mut out {
+            *value = l + (*value - l) * (1.0 - l) / (x - l);
+        }
+    }
+
+    out
+}
+
+fn set_lum(c: [f32; 3], l: f32) -> [f32; 3] {
+    let d = l - lum(c);
+    clip_color([c[0] + d, c[1] + d, c[2] + d])
+}
+
+fn sat(c: [f32; 3]) -> f32 {
+    c[0].max(c[1]).max(c[2]) - c[0].min(c[1]).min(c[2])
+}
+
+fn set_sat(c: [f32; 3], s: f32) -> [f32; 3] {
+    let mut order = [0, 1, 2];
+    order.sort_by(|&a, &b| c[a].total_cmp(&c[b]));
+    let [min, mid, max] = order;
+
+    let mut out = [0.0; 3];
+    if c[max] > c[min] {
+        out[mid] = (c[mid] - c[min]) * s / (c[max] - c[min]);
+        out[max] = s;
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RED: Color = Color::from_rgb(255, 0, 0);
+    const BLUE: Color = Color::from_rgb(0, 0, 255);
+
+    #[test]
+    fn source_over_keeps_opaque_sources_and_mixes_translucent_ones() {
+        assert_eq!(source_over(RED, BLUE), RED);
+        assert_eq!(source_over(RED.with_alpha(0), BLUE), BLUE);
+        assert_eq!(source_over(RED.with_alpha(128), Color::TRANSPARENT), RED.with_alpha(128));
+        assert_eq!(source_over(RED.with_alpha(128), BLUE), Color::from_rgba_unmultiplied(128, 0, 127, 255));
+    }
+
+    #[test]
+    fn translucent_over_translucent_adds_coverage() {
+        let out = source_over(RED.with_alpha(128), BLUE.with_alpha(128));
+        assert_eq!(out.a(), 192);
+        assert!(out.r() > out.b());
+    }
+
+    #[test]
+    fn separable_modes_follow_their_formulas() {
+        let src = Color::from_rgb(100, 200, 50);
+        let dst = Color::from_rgb(150, 100, 50);
+        assert_eq!(blend(src, dst, BlendMode::Darken), Color::from_rgb(100, 100, 50));
+        assert_eq!(blend(src, dst, BlendMode::Lighten), Color::from_rgb(150, 200, 50));
+        assert_eq!(blend(src, dst, BlendMode::Add), Color::from_rgb(250, 255, 100));
+        assert_eq!(blend(src, Color::WHITE, BlendMode::Multiply), src);
+        assert_eq!(blend(src, Color::BLACK, BlendMode::Multiply), Color::BLACK);
+        assert_eq!(blend(Color::BLACK, dst, BlendMode::Screen), dst);
+        assert_eq!(blend(Color::WHITE, dst, BlendMode::Screen), Color::WHITE);
+    }
+
+    #[test]
+    fn neutral_colors_leave_the_destination_unchanged() {
+        let dst = Color::from_rgb(30, 140, 220);
+        let gray = Color::from_gray(128);
+        for mode in [BlendMode::Overlay, BlendMode::SoftLight] {
+            let out = blend(gray, dst, mode);
+            for (a, b) in out.to_array().iter().zip(dst.to_array()) {
+                assert!(a.abs_diff(b) <= 1, "{:?} gave {:?}", mode, out);
+            }
+        }
+    }
+
+    #[test]
+    fn non_separable_modes_swap_hue_and_luminosity() {
+        assert_eq!(blend(Color::from_gray(200), Color::from_gray(50), BlendMode::Luminosity), Color::from_gray(200));
+        assert_eq!(blend(RED, Color::from_gray(128), BlendMode::Saturation), Color::from_gray(128));
+        let colored = blend(RED, Color::from_gray(128), BlendMode::Color);
+        assert!(colored.r() > colored.g() && colored.g() == colored.b());
+    }
+
+    #[test]
+    fn every_mode_paints_normally_on_transparent_pixels() {
+        for mode in BlendMode::all() {
+            assert_eq!(blend(RED, Color::TRANSPARENT, mode), RED, "{:?}", mode);
+        }
+    }
+
+    #[test]
+    fn behind_only_fills_transparent_pixels() {
+        assert_eq!(blend(RED, BLUE, BlendMode::Behind), BLUE);
+        assert_eq!(blend(RED, Color::TRANSPARENT, BlendMode::Behind), RED);
+    }
+
+    #[test]
+    fn composite_layer_applies_opacity() {
+        assert_eq!(composite_layer(None, Some(RED), 0.5, BlendMode::Normal), Some(RED.with_alpha(128)));
+        assert_eq!(composite_layer(None, Some(RED), 0.0, BlendMode::Normal), None);
+        assert_eq!(composite_layer(Some(BLUE), None, 1.0, BlendMode::Multiply), Some(BLUE));
+    }
+}
@@ -0,0 +1,62 @@
+use crate::canvas::CanvasState;
+use crate::document::Document;
+use crate::history::ActionKind;
+use crate::resample::collect_rows;
+
+impl CanvasState {
+    /// Keeps the `width` by `height` area at `(x, y)` of every layer.
+    pub fn crop(&mut self, x: usize, y: usize, width: usize, height: usize) {
+        self.reframe(width, height, -(x as i64), -(y as i64));
+    }
+
+    /// The image without its borders of a single color or of transparency,
+    /// as `(x, y, width, height)`. The border color is the one of the top
+    /// left corner. `None` when there is nothing to trim or nothing left.
+    pub fn trim_bounds(&self) -> Option<(usize, usize, usize, usize)> {
+        let (width, height) = (self.width, self.height);
+        let pixels = collect_rows(width, height, |x, y| self.get(x, y));
+        let border = *pixels.first()?;
+
+        let row_is_border = |y: usize| pixels[y * width..(y + 1) * width].iter().all(|&pixel| pixel == border);
+        let column_is_border = |x: usize, top: usize, bottom: usize| (top..bottom).all(|y| pixels[y * width + x] == border);
+
+        let top = (0..height).find(|&y| !row_is_border(y))?;
+        let bottom = (top..height).rfind(|&y| !row_is_border(y))? + 1;
+        let left = (0..width).find(|&x| !column_is_border(x, top, bottom))?;
+        let right = (left..width).rfind(|&x| !column_is_border(x, top, bottom))? + 1;
+
+        let bounds = (left, top, right - left, bottom - top);
+        (bounds != (0, 0, width, height)).then_some(bounds)
+    }
+}
+
+/// Crops, each a single undoable action applied to all layers alike.
+impl Document {
+    pub fn crop(&mut self, x: usize, y: usize, width: usize, height: usize) -> bool {
+        let fits = width > 0 && height > 0 && x + width <= self.width() && y + height <= self.height();
+        if !fits || (x, y, width, height) == (0, 0, self.width(), self.height()) {
+            return false;
+        }
+        self.begin_reshape();
+        self.edit_layers(ActionKind::Crop, |canvas| {
+            canvas.crop(x, y, width, height);
+            true
+        })
+    }
+
+    /// Crops to the non-empty pixels of the active layer or group.
+    pub fn crop_to_layer_content(&mut self) -> bool {
+        match self.canvas.content_bounds(self.canvas.active_layer_index) {
+            Some((x, y, width, height)) => self.crop(x, y, width, height),
+            None => false,
+        }
+    }
+
+    /// Crops away transparent or single-colored borders.
+    pub fn trim(&mut self) -> bool {
+        match self.canvas.trim_bounds() {
+            Some((x, y, width, height)) => self.crop(x, y, width, height),
+            None => false,
+        }
+    }
+}
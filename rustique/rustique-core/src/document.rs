@@ -0,0 +1,223 @@
+use std::collections::BTreeSet;
+
+use rayon::prelude::*;
+
+use crate::brush_system::{BrushType, Dab, TipId};
+use crate::canvas::CanvasState;
+use crate::clipboard::FloatingSelection;
+use crate::color::Color;
+use crate::history::{ActionKind, CoalesceKey, History, LayerSnapshot};
+use crate::selection::Selection;
+use crate::stroke::{StrokeBuffer, StrokeStyle};
+
+/// Dab area, in pixels, from which its pixels are worked out in parallel.
+const PARALLEL_DAB_PIXELS: usize = 64 * 64;
+
+/// A canvas together with its undo history. Every editing operation of the
+/// engine goes through a `Document` so that it can be undone.
+pub struct Document {
+    pub canvas: CanvasState,
+    pub history: History,
+    /// Pixels editing is limited to, `None` when the whole canvas is.
+    pub selection: Option<Selection>,
+    /// Pasted pixels not committed to a layer yet.
+    pub floating: Option<FloatingSelection>,
+    stroke: StrokeBuffer,
+    /// Custom tips and hoses of the committed strokes.
+    used_tips: BTreeSet<TipId>,
+    /// Custom tip or hose of the current stroke.
+    stroke_tip: Option<TipId>,
+    /// Pixels changed since the last [`Document::take_damage`], as left, top,
+    /// right and bottom edges.
+    damage: Option<(usize, usize, usize, usize)>,
+}
+
+impl Document {
+    pub fn new(width: usize, height: usize) -> Self {
+        Self::from_canvas(CanvasState::new(width, height))
+    }
+
+    pub fn from_canvas(canvas: CanvasState) -> Self {
+        Self {
+            canvas,
+            history: History::new(),
+            selection: None,
+            floating: None,
+            stroke: StrokeBuffer::new(),
+            used_tips: BTreeSet::new(),
+            stroke_tip: None,
+            damage: None,
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.canvas.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.canvas.height
+    }
+
+    /// Changes one pixel of the active layer as part of the current action.
+    /// Pixels outside of the selection are left untouched.
+    pub fn record_change(&mut self, action: ActionKind, x: usize, y: usize, new_color: Option<Color>) -> bool {
+        if !self.is_selected(x, y) || !self.history.record_change(&mut self.canvas, action, x, y, new_color) {
+            return false;
+        }
+        self.damage = Some(match self.damage {
+            Some((left, top, right, bottom)) => (left.min(x), top.min(y), right.max(x + 1), bottom.max(y + 1)),
+            None => (x, y, x + 1, y + 1),
+        });
+        true
+    }
+
+    /// Bounds of the pixels changed through [`Document::record_change`] since
+    /// the last call, as `(x, y, width, height)`.
+    pub fn take_damage(&mut self) -> Option<(usize, usize, usize, usize)> {
+        self.damage.take().map(|(left, top, right, bottom)| (left, top, right - left, bottom - top))
+    }
+
+    pub fn has_damage(&self) -> bool {
+        self.damage.is_some()
+    }
+
+    /// Blends one brush dab into the active layer as part of the current
+    /// stroke. See [`StrokeBuffer::apply_dab`].
+    pub fn apply_dab(&mut self, x: usize, y: usize, style: &StrokeStyle, dab_alpha: f32) -> bool {
+        if x >= self.canvas.width || y >= self.canvas.height {
+            return false;
+        }
+
+        let current = self.canvas.get_from_active_layer(x, y);
+        let new_color = self.stroke.apply_dab(y * self.canvas.width + x, current, style, dab_alpha);
+        self.record_change(style.action(), x, y, new_color)
+    }
+
+    /// Stamps `dab`, its strength scaled by `weight`, onto the active layer as
+    /// part of the current stroke. The new values of large dabs are worked
+    /// out in parallel, then written in one go.
+    pub fn stamp_dab(&mut self, dab: &Dab, style: &StrokeStyle, weight: f32) {
+        let size = dab.size as i32;
+        let (left, top) = (dab.x - size / 2, dab.y - size / 2);
+        let (width, height) = (self.canvas.width as i32, self.canvas.height as i32);
+        let columns = left.max(0)..(left + size).min(width);
+        let rows = top.max(0)..(top + size).min(height);
+        if columns.is_empty() || rows.is_empty() {
+            return;
+        }
+
+        let pixel = |x: i32, y: i32| {
+            let dab_alpha = dab.mask[((y - top) * size + x - left) as usize] * dab.opacity * weight;
+            let (x, y) = (x as usize, y as usize);
+            if dab_alpha <= 0.0 || !self.is_selected(x, y) {
+                return None;
+            }
+            let idx = y * self.canvas.width + x;
+            let current = self.canvas.get_from_active_layer(x, y);
+            let (stroke_pixel, color) = self.stroke.dab_result(idx, current, style, dab_alpha);
+            (!self.stroke.holds(idx, &stroke_pixel)).then_some((x, y, stroke_pixel, color))
+        };
+        let results: Vec<_> = if columns.len() * rows.len() >= PARALLEL_DAB_PIXELS {
+            rows.into_par_iter().flat_map_iter(|y| columns.clone().filter_map(move |x| pixel(x, y))).collect()
+        } else {
+            rows.flat_map(|y| columns.clone().filter_map(move |x| pixel(x, y))).collect()
+        };
+
+        let action = style.action();
+        let mut changed = false;
+        for (x, y, stroke_pixel, color) in results {
+            self.stroke.store(y * self.canvas.width + x, stroke_pixel);
+            changed |= self.record_change(action, x, y, color);
+        }
+        if let BrushType::Custom(id) = style.brush_type
+            && changed
+        {
+            self.stroke_tip = Some(id);
+        }
+    }
+
+    /// Custom tips and hoses painted with, to be embedded when the document
+    /// is saved. Includes the stroke still in progress.
+    pub fn used_tips(&self) -> BTreeSet<TipId> {
+        self.used_tips.iter().copied().chain(self.stroke_tip).collect()
+    }
+
+    /// Counts tip or hose `id` as painted with, e.g. when the document was
+    /// opened with it embedded.
+    pub fn mark_tip_used(&mut self, id: TipId) {
+        self.used_tips.insert(id);
+    }
+
+    /// Runs a change of the layer stack as a single undoable action. `edit`
+    /// returns `false` when it left the canvas untouched.
+    pub fn edit_layers(&mut self, action: ActionKind, edit: impl FnOnce(&mut CanvasState) -> bool) -> bool {
+        self.edit_layers_coalesced(action, None, edit)
+    }
+
+    /// Like [`Document::edit_layers`], successive edits with the same `key`
+    /// are undone at once.
+    pub fn edit_layers_coalesced(&mut self, action: ActionKind, key: Option<CoalesceKey>, edit: impl FnOnce(&mut CanvasState) -> bool) -> bool {
+        self.save_state();
+        let before = LayerSnapshot::of(&self.canvas);
+        if !edit(&mut self.canvas) {
+            return false;
+        }
+        self.history.record_layers(action, before, &self.canvas, key);
+        true
+    }
+
+    /// Ends the current stroke and pushes it on the undo stack.
+    pub fn save_state(&mut self) -> bool {
+        self.stroke.clear();
+        let committed = self.history.save_state(&self.canvas);
+        if let Some(id) = self.stroke_tip.take()
+            && committed
+        {
+            self.used_tips.insert(id);
+        }
+        committed
+    }
+
+    /// Undoes the last action. A floating selection is dropped first, as
+    /// the paste that made it.
+    pub fn undo(&mut self) -> bool {
+        if self.cancel_floating() {
+            return true;
+        }
+        self.save_state();
+        let changed = self.history.undo(&mut self.canvas);
+        self.drop_stale_selection();
+        changed
+    }
+
+    pub fn redo(&mut self) -> bool {
+        self.stroke.clear();
+        let changed = self.history.redo(&mut self.canvas);
+        self.drop_stale_selection();
+        changed
+    }
+
+    /// Brings the document to the state after the first `position` actions
+    /// of the history.
+    pub fn jump_to(&mut self, position: usize) -> bool {
+        self.save_state();
+        let changed = self.history.jump_to(&mut self.canvas, position);
+        self.drop_stale_selection();
+        changed
+    }
+
+    /// Commits the floating selection and drops the selection before the
+    /// canvas is reshaped, as they would no longer line up with it.
+    pub(crate) fn begin_reshape(&mut self) {
+        self.commit_floating();
+        self.selection = None;
+    }
+
+    /// Forgets the selection when the history brought back another canvas
+    /// size.
+    fn drop_stale_selection(&mut self) {
+        if self.selection.as_ref().is_some_and(|selection| (selection.width, selection.height) != (self.canvas.width, self.canvas.height)) {
+            self.selection = None;
+        }
+    }
+}
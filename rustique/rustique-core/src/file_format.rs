@@ -0,0 +1,386 @@
+use std::fmt;
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+use std::sync::Arc;
+
+use image::{ImageBuffer, ImageFormat, Rgba, RgbaImage};
+use serde::{Serialize, Deserialize};
+
+use crate::brush_system::{BrushTip, ImageHose};
+use crate::canvas::{CanvasState, EditTarget};
+use crate::color::Color;
+use crate::compositing::BlendMode;
+use crate::layer::{Layer, LayerId, LayerKind, LayerLocks, LayerMask};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FileFormat {
+    Png,
+    Jpeg,
+    Bmp,
+    Tiff,
+    Gif,
+    WebP,
+    Rustiq,
+    Unknown,
+}
+
+impl FileFormat {
+    pub fn from_extension(ext: &str) -> Self {
+        match ext.to_lowercase().as_str() {
+            "png" => FileFormat::Png,
+            "jpg" | "jpeg" => FileFormat::Jpeg,
+            "bmp" => FileFormat::Bmp,
+            "tiff" | "tif" => FileFormat::Tiff,
+            "gif" => FileFormat::Gif,
+            "webp" => FileFormat::WebP,
+            "rustiq" => FileFormat::Rustiq,
+            _ => FileFormat::Unknown,
+        }
+    }
+
+    pub fn detect<P: AsRef<Path>>(path: P) -> Self {
+        path.as_ref()
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .map(FileFormat::from_extension)
+            .unwrap_or(FileFormat::Unknown)
+    }
+
+    pub fn get_image_format(&self) -> Option<ImageFormat> {
+        match self {
+            FileFormat::Png => Some(ImageFormat::Png),
+            FileFormat::Jpeg => Some(ImageFormat::Jpeg),
+            FileFormat::Bmp => Some(ImageFormat::Bmp),
+            FileFormat::Tiff => Some(ImageFormat::Tiff),
+            FileFormat::Gif => Some(ImageFormat::Gif),
+            FileFormat::WebP => Some(ImageFormat::WebP),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum FileError {
+    UnsupportedFormat(String),
+    Read(io::Error),
+    Create(io::Error),
+    Write(io::Error),
+    Parse(serde_json::Error),
+    Serialize(serde_json::Error),
+    ImageDecode(image::ImageError),
+    ImageEncode(image::ImageError),
+    InvalidBrush(String),
+    InvalidPresets(serde_json::Error),
+}
+
+impl fmt::Display for FileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FileError::UnsupportedFormat(path) => write!(f, "unsupported file format: {}", path),
+            FileError::Read(e) => write!(f, "cannot read file: {}", e),
+            FileError::Create(e) => write!(f, "cannot create file: {}", e),
+            FileError::Write(e) => write!(f, "cannot write file: {}", e),
+            FileError::Parse(e) => write!(f, "invalid .rustiq file: {}", e),
+            FileError::Serialize(e) => write!(f, "cannot serialize document: {}", e),
+            FileError::ImageDecode(e) => write!(f, "cannot decode image: {}", e),
+            FileError::ImageEncode(e) => write!(f, "cannot encode image: {}", e),
+            FileError::InvalidBrush(reason) => write!(f, "invalid brush file: {}", reason),
+            FileError::InvalidPresets(e) => write!(f, "invalid brush preset file: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for FileError {}
+
+/// Palette and tool state stored next to the pixels in a `.rustiq` file.
+#[derive(Clone)]
+pub struct DocumentSettings {
+    pub primary_color: Color,
+    pub secondary_color: Color,
+    pub saved_colors: Vec<Color>,
+    pub brush_size: i32,
+    pub eraser_size: i32,
+    /// Custom brush tips travelling with the document.
+    pub brush_tips: Vec<BrushTip>,
+    pub brush_hoses: Vec<ImageHose>,
+}
+
+impl Default for DocumentSettings {
+    fn default() -> Self {
+        Self {
+            primary_color: Color::BLACK,
+            secondary_color: Color::WHITE,
+            saved_colors: Vec::new(),
+            brush_size: 3,
+            eraser_size: 3,
+            brush_tips: Vec::new(),
+            brush_hoses: Vec::new(),
+        }
+    }
+}
+
+/// One node of the layer tree. Flat files written before groups existed are
+/// simply trees without any group.
+#[derive(Serialize, Deserialize)]
+pub struct LayerData {
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub data: Vec<Option<[u8; 4]>>,
+    pub visible: bool,
+    #[serde(default = "default_layer_opacity")]
+    pub opacity: f32,
+    #[serde(default)]
+    pub blend_mode: BlendMode,
+    #[serde(default)]
+    pub locks: LayerLocks,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mask: Option<LayerMask>,
+    #[serde(default)]
+    pub clipped: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub group: Option<GroupData>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct GroupData {
+    pub expanded: bool,
+    /// Children ordered bottom to top.
+    pub children: Vec<LayerData>,
+}
+
+fn default_layer_opacity() -> f32 {
+    1.0
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct RustiqueFile {
+    pub width: usize,
+    pub height: usize,
+    pub layers: Vec<LayerData>,
+    /// Index in the flattened layer stack, see [`CanvasState`].
+    pub active_layer_index: usize,
+    pub primary_color: [u8; 4],
+    pub secondary_color: [u8; 4],
+    pub saved_colors: Vec<[u8; 4]>,
+    pub brush_size: i32,
+    pub eraser_size: i32,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub brush_tips: Vec<BrushTip>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub brush_hoses: Vec<ImageHose>,
+}
+
+impl RustiqueFile {
+    pub fn from_canvas(canvas: &CanvasState, settings: &DocumentSettings) -> Self {
+        let layers = layers_to_tree(&canvas.layers, 0);
+
+        Self {
+            width: canvas.width,
+            height: canvas.height,
+            layers,
+            active_layer_index: canvas.active_layer_index,
+            primary_color: settings.primary_color.to_array(),
+            secondary_color: settings.secondary_color.to_array(),
+            saved_colors: settings.saved_colors.iter().map(|color| color.to_array()).collect(),
+            brush_size: settings.brush_size,
+            eraser_size: settings.eraser_size,
+            brush_tips: settings.brush_tips.clone(),
+            brush_hoses: settings.brush_hoses.clone(),
+        }
+    }
+
+    pub fn into_parts(self) -> (CanvasState, DocumentSettings) {
+        let mut layers = Vec::new();
+        flatten_tree(self.layers, 0, &mut layers);
+
+        let canvas = CanvasState {
+            width: self.width,
+            height: self.height,
+            layers,
+            active_layer_index: self.active_layer_index,
+            edit_target: EditTarget::Pixels,
+        };
+
+        let settings = DocumentSettings {
+            primary_color: Color::from_array(self.primary_color),
+            secondary_color: Color::from_array(self.secondary_color),
+            saved_colors: self.saved_colors.into_iter().map(Color::from_array).collect(),
+            brush_size: self.brush_size,
+            eraser_size: self.eraser_size,
+            brush_tips: self.brush_tips,
+            brush_hoses: self.brush_hoses,
+        };
+
+        (canvas, settings)
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, FileError> {
+        let content = fs::read_to_string(path).map_err(FileError::Read)?;
+        serde_json::from_str(&content).map_err(FileError::Parse)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), FileError> {
+        let json = serde_json::to_string(self).map_err(FileError::Serialize)?;
+        let mut file = fs::File::create(path).map_err(FileError::Create)?;
+        file.write_all(json.as_bytes()).map_err(FileError::Write)
+    }
+}
+
+/// Rebuilds the tree of the layers nested at `depth` in `layers`.
+fn layers_to_tree(layers: &[Layer], depth: usize) -> Vec<LayerData> {
+    let mut nodes = Vec::new();
+    let mut first_child = 0;
+
+    for (i, layer) in layers.iter().enumerate() {
+        if layer.depth > depth {
+            continue;
+        }
+
+        let group = match layer.kind {
+            LayerKind::Group { expanded } => Some(GroupData {
+                expanded,
+                children: layers_to_tree(&layers[first_child..i], depth + 1),
+            }),
+            LayerKind::Raster => None,
+        };
+        nodes.push(LayerData {
+            name: layer.name.clone(),
+            data: layer.data.iter().map(|pixel| pixel.map(Color::to_array)).collect(),
+            visible: layer.visible,
+            opacity: layer.opacity,
+            blend_mode: layer.blend_mode,
+            locks: layer.locks,
+            mask: layer.mask.clone(),
+            clipped: layer.clipped,
+            group,
+        });
+        first_child = i + 1;
+    }
+
+    nodes
+}
+
+/// Appends `nodes` and their children to the flat layer stack, each group
+/// right after its content.
+fn flatten_tree(nodes: Vec<LayerData>, depth: usize, layers: &mut Vec<Layer>) {
+    for node in nodes {
+        let kind = match node.group {
+            Some(group) => {
+                flatten_tree(group.children, depth + 1, layers);
+                LayerKind::Group { expanded: group.expanded }
+            },
+            None => LayerKind::Raster,
+        };
+        layers.push(Layer {
+            id: LayerId::next(),
+            name: node.name,
+            data: Arc::new(node.data.into_iter().map(|pixel| pixel.map(Color::from_array)).collect()),
+            visible: node.visible,
+            opacity: node.opacity.clamp(0.0, 1.0),
+            blend_mode: node.blend_mode,
+            locks: node.locks,
+            mask: node.mask,
+            clipped: node.clipped,
+            depth,
+            kind,
+        });
+    }
+}
+
+/// Flattens the visible layers into an RGBA image.
+pub fn render_image(canvas: &CanvasState) -> RgbaImage {
+    let mut img = ImageBuffer::new(canvas.width as u32, canvas.height as u32);
+
+    for y in 0..canvas.height {
+        for x in 0..canvas.width {
+            let color = canvas.get(x, y).unwrap_or(Color::TRANSPARENT);
+            img.put_pixel(x as u32, y as u32, Rgba(color.to_array()));
+        }
+    }
+
+    img
+}
+
+pub fn export_image<P: AsRef<Path>>(canvas: &CanvasState, path: P, format: ImageFormat) -> Result<(), FileError> {
+    render_image(canvas)
+        .save_with_format(path, format)
+        .map_err(FileError::ImageEncode)
+}
+
+/// Loads a raster image as a single-layer canvas.
+pub fn import_image<P: AsRef<Path>>(path: P) -> Result<CanvasState, FileError> {
+    let img = image::open(path).map_err(FileError::ImageDecode)?;
+    let width = img.width() as usize;
+    let height = img.height() as usize;
+    let mut canvas = CanvasState::new(width, height);
+
+    let rgba_img = img.to_rgba8();
+    for (x, y, pixel) in rgba_img.enumerate_pixels() {
+        if pixel[3] > 0 {
+            canvas.set(x as usize, y as usize, Some(Color::from_array(pixel.0)));
+        }
+    }
+
+    Ok(canvas)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A document as saved before layer settings and groups existed.
+    const LEGACY_FILE: &str = r#"{
+        "width": 2,
+        "height": 1,
+        "layers": [
+            {"name": "Background", "data": [[255, 0, 0, 255], null], "visible": true},
+            {"name": "Hidden", "data": [null, [0, 0, 255, 128]], "visible": false}
+        ],
+        "active_layer_index": 1,
+        "primary_color": [0, 0, 0, 255],
+        "secondary_color": [255, 255, 255, 255],
+        "saved_colors": [[10, 20, 30, 255]],
+        "brush_size": 5,
+        "eraser_size": 7
+    }"#;
+
+    #[test]
+    fn legacy_files_load_with_default_layer_settings() {
+        let file: RustiqueFile = serde_json::from_str(LEGACY_FILE).unwrap();
+        let (canvas, settings) = file.into_parts();
+
+        assert_eq!((canvas.width, canvas.height), (2, 1));
+        assert_eq!(canvas.active_layer_index, 1);
+        assert_eq!(canvas.layers.len(), 2);
+        for layer in &canvas.layers {
+            assert_eq!(layer.opacity, 1.0);
+            assert_eq!(layer.blend_mode, BlendMode::Normal);
+            assert!(!layer.locks.any());
+            assert!(layer.mask.is_none() && !layer.clipped);
+            assert_eq!((layer.depth, layer.kind), (0, LayerKind::Raster));
+        }
+        assert!(!canvas.layers[1].visible);
+        assert_eq!(canvas.get(0, 0), Some(Color::from_rgb(255, 0, 0)));
+        assert_eq!(canvas.get(1, 0), None);
+
+        assert_eq!(settings.saved_colors, [Color::from_rgb(10, 20, 30)]);
+        assert_eq!((settings.brush_size, settings.eraser_size), (5, 7));
+        assert!(settings.brush_tips.is_empty() && settings.brush_hoses.is_empty());
+    }
+
+    #[test]
+    fn groups_survive_a_save_and_load() {
+        let mut canvas = CanvasState::new(1, 1);
+        canvas.add_layer("Inner".to_string());
+        canvas.set(0, 0, Some(Color::WHITE));
+        assert!(canvas.group_layer(1, "Group".to_string()));
+
+        let json = serde_json::to_string(&RustiqueFile::from_canvas(&canvas, &DocumentSettings::default())).unwrap();
+        let (loaded, _) = serde_json::from_str::<RustiqueFile>(&json).unwrap().into_parts();
+
+        let shape = |canvas: &CanvasState| canvas.layers.iter().map(|layer| (layer.name.clone(), layer.depth, layer.kind)).collect::<Vec<_>>();
+        assert_eq!(shape(&loaded), shape(&canvas));
+        assert_eq!(loaded.get(0, 0), Some(Color::WHITE));
+    }
+}
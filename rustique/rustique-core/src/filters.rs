@@ -0,0 +1,350 @@
+use rayon::prelude::*;
+
+use crate::canvas::{CanvasState, EditTarget};
+use crate::color::Color;
+use crate::document::Document;
+use crate::history::ActionKind;
+use crate::resample::{collect_rows, premultiply, to_color};
+use crate::selection::Selection;
+
+/// Blur radius up to which the Gaussian kernel is used as it is. Beyond it,
+/// three box blurs approximate it in a time that does not grow with the
+/// radius.
+const EXACT_GAUSSIAN_RADIUS: f32 = 6.0;
+
+type Pixel = [f32; 4];
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum FilterKind {
+    GaussianBlur,
+    BoxBlur,
+    MotionBlur,
+    UnsharpMask,
+    EdgeDetect,
+    Emboss,
+}
+
+impl FilterKind {
+    pub fn all() -> [FilterKind; 6] {
+        [
+            FilterKind::GaussianBlur,
+            FilterKind::BoxBlur,
+            FilterKind::MotionBlur,
+            FilterKind::UnsharpMask,
+            FilterKind::EdgeDetect,
+            FilterKind::Emboss,
+        ]
+    }
+
+    /// Whether the filter has settings to choose before applying it.
+    pub fn has_settings(self) -> bool {
+        self != FilterKind::EdgeDetect
+    }
+}
+
+/// Filter working on neighbouring pixels. Blurs spread transparency as well
+/// as color, the others keep the transparency of each pixel.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Filter {
+    /// Spreads each pixel about `radius` pixels away, two standard deviations.
+    GaussianBlur { radius: f32 },
+    /// Average of the square reaching `radius` pixels around each pixel.
+    BoxBlur { radius: usize },
+    /// Average along a line `length` pixels long, at `angle` degrees
+    /// counterclockwise from the horizontal.
+    MotionBlur { length: f32, angle: f32 },
+    /// Adds `amount` percent of the difference with a Gaussian blur of
+    /// `radius`, where that difference reaches `threshold`.
+    UnsharpMask { radius: f32, amount: f32, threshold: u8 },
+    /// Sobel gradient strength of each channel.
+    EdgeDetect,
+    /// Gray relief lit from `angle` degrees, brightness differences
+    /// multiplied by `strength`.
+    Emboss { angle: f32, strength: f32 },
+}
+
+impl Filter {
+    pub fn new(kind: FilterKind) -> Self {
+        match kind {
+            FilterKind::GaussianBlur => Filter::GaussianBlur { radius: 4.0 },
+            FilterKind::BoxBlur => Filter::BoxBlur { radius: 2 },
+            FilterKind::MotionBlur => Filter::MotionBlur { length: 20.0, angle: 0.0 },
+            FilterKind::UnsharpMask => Filter::UnsharpMask { radius: 2.0, amount: 100.0, threshold: 0 },
+            FilterKind::EdgeDetect => Filter::EdgeDetect,
+            FilterKind::Emboss => Filter::Emboss { angle: 135.0, strength: 1.0 },
+        }
+    }
+
+    pub fn kind(&self) -> FilterKind {
+        match self {
+            Filter::GaussianBlur { .. } => FilterKind::GaussianBlur,
+            Filter::BoxBlur { .. } => FilterKind::BoxBlur,
+            Filter::MotionBlur { .. } => FilterKind::MotionBlur,
+            Filter::UnsharpMask { .. } => FilterKind::UnsharpMask,
+            Filter::EdgeDetect => FilterKind::EdgeDetect,
+            Filter::Emboss { .. } => FilterKind::Emboss,
+        }
+    }
+}
+
+/// Weights of the neighbours of a pixel, row by row, centered on it.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Kernel {
+    width: usize,
+    height: usize,
+    weights: Vec<f32>,
+}
+
+impl Kernel {
+    /// `None` unless both sides are odd and there is a weight for each cell.
+    pub fn new(width: usize, height: usize, weights: Vec<f32>) -> Option<Self> {
+        (width % 2 == 1 && height % 2 == 1 && weights.len() == width * height).then_some(Self { width, height, weights })
+    }
+
+    fn sobel_x() -> Self {
+        Self { width: 3, height: 3, weights: vec![-1.0, 0.0, 1.0, -2.0, 0.0, 2.0, -1.0, 0.0, 1.0] }
+    }
+
+    fn sobel_y() -> Self {
+        Self { width: 3, height: 3, weights: vec![-1.0, -2.0, -1.0, 0.0, 0.0, 0.0, 1.0, 2.0, 1.0] }
+    }
+
+    /// Brightness difference across the pixel, lit from `angle` degrees.
+    fn emboss(angle: f32) -> Self {
+        let (sin, cos) = angle.to_radians().sin_cos();
+        let weights = (0..9)
+            .map(|i| {
+                let (dx, dy) = ((i % 3) as f32 - 1.0, (i / 3) as f32 - 1.0);
+                dy * sin - dx * cos
+            })
+            .collect();
+        Self { width: 3, height: 3, weights }
+    }
+}
+
+/// Convolves `data` with `kernel`, in premultiplied alpha. The pixels at
+/// the edges repeat outwards.
+pub fn convolve_pixels(data: &[Option<Color>], width: usize, height: usize, kernel: &Kernel) -> Vec<Option<Color>> {
+    let convolved = convolve(&premultiply(data), width, height, kernel);
+    convolved.into_par_iter().map(to_color).collect()
+}
+
+fn convolve(pixels: &[Pixel], width: usize, height: usize, kernel: &Kernel) -> Vec<Pixel> {
+    let (half_width, half_height) = ((kernel.width / 2) as i64, (kernel.height / 2) as i64);
+    collect_rows(width, height, |x, y| {
+        let mut sum = [0.0; 4];
+        for (ky, row) in kernel.weights.chunks(kernel.width).enumerate() {
+            let sy = (y as i64 + ky as i64 - half_height).clamp(0, height as i64 - 1) as usize;
+            for (kx, &weight) in row.iter().enumerate() {
+                if weight == 0.0 {
+                    continue;
+                }
+                let sx = (x as i64 + kx as i64 - half_width).clamp(0, width as i64 - 1) as usize;
+                add_scaled(&mut sum, pixels[sy * width + sx], weight);
+            }
+        }
+        sum
+    })
+}
+
+fn add_scaled(sum: &mut Pixel, pixel: Pixel, weight: f32) {
+    for (total, value) in sum.iter_mut().zip(pixel) {
+        *total += value * weight;
+    }
+}
+
+fn transpose(pixels: &[Pixel], width: usize, height: usize) -> Vec<Pixel> {
+    collect_rows(height, width, |x, y| pixels[x * width + y])
+}
+
+/// Runs `pass` over the rows, then over the columns.
+fn separable(pixels: &[Pixel], width: usize, height: usize, pass: impl Fn(&[Pixel], usize) -> Vec<Pixel>) -> Vec<Pixel> {
+    let rows = pass(pixels, width);
+    let columns = pass(&transpose(&rows, width, height), height);
+    transpose(&columns, height, width)
+}
+
+/// Convolves each row with a symmetric kernel, `weights[0]` at its center.
+fn convolve_rows(pixels: &[Pixel], width: usize, weights: &[f32]) -> Vec<Pixel> {
+    let mut output = vec![[0.0; 4]; pixels.len()];
+    output.par_chunks_mut(width).zip(pixels.par_chunks(width)).for_each(|(output, row)| {
+        for (x, pixel) in output.iter_mut().enumerate() {
+            add_scaled(pixel, row[x], weights[0]);
+            for (i, &weight) in weights.iter().enumerate().skip(1) {
+                add_scaled(pixel, row[x.saturating_sub(i)], weight);
+                add_scaled(pixel, row[(x + i).min(width - 1)], weight);
+            }
+        }
+    });
+    output
+}
+
+/// Averages each row over `radius` pixels on both sides with a running sum,
+/// whatever the radius.
+fn box_rows(pixels: &[Pixel], width: usize, radius: usize) -> Vec<Pixel> {
+    let scale = 1.0 / (2 * radius + 1) as f32;
+    let radius = radius as i64;
+    let mut output = vec![[0.0; 4]; pixels.len()];
+    output.par_chunks_mut(width).zip(pixels.par_chunks(width)).for_each(|(output, row)| {
+        let at = |x: i64| row[x.clamp(0, width as i64 - 1) as usize];
+        let mut sum = [0.0; 4];
+        for x in -radius..=radius {
+            add_scaled(&mut sum, at(x), 1.0);
+        }
+        for (x, pixel) in output.iter_mut().enumerate() {
+            *pixel = sum.map(|total| total * scale);
+            add_scaled(&mut sum, at(x as i64 + radius + 1), 1.0);
+            add_scaled(&mut sum, at(x as i64 - radius), -1.0);
+        }
+    });
+    output
+}
+
+/// Radii of three box blurs adding up to a Gaussian blur of deviation `sigma`.
+fn box_radii(sigma: f32) -> [usize; 3] {
+    let ideal = (12.0 * sigma * sigma / 3.0 + 1.0).sqrt();
+    let mut lower = ideal.floor() as i64;
+    if lower % 2 == 0 {
+        lower -= 1;
+    }
+    let lower = lower.max(1) as f32;
+    let lower_count = ((12.0 * sigma * sigma - 3.0 * lower * lower - 12.0 * lower - 9.0) / (-4.0 * lower - 4.0)).round();
+    std::array::from_fn(|i| {
+        let size = if (i as f32) < lower_count { lower } else { lower + 2.0 };
+        (size as usize - 1) / 2
+    })
+}
+
+fn gaussian_blur(pixels: &[Pixel], width: usize, height: usize, radius: f32) -> Vec<Pixel> {
+    let sigma = radius / 2.0;
+    if sigma <= 0.0 {
+        return pixels.to_vec();
+    }
+    if radius > EXACT_GAUSSIAN_RADIUS {
+        let radii = box_radii(sigma);
+        return separable(pixels, width, height, |pixels, width| {
+            radii.iter().fold(pixels.to_vec(), |pixels, &radius| box_rows(&pixels, width, radius))
+        });
+    }
+
+    let half = (3.0 * sigma).ceil() as usize;
+    let mut weights: Vec<f32> = (0..=half).map(|i| (-((i * i) as f32) / (2.0 * sigma * sigma)).exp()).collect();
+    let total = weights[0] + 2.0 * weights[1..].iter().sum::<f32>();
+    for weight in &mut weights {
+        *weight /= total;
+    }
+    separable(pixels, width, height, |pixels, width| convolve_rows(pixels, width, &weights))
+}
+
+/// Averages along a line with passes of two samples each, their distance
+/// halving every pass. That spreads pixels over evenly spaced points less
+/// than a pixel apart, in a number of passes growing with the logarithm of
+/// the length.
+fn motion_blur(pixels: &[Pixel], width: usize, height: usize, length: f32, angle: f32) -> Vec<Pixel> {
+    let (sin, cos) = angle.to_radians().sin_cos();
+    let passes = length.log2().ceil().max(1.0) as i32;
+    let last = |size: usize| size as i64 - 1;
+    (1..=passes).fold(pixels.to_vec(), |pixels, pass| {
+        let offset = length / 2.0_f32.powi(pass + 1);
+        // Both samples are bilinear, with the same weights for every pixel.
+        let taps = [(offset * cos, -offset * sin), (-offset * cos, offset * sin)].map(|(dx, dy)| {
+            let (fx, fy) = (dx.floor(), dy.floor());
+            (fx as i64, fy as i64, dx - fx, dy - fy)
+        });
+        collect_rows(width, height, |x, y| {
+            let mut sum = [0.0; 4];
+            for (ox, oy, tx, ty) in taps {
+                let (x0, y0) = (x as i64 + ox, y as i64 + oy);
+                let (x1, y1) = ((x0 + 1).clamp(0, last(width)) as usize, (y0 + 1).clamp(0, last(height)) as usize);
+                let (x0, y0) = (x0.clamp(0, last(width)) as usize, y0.clamp(0, last(height)) as usize);
+                add_scaled(&mut sum, pixels[y0 * width + x0], 0.5 * (1.0 - tx) * (1.0 - ty));
+                add_scaled(&mut sum, pixels[y0 * width + x1], 0.5 * tx * (1.0 - ty));
+                add_scaled(&mut sum, pixels[y1 * width + x0], 0.5 * (1.0 - tx) * ty);
+                add_scaled(&mut sum, pixels[y1 * width + x1], 0.5 * tx * ty);
+            }
+            sum
+        })
+    })
+}
+
+/// Applies `filter` to a `width` by `height` buffer.
+pub fn filter_pixels(data: &[Option<Color>], width: usize, height: usize, filter: &Filter) -> Vec<Option<Color>> {
+    let pixels = premultiply(data);
+    let blurred = match *filter {
+        Filter::GaussianBlur { radius } if radius > 0.0 => gaussian_blur(&pixels, width, height, radius),
+        Filter::BoxBlur { radius } if radius > 0 => {
+            separable(&pixels, width, height, |pixels, width| box_rows(pixels, width, radius))
+        },
+        Filter::MotionBlur { length, angle } if length >= 1.0 => motion_blur(&pixels, width, height, length, angle),
+        Filter::UnsharpMask { radius, amount, threshold } => {
+            let blurred = gaussian_blur(&pixels, width, height, radius);
+            return collect_rows(width, height, |x, y| {
+                let i = y * width + x;
+                let (color, soft) = (data[i]?, to_color(blurred[i]).unwrap_or(Color::TRANSPARENT));
+                let [r, g, b, a] = color.to_array();
+                let sharpen = |value: u8, soft: u8| {
+                    let difference = value as f32 - soft as f32;
+                    if difference.abs() < threshold as f32 {
+                        return value;
+                    }
+                    (value as f32 + difference * amount / 100.0).round().clamp(0.0, 255.0) as u8
+                };
+                Some(Color::from_rgba_unmultiplied(sharpen(r, soft.r()), sharpen(g, soft.g()), sharpen(b, soft.b()), a))
+            });
+        },
+        Filter::EdgeDetect => {
+            let (gx, gy) = (convolve(&pixels, width, height, &Kernel::sobel_x()), convolve(&pixels, width, height, &Kernel::sobel_y()));
+            return collect_rows(width, height, |x, y| {
+                let i = y * width + x;
+                let strength = |c: usize| gx[i][c].hypot(gy[i][c]).round().clamp(0.0, 255.0) as u8;
+                data[i].map(|color| Color::from_rgba_unmultiplied(strength(0), strength(1), strength(2), color.a()))
+            });
+        },
+        Filter::Emboss { angle, strength } => {
+            let relief = convolve(&pixels, width, height, &Kernel::emboss(angle));
+            return collect_rows(width, height, |x, y| {
+                let [r, g, b, _] = relief[y * width + x];
+                let value = 128.0 + strength * (0.3 * r + 0.59 * g + 0.11 * b);
+                data[y * width + x].map(|color| Color::from_gray(value.round().clamp(0.0, 255.0) as u8).with_alpha(color.a()))
+            });
+        },
+        _ => return data.to_vec(),
+    };
+    blurred.into_par_iter().map(to_color).collect()
+}
+
+impl CanvasState {
+    /// Filters the pixels or the mask of a layer, only inside `selection`
+    /// when there is one. Returns `false` when nothing changed.
+    pub fn filter_layer(&mut self, index: usize, target: EditTarget, filter: &Filter, selection: Option<&Selection>) -> bool {
+        let (width, height) = (self.width, self.height);
+        self.filter_target(index, target, selection, |data| filter_pixels(data, width, height, filter))
+    }
+}
+
+impl Document {
+    /// Filters the active layer, or its mask while it is edited, within the
+    /// selection. A single undoable action.
+    pub fn apply_filter(&mut self, filter: &Filter) -> bool {
+        if !self.canvas.active_layer_editable() {
+            return false;
+        }
+        let (index, target) = (self.canvas.active_layer_index, self.canvas.active_target());
+        let selection = self.selection.take();
+        let changed = self.edit_layers(ActionKind::Filter(filter.kind()), |canvas| {
+            canvas.filter_layer(index, target, filter, selection.as_ref())
+        });
+        self.selection = selection;
+        changed
+    }
+
+    /// The canvas as it would look once filtered, for live previews.
+    pub fn preview_filter(&self, filter: &Filter) -> CanvasState {
+        let mut preview = self.canvas.clone();
+        if self.canvas.active_layer_editable() {
+            let target = self.canvas.active_target();
+            preview.filter_layer(self.canvas.active_layer_index, target, filter, self.selection.as_ref());
+        }
+        preview
+    }
+}
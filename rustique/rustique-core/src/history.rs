@@ -0,0 +1,438 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+
+use crate::adjustments::AdjustmentKind;
+use crate::brush_system::BrushType;
+use crate::canvas::CanvasState;
+use crate::change_set::{ChangeSet, PendingChanges};
+use crate::color::Color;
+use crate::filters::FilterKind;
+use crate::layer::{Layer, LayerId};
+
+/// Memory the undo history may use before it forgets the oldest actions.
+pub const DEFAULT_MEMORY_BUDGET: usize = 256 * 1024 * 1024;
+
+/// What produced an undo step, so that it can be named in the history.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ActionKind {
+    BrushStroke(BrushType),
+    Erase,
+    BucketFill,
+    AddLayer,
+    RemoveLayer,
+    MoveLayer,
+    GroupLayer,
+    RenameLayer,
+    LayerVisibility,
+    LayerOpacity,
+    LayerBlendMode,
+    LayerLocks,
+    AddLayerMask,
+    RemoveLayerMask,
+    ToggleLayerMask,
+    LayerClipping,
+    DuplicateLayer,
+    MergeDown,
+    MergeVisible,
+    Flatten,
+    Cut,
+    Paste,
+    PasteAsLayer,
+    Transform,
+    CanvasSize,
+    ImageSize,
+    RotateImage,
+    FlipImage,
+    RotateLayer,
+    FlipLayer,
+    Crop,
+    Adjustment(AdjustmentKind),
+    Filter(FilterKind),
+}
+
+/// Repeated edits folding into a single undo step, such as the successive
+/// values of a slider being dragged.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum CoalesceKey {
+    LayerOpacity(LayerId),
+}
+
+/// The layer stack and canvas size as they were on the other side of a
+/// structural action. Pixels that are the same on both sides are not kept: they are taken back
+/// from the canvas when the snapshot is restored.
+pub struct LayerSnapshot {
+    width: usize,
+    height: usize,
+    layers: Vec<Layer>,
+    active_layer_index: usize,
+    shared: HashSet<LayerId>,
+}
+
+impl LayerSnapshot {
+    /// Captures the stack. Cheap, pixel buffers are shared with the canvas.
+    pub fn of(canvas: &CanvasState) -> Self {
+        Self {
+            width: canvas.width,
+            height: canvas.height,
+            layers: canvas.layers.clone(),
+            active_layer_index: canvas.active_layer_index,
+            shared: HashSet::new(),
+        }
+    }
+
+    /// Drops the pixel buffers still used by `canvas`, so that painting on
+    /// them later does not copy them.
+    fn release_shared_pixels(&mut self, canvas: &CanvasState) {
+        let current: HashMap<LayerId, &Layer> = canvas.layers.iter().map(|layer| (layer.id, layer)).collect();
+        for layer in &mut self.layers {
+            if current.get(&layer.id).is_some_and(|other| Arc::ptr_eq(&layer.data, &other.data)) {
+                layer.data = Arc::default();
+                self.shared.insert(layer.id);
+            }
+        }
+    }
+
+    /// Approximate memory held by the snapshot, in bytes. Shared pixels are
+    /// accounted to the canvas.
+    fn heap_size(&self) -> usize {
+        self.layers
+            .iter()
+            .map(|layer| {
+                std::mem::size_of::<Layer>()
+                    + layer.name.len()
+                    + layer.data.len() * std::mem::size_of::<Option<Color>>()
+                    + layer.mask.as_ref().map_or(0, |mask| mask.data.len())
+            })
+            .sum()
+    }
+
+    /// Puts the snapshot on the canvas and keeps what the canvas held instead.
+    fn swap_with(&mut self, canvas: &mut CanvasState) {
+        let mut current = std::mem::take(&mut canvas.layers);
+        let index_of: HashMap<LayerId, usize> = current.iter().enumerate().map(|(i, layer)| (layer.id, i)).collect();
+
+        for layer in &mut self.layers {
+            if self.shared.contains(&layer.id)
+                && let Some(&i) = index_of.get(&layer.id)
+            {
+                layer.data = std::mem::take(&mut current[i].data);
+            }
+        }
+
+        canvas.layers = std::mem::replace(&mut self.layers, current);
+        std::mem::swap(&mut self.active_layer_index, &mut canvas.active_layer_index);
+        std::mem::swap(&mut self.width, &mut canvas.width);
+        std::mem::swap(&mut self.height, &mut canvas.height);
+    }
+}
+
+enum Change {
+    Pixels(ChangeSet),
+    /// Layer stack from the other side of the action: before it on the undo
+    /// stack, after it on the redo stack.
+    Layers {
+        snapshot: LayerSnapshot,
+        coalesce: Option<CoalesceKey>,
+    },
+}
+
+struct HistoryEntry {
+    action: ActionKind,
+    change: Change,
+}
+
+impl HistoryEntry {
+    fn heap_size(&self) -> usize {
+        match &self.change {
+            Change::Pixels(changes) => changes.heap_size(),
+            Change::Layers { snapshot, .. } => snapshot.heap_size(),
+        }
+    }
+
+    /// Swaps the canvas to the other side of the action.
+    fn toggle(&mut self, canvas: &mut CanvasState, undo: bool) {
+        match &mut self.change {
+            Change::Pixels(changes) if undo => changes.revert(canvas),
+            Change::Pixels(changes) => changes.apply(canvas),
+            Change::Layers { snapshot, .. } => snapshot.swap_with(canvas),
+        }
+    }
+}
+
+/// Undo and redo stacks. Old actions are forgotten once the stacks use more
+/// than the memory budget, the last action is always kept.
+pub struct History {
+    undo_stack: Vec<HistoryEntry>,
+    redo_stack: Vec<HistoryEntry>,
+    pending: PendingChanges,
+    pending_action: Option<ActionKind>,
+    memory_budget: usize,
+}
+
+impl Default for History {
+    fn default() -> Self {
+        Self::with_budget(DEFAULT_MEMORY_BUDGET)
+    }
+}
+
+impl History {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_budget(memory_budget: usize) -> Self {
+        Self {
+            undo_stack: Vec::new(),
+            redo_stack: Vec::new(),
+            pending: PendingChanges::default(),
+            pending_action: None,
+            memory_budget,
+        }
+    }
+
+    pub fn memory_budget(&self) -> usize {
+        self.memory_budget
+    }
+
+    pub fn set_memory_budget(&mut self, memory_budget: usize) {
+        self.memory_budget = memory_budget;
+        self.enforce_budget();
+    }
+
+    /// Approximate memory used by the undo and redo stacks, in bytes.
+    pub fn memory_usage(&self) -> usize {
+        self.undo_stack
+            .iter()
+            .chain(&self.redo_stack)
+            .map(HistoryEntry::heap_size)
+            .sum()
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.undo_stack.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo_stack.is_empty()
+    }
+
+    pub fn has_pending_changes(&self) -> bool {
+        !self.pending.is_empty()
+    }
+
+    /// Actions that can be undone, oldest first, followed by the ones that
+    /// can be redone, next one first.
+    pub fn actions(&self) -> impl Iterator<Item = ActionKind> + '_ {
+        self.undo_stack
+            .iter()
+            .chain(self.redo_stack.iter().rev())
+            .map(|entry| entry.action)
+    }
+
+    /// Number of actions currently applied, an index into [`History::actions`].
+    pub fn position(&self) -> usize {
+        self.undo_stack.len()
+    }
+
+    /// Writes `new_color` on the active layer, or its mask, and remembers the
+    /// previous value so the current action can be undone. The action is
+    /// named after the `action` of its first change. Layer locks are enforced
+    /// here. Returns `false` if nothing changed.
+    pub fn record_change(&mut self, canvas: &mut CanvasState, action: ActionKind, x: usize, y: usize, new_color: Option<Color>) -> bool {
+        let Some(layer_id) = canvas.active_layer().map(|layer| layer.id) else {
+            return false;
+        };
+
+        if x < canvas.width && y < canvas.height {
+            let target = canvas.active_target();
+            let old_color = canvas.get_from_active_layer(x, y);
+            let new_color = canvas.constrain_edit(canvas.active_layer_index, target, old_color, new_color);
+            if old_color != new_color {
+                if self.pending.is_empty() {
+                    self.pending_action = Some(action);
+                }
+                self.pending.record(layer_id, target, x, y, old_color);
+                canvas.set(x, y, new_color);
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Closes the current action and pushes it on the undo stack.
+    pub fn save_state(&mut self, canvas: &CanvasState) -> bool {
+        if self.pending.is_empty() {
+            return false;
+        }
+
+        let changes = std::mem::take(&mut self.pending).finish(canvas);
+        let Some(action) = self.pending_action.take() else {
+            return false;
+        };
+        if changes.is_empty() {
+            return false;
+        }
+        self.push(HistoryEntry {
+            action,
+            change: Change::Pixels(changes),
+        });
+        true
+    }
+
+    /// Records a change of the layer stack from `before` to the current
+    /// `canvas`. With a `coalesce` key equal to the one of the last action,
+    /// that action is extended instead.
+    pub fn record_layers(&mut self, action: ActionKind, mut before: LayerSnapshot, canvas: &CanvasState, coalesce: Option<CoalesceKey>) {
+        self.save_state(canvas);
+
+        let extends_last = coalesce.is_some()
+            && self.redo_stack.is_empty()
+            && matches!(self.undo_stack.last(), Some(HistoryEntry { change: Change::Layers { coalesce: last, .. }, .. }) if *last == coalesce);
+        if extends_last {
+            return;
+        }
+
+        before.release_shared_pixels(canvas);
+        self.push(HistoryEntry {
+            action,
+            change: Change::Layers { snapshot: before, coalesce },
+        });
+    }
+
+    /// Keeps the next edits from extending the last action, e.g. when the
+    /// slider they come from is released.
+    pub fn end_coalescing(&mut self) {
+        if let Some(HistoryEntry { change: Change::Layers { coalesce, .. }, .. }) = self.undo_stack.last_mut() {
+            *coalesce = None;
+        }
+    }
+
+    fn push(&mut self, entry: HistoryEntry) {
+        self.redo_stack.clear();
+        self.undo_stack.push(entry);
+        self.enforce_budget();
+    }
+
+    fn enforce_budget(&mut self) {
+        let mut usage = self.memory_usage();
+        while usage > self.memory_budget && !self.redo_stack.is_empty() {
+            let entry = self.redo_stack.remove(0);
+            usage -= entry.heap_size();
+        }
+        while usage > self.memory_budget && self.undo_stack.len() > 1 {
+            let entry = self.undo_stack.remove(0);
+            usage -= entry.heap_size();
+        }
+    }
+
+    pub fn undo(&mut self, canvas: &mut CanvasState) -> bool {
+        self.save_state(canvas);
+        let Some(mut entry) = self.undo_stack.pop() else {
+            return false;
+        };
+        entry.toggle(canvas, true);
+        self.redo_stack.push(entry);
+        true
+    }
+
+    pub fn redo(&mut self, canvas: &mut CanvasState) -> bool {
+        let Some(mut entry) = self.redo_stack.pop() else {
+            return false;
+        };
+        entry.toggle(canvas, false);
+        self.undo_stack.push(entry);
+        true
+    }
+
+    /// Undoes or redoes as many actions as needed for `position` of them to
+    /// be applied.
+    pub fn jump_to(&mut self, canvas: &mut CanvasState, position: usize) -> bool {
+        self.save_state(canvas);
+        let mut moved = false;
+        while self.position() > position && self.undo(canvas) {
+            moved = true;
+        }
+        while self.position() < position && self.redo(canvas) {
+            moved = true;
+        }
+        moved
+    }
+
+    pub fn clear(&mut self) {
+        self.undo_stack.clear();
+        self.redo_stack.clear();
+        self.pending = PendingChanges::default();
+        self.pending_action = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::document::Document;
+
+    const RED: Color = Color::from_rgb(255, 0, 0);
+
+    fn layer_ids(document: &Document) -> Vec<LayerId> {
+        document.canvas.layers.iter().map(|layer| layer.id).collect()
+    }
+
+    #[test]
+    fn undo_redo_and_jump_to_bring_layers_back_with_their_ids() {
+        let mut document = Document::new(4, 4);
+        let background = layer_ids(&document);
+        assert!(document.add_layer("Top".to_string()));
+        let with_top = layer_ids(&document);
+        assert!(document.record_change(ActionKind::BucketFill, 1, 2, Some(RED)));
+        assert!(document.save_state());
+        assert!(document.remove_layer(1));
+        assert_eq!(
+            document.history.actions().collect::<Vec<_>>(),
+            [ActionKind::AddLayer, ActionKind::BucketFill, ActionKind::RemoveLayer],
+        );
+
+        assert!(document.undo());
+        assert_eq!(layer_ids(&document), with_top);
+        assert_eq!(document.canvas.get_from_layer(1, 1, 2), Some(RED));
+        assert!(document.redo());
+        assert_eq!(layer_ids(&document), background);
+
+        assert!(document.jump_to(0));
+        assert_eq!(layer_ids(&document), background);
+        assert_eq!(document.canvas.get(1, 2), None);
+        assert!(document.jump_to(2));
+        assert_eq!(layer_ids(&document), with_top);
+        assert_eq!(document.canvas.get(1, 2), Some(RED));
+        assert_eq!(document.history.position(), 2);
+        assert!(document.history.can_redo());
+    }
+
+    #[test]
+    fn a_new_action_drops_the_redo_stack() {
+        let mut document = Document::new(2, 2);
+        document.record_change(ActionKind::BucketFill, 0, 0, Some(RED));
+        document.save_state();
+        assert!(document.undo());
+        document.record_change(ActionKind::BucketFill, 1, 1, Some(RED));
+        document.save_state();
+        assert!(!document.history.can_redo());
+        assert_eq!(document.canvas.get(0, 0), None);
+    }
+
+    #[test]
+    fn opacity_edits_coalesce_until_ended() {
+        let mut document = Document::new(2, 2);
+        assert!(document.set_layer_opacity(0, 0.5));
+        assert!(document.set_layer_opacity(0, 0.3));
+        assert_eq!(document.history.position(), 1);
+
+        document.history.end_coalescing();
+        assert!(document.set_layer_opacity(0, 0.2));
+        assert_eq!(document.history.position(), 2);
+
+        assert!(document.undo());
+        assert_eq!(document.canvas.layers[0].opacity, 0.3);
+        assert!(document.undo());
+        assert_eq!(document.canvas.layers[0].opacity, 1.0);
+    }
+}
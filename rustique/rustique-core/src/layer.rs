@@ -0,0 +1,148 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use serde::{Serialize, Deserialize};
+
+use crate::color::Color;
+use crate::compositing::BlendMode;
+
+/// Identity of a layer that survives reordering, unlike its index in the
+/// stack. Ids are unique within the process and not saved in files.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct LayerId(u64);
+
+impl LayerId {
+    pub fn next() -> Self {
+        static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+        Self(NEXT_ID.fetch_add(1, Ordering::Relaxed))
+    }
+}
+
+/// Protections against accidental edits of a layer.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, Serialize, Deserialize)]
+pub struct LayerLocks {
+    /// No pixel of the layer can be changed.
+    pub pixels: bool,
+    /// Pixels can be recolored but keep their transparency.
+    pub alpha: bool,
+    /// The layer content cannot be moved or transformed.
+    pub position: bool,
+}
+
+impl LayerLocks {
+    pub fn any(&self) -> bool {
+        self.pixels || self.alpha || self.position
+    }
+}
+
+/// Grayscale visibility of a layer: 255 shows the pixel, 0 hides it.
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct LayerMask {
+    pub data: Vec<u8>,
+    pub enabled: bool,
+}
+
+impl LayerMask {
+    pub fn new(width: usize, height: usize) -> Self {
+        Self {
+            data: vec![255; width * height],
+            enabled: true,
+        }
+    }
+
+    /// Mask value painted by `color`: its luminance, transparent areas hide.
+    pub fn value_from_color(color: Option<Color>) -> u8 {
+        color.map_or(0, |color| {
+            let luminance = 0.3 * color.r() as f32 + 0.59 * color.g() as f32 + 0.11 * color.b() as f32;
+            (luminance * color.a() as f32 / 255.0).round() as u8
+        })
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum LayerKind {
+    Raster,
+    /// A folder of layers. Its children are the layers right below it in the
+    /// stack with a greater depth; it holds no pixels of its own.
+    Group { expanded: bool },
+}
+
+#[derive(Clone, PartialEq)]
+pub struct Layer {
+    pub id: LayerId,
+    pub name: String,
+    /// Pixels, shared with undo snapshots until written to.
+    pub data: Arc<Vec<Option<Color>>>,
+    pub visible: bool,
+    /// Layer opacity, from 0.0 to 1.0.
+    pub opacity: f32,
+    pub blend_mode: BlendMode,
+    pub locks: LayerLocks,
+    pub mask: Option<LayerMask>,
+    /// Only shows where the layer below has pixels.
+    pub clipped: bool,
+    /// Nesting level, 0 for layers outside of any group.
+    pub depth: usize,
+    pub kind: LayerKind,
+}
+
+impl Layer {
+    pub fn new(name: String, width: usize, height: usize) -> Self {
+        Self {
+            id: LayerId::next(),
+            name,
+            data: Arc::new(vec![None; width * height]),
+            visible: true,
+            opacity: 1.0,
+            blend_mode: BlendMode::Normal,
+            locks: LayerLocks::default(),
+            mask: None,
+            clipped: false,
+            depth: 0,
+            kind: LayerKind::Raster,
+        }
+    }
+
+    pub fn new_group(name: String) -> Self {
+        Self {
+            data: Arc::default(),
+            kind: LayerKind::Group { expanded: true },
+            ..Self::new(name, 0, 0)
+        }
+    }
+
+    pub fn is_group(&self) -> bool {
+        matches!(self.kind, LayerKind::Group { .. })
+    }
+
+    /// Whether this layer fully hides what is under an opaque pixel of it.
+    pub fn covers_below(&self) -> bool {
+        self.opacity >= 1.0 && self.blend_mode == BlendMode::Normal && !self.clipped
+    }
+
+    /// Hides `pixel` according to the mask value at `idx`.
+    pub fn apply_mask(&self, pixel: Option<Color>, idx: usize) -> Option<Color> {
+        let Some(mask) = self.mask.as_ref().filter(|mask| mask.enabled) else {
+            return pixel;
+        };
+        let value = mask.data.get(idx).copied().unwrap_or(255);
+        pixel
+            .map(|color| color.with_alpha((color.a() as u32 * value as u32 / 255) as u8))
+            .filter(|color| color.a() > 0)
+    }
+
+    /// Returns what an edit replacing `old` by `new` actually writes once the
+    /// locks of the layer are taken into account.
+    pub fn constrain_edit(&self, old: Option<Color>, new: Option<Color>) -> Option<Color> {
+        if self.locks.pixels {
+            return old;
+        }
+        if self.locks.alpha {
+            return match (old, new) {
+                (Some(old), Some(new)) => Some(new.with_alpha(old.a())),
+                _ => old,
+            };
+        }
+        new
+    }
+}
@@ -0,0 +1,221 @@
+use std::ops::Range;
+use std::sync::Arc;
+
+use crate::canvas::CanvasState;
+use crate::color::Color;
+use crate::compositing::BlendMode;
+use crate::document::Document;
+use crate::history::{ActionKind, CoalesceKey};
+use crate::layer::{Layer, LayerId, LayerLocks};
+
+/// Operations combining layers. Merged pixels are computed by the same
+/// compositor as the display, with the layer opacity and blend modes baked in.
+impl CanvasState {
+    /// Copies the layer at `index`, with its content for a group, right above it.
+    pub fn duplicate_layer(&mut self, index: usize) -> bool {
+        if index >= self.layers.len() {
+            return false;
+        }
+
+        let node = self.node_range(index);
+        let mut copy = self.layers[node.clone()].to_vec();
+        for layer in &mut copy {
+            layer.id = LayerId::next();
+        }
+        if let Some(header) = copy.last_mut() {
+            header.name = format!("{} copy", header.name);
+        }
+        self.layers.splice(node.end..node.end, copy);
+        self.active_layer_index = node.end + node.len() - 1;
+        true
+    }
+
+    /// The layer that the layer at `index` would be merged into: the raster
+    /// layer right below it in the same group.
+    pub fn merge_down_target(&self, index: usize) -> Option<usize> {
+        let layer = self.layers.get(index)?;
+        let below = self.node_range(index).start.checked_sub(1)?;
+        let target = &self.layers[below];
+
+        let mergeable = target.depth == layer.depth
+            && !target.is_group()
+            && layer.visible
+            && target.visible
+            && !target.locks.pixels;
+        mergeable.then_some(below)
+    }
+
+    /// Merges the layer at `index`, or a whole group, into the layer below.
+    pub fn merge_down(&mut self, index: usize) -> bool {
+        let Some(below) = self.merge_down_target(index) else {
+            return false;
+        };
+
+        let merged = below..index + 1;
+        let data = self.render_range(merged.clone(), self.layers[index].depth);
+        let target = &mut self.layers[below];
+        target.data = Arc::new(data);
+        target.opacity = 1.0;
+        target.blend_mode = BlendMode::Normal;
+        target.mask = None;
+
+        self.layers.drain(below + 1..merged.end);
+        if self.active_layer_index >= merged.end {
+            self.active_layer_index -= merged.len() - 1;
+        } else if self.active_layer_index > below {
+            self.active_layer_index = below;
+        }
+        true
+    }
+
+    /// Merges all visible layers into one, hidden layers are kept apart.
+    pub fn merge_visible(&mut self) -> bool {
+        let nodes: Vec<Range<usize>> = (0..self.layers.len())
+            .filter(|&i| self.layers[i].depth == 0)
+            .map(|i| self.node_range(i))
+            .collect();
+        let Some(first_visible) = nodes.iter().position(|node| self.layers[node.end - 1].visible) else {
+            return false;
+        };
+        if nodes.iter().filter(|node| self.layers[node.end - 1].visible).count() < 2 {
+            return false;
+        }
+
+        let merged = Layer {
+            data: Arc::new(self.render_range(0..self.layers.len(), 0)),
+            ..Layer::new(self.layers[nodes[first_visible].end - 1].name.clone(), 0, 0)
+        };
+
+        let mut layers = Vec::with_capacity(self.layers.len());
+        let mut active_layer_index = 0;
+        for (n, node) in nodes.into_iter().enumerate() {
+            if n == first_visible {
+                active_layer_index = layers.len();
+                layers.push(merged.clone());
+            } else if !self.layers[node.end - 1].visible {
+                layers.extend_from_slice(&self.layers[node]);
+            }
+        }
+
+        self.layers = layers;
+        self.active_layer_index = active_layer_index;
+        true
+    }
+
+    /// Replaces the whole stack by a single layer holding the visible image.
+    pub fn flatten(&mut self) -> bool {
+        if let [layer] = self.layers.as_slice()
+            && layer.visible
+            && layer.covers_below()
+            && layer.mask.is_none()
+            && !layer.is_group()
+        {
+            return false;
+        }
+
+        let data = self.render_range(0..self.layers.len(), 0);
+        self.layers = vec![Layer {
+            data: Arc::new(data),
+            ..Layer::new("Background".to_string(), 0, 0)
+        }];
+        self.active_layer_index = 0;
+        true
+    }
+
+    /// Composites the layers of `range`, nested at `depth`, on their own.
+    fn render_range(&self, range: Range<usize>, depth: usize) -> Vec<Option<Color>> {
+        (0..self.width * self.height)
+            .map(|idx| self.composite_range(range.clone(), depth, idx))
+            .collect()
+    }
+}
+
+/// Undoable layer operations, for the ones above as well as the basic
+/// operations of [`CanvasState`].
+impl Document {
+    pub fn add_layer(&mut self, name: String) -> bool {
+        self.edit_layers(ActionKind::AddLayer, |canvas| {
+            canvas.add_layer(name);
+            true
+        })
+    }
+
+    pub fn remove_layer(&mut self, index: usize) -> bool {
+        self.edit_layers(ActionKind::RemoveLayer, |canvas| canvas.remove_layer(index))
+    }
+
+    pub fn move_layer_up(&mut self, index: usize) -> bool {
+        self.edit_layers(ActionKind::MoveLayer, |canvas| canvas.move_layer_up(index))
+    }
+
+    pub fn move_layer_down(&mut self, index: usize) -> bool {
+        self.edit_layers(ActionKind::MoveLayer, |canvas| canvas.move_layer_down(index))
+    }
+
+    pub fn group_layer(&mut self, index: usize, name: String) -> bool {
+        self.edit_layers(ActionKind::GroupLayer, |canvas| canvas.group_layer(index, name))
+    }
+
+    pub fn rename_layer(&mut self, index: usize, name: String) -> bool {
+        if self.canvas.layers.get(index).is_some_and(|layer| layer.name == name) {
+            return false;
+        }
+        self.edit_layers(ActionKind::RenameLayer, |canvas| canvas.rename_layer(index, name))
+    }
+
+    pub fn toggle_layer_visibility(&mut self, index: usize) -> bool {
+        self.edit_layers(ActionKind::LayerVisibility, |canvas| canvas.toggle_layer_visibility(index))
+    }
+
+    /// Consecutive opacity changes of a layer make a single undo step, until
+    /// [`History::end_coalescing`](crate::History::end_coalescing) is called.
+    pub fn set_layer_opacity(&mut self, index: usize, opacity: f32) -> bool {
+        let Some(id) = self.canvas.layers.get(index).map(|layer| layer.id) else {
+            return false;
+        };
+        self.edit_layers_coalesced(ActionKind::LayerOpacity, Some(CoalesceKey::LayerOpacity(id)), |canvas| canvas.set_layer_opacity(index, opacity))
+    }
+
+    pub fn set_layer_blend_mode(&mut self, index: usize, blend_mode: BlendMode) -> bool {
+        if self.canvas.layers.get(index).is_some_and(|layer| layer.blend_mode == blend_mode) {
+            return false;
+        }
+        self.edit_layers(ActionKind::LayerBlendMode, |canvas| canvas.set_layer_blend_mode(index, blend_mode))
+    }
+
+    pub fn set_layer_locks(&mut self, index: usize, locks: LayerLocks) -> bool {
+        self.edit_layers(ActionKind::LayerLocks, |canvas| canvas.set_layer_locks(index, locks))
+    }
+
+    pub fn add_layer_mask(&mut self, index: usize) -> bool {
+        self.edit_layers(ActionKind::AddLayerMask, |canvas| canvas.add_layer_mask(index))
+    }
+
+    pub fn remove_layer_mask(&mut self, index: usize) -> bool {
+        self.edit_layers(ActionKind::RemoveLayerMask, |canvas| canvas.remove_layer_mask(index))
+    }
+
+    pub fn toggle_layer_mask_enabled(&mut self, index: usize) -> bool {
+        self.edit_layers(ActionKind::ToggleLayerMask, |canvas| canvas.toggle_layer_mask_enabled(index))
+    }
+
+    pub fn toggle_layer_clipping(&mut self, index: usize) -> bool {
+        self.edit_layers(ActionKind::LayerClipping, |canvas| canvas.toggle_layer_clipping(index))
+    }
+
+    pub fn duplicate_layer(&mut self, index: usize) -> bool {
+        self.edit_layers(ActionKind::DuplicateLayer, |canvas| canvas.duplicate_layer(index))
+    }
+
+    pub fn merge_down(&mut self, index: usize) -> bool {
+        self.edit_layers(ActionKind::MergeDown, |canvas| canvas.merge_down(index))
+    }
+
+    pub fn merge_visible(&mut self) -> bool {
+        self.edit_layers(ActionKind::MergeVisible, |canvas| canvas.merge_visible())
+    }
+
+    pub fn flatten(&mut self) -> bool {
+        self.edit_layers(ActionKind::Flatten, |canvas| canvas.flatten())
+    }
+}
@@ -0,0 +1,44 @@
+//! Headless painting engine of Rustique.
+//!
+//! Layers, brushes, undo history, flood fill and `.rustiq` / image I/O live
+//! here without any dependency on the GUI toolkit, so documents can be built
+//! and edited from scripts, tests or other tools.
+
+pub mod adjustments;
+pub mod brush_system;
+pub mod canvas;
+mod change_set;
+pub mod clipboard;
+pub mod color;
+pub mod compositing;
+mod crop;
+pub mod document;
+pub mod file_format;
+pub mod filters;
+pub mod history;
+pub mod layer;
+mod layer_ops;
+pub mod orientation;
+mod paint;
+pub mod resample;
+pub mod resize;
+pub mod selection;
+pub mod stroke;
+pub mod transform;
+
+pub use adjustments::{Adjustment, AdjustmentKind, Curve, Curves, Histogram};
+pub use brush_system::{BlendMode, BrushManager, BrushPreset, BrushProperties, BrushTip, BrushType, Dab, ImageHose, PresetPack, TipId};
+pub use canvas::{CanvasState, EditTarget};
+pub use clipboard::{ClipboardImage, FloatingSelection};
+pub use color::Color;
+pub use document::Document;
+pub use filters::{Filter, FilterKind, Kernel};
+pub use file_format::{DocumentSettings, FileError, FileFormat, RustiqueFile};
+pub use history::{ActionKind, CoalesceKey, History, LayerSnapshot, DEFAULT_MEMORY_BUDGET};
+pub use orientation::{FlipAxis, QuarterTurn};
+pub use resample::Interpolation;
+pub use resize::Anchor;
+pub use selection::{Selection, SelectionMode};
+pub use stroke::StrokeStyle;
+pub use transform::Affine;
+pub use layer::{Layer, LayerId, LayerKind, LayerLocks, LayerMask};
@@ -0,0 +1,181 @@
+use crate::canvas::CanvasState;
+use crate::color::Color;
+use crate::document::Document;
+use crate::history::ActionKind;
+use crate::resample::{collect_rows, transform_pixels, Interpolation};
+use crate::resize::Anchor;
+use crate::transform::Affine;
+
+/// Clockwise rotation by a multiple of 90 degrees.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum QuarterTurn {
+    Cw90,
+    Cw180,
+    Cw270,
+}
+
+impl QuarterTurn {
+    pub fn all() -> [QuarterTurn; 3] {
+        [QuarterTurn::Cw90, QuarterTurn::Cw180, QuarterTurn::Cw270]
+    }
+
+    /// Size of a `width` by `height` image once turned.
+    fn turned_size(self, width: usize, height: usize) -> (usize, usize) {
+        match self {
+            QuarterTurn::Cw180 => (width, height),
+            _ => (height, width),
+        }
+    }
+
+    /// Pixel of a `width` by `height` image landing at `(x, y)` once turned.
+    fn source(self, width: usize, height: usize, x: usize, y: usize) -> (usize, usize) {
+        match self {
+            QuarterTurn::Cw90 => (y, height - 1 - x),
+            QuarterTurn::Cw180 => (width - 1 - x, height - 1 - y),
+            QuarterTurn::Cw270 => (width - 1 - y, x),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum FlipAxis {
+    /// Mirrors left and right.
+    Horizontal,
+    /// Mirrors top and bottom.
+    Vertical,
+}
+
+/// Turns a `width` by `height` buffer into one of `new_size`, the turned
+/// image centered in it.
+fn turn_pixels(data: &[Option<Color>], width: usize, height: usize, turn: QuarterTurn, new_size: (usize, usize)) -> Vec<Option<Color>> {
+    let (turned_width, turned_height) = turn.turned_size(width, height);
+    let offset_x = (new_size.0 as i64 - turned_width as i64) / 2;
+    let offset_y = (new_size.1 as i64 - turned_height as i64) / 2;
+    collect_rows(new_size.0, new_size.1, |x, y| {
+        let (tx, ty) = (x as i64 - offset_x, y as i64 - offset_y);
+        if tx < 0 || ty < 0 || tx >= turned_width as i64 || ty >= turned_height as i64 {
+            return None;
+        }
+        let (sx, sy) = turn.source(width, height, tx as usize, ty as usize);
+        data[sy * width + sx]
+    })
+}
+
+fn flip_pixels(data: &[Option<Color>], width: usize, height: usize, axis: FlipAxis) -> Vec<Option<Color>> {
+    collect_rows(width, height, |x, y| match axis {
+        FlipAxis::Horizontal => data[y * width + width - 1 - x],
+        FlipAxis::Vertical => data[(height - 1 - y) * width + x],
+    })
+}
+
+/// Rotation by `angle` radians around the center of a `width` by `height`
+/// canvas.
+fn rotation_around_center(width: usize, height: usize, angle: f32) -> Affine {
+    let (cx, cy) = (width as f32 / 2.0, height as f32 / 2.0);
+    Affine::translate(-cx, -cy)
+        .then(&Affine::rotate(angle))
+        .then(&Affine::translate(cx, cy))
+}
+
+/// Rotations and flips of the whole image, which ignore layer locks, or of
+/// a single layer or group, which stays on the canvas it had.
+impl CanvasState {
+    pub fn rotate_image(&mut self, turn: QuarterTurn) {
+        let (width, height) = (self.width, self.height);
+        let new_size = turn.turned_size(width, height);
+        self.map_layers(0..self.layers.len(), false, |data| turn_pixels(data, width, height, turn, new_size));
+        (self.width, self.height) = new_size;
+    }
+
+    pub fn flip_image(&mut self, axis: FlipAxis) {
+        let (width, height) = (self.width, self.height);
+        self.map_layers(0..self.layers.len(), false, |data| flip_pixels(data, width, height, axis));
+    }
+
+    /// Rotates the image by `angle` radians, growing the canvas to hold all
+    /// of it.
+    pub fn rotate_image_by(&mut self, angle: f32, interpolation: Interpolation) {
+        let (sin, cos) = angle.sin_cos();
+        let (width, height) = (self.width as f32, self.height as f32);
+        // Tolerance so that right angles do not gain a pixel from rounding errors.
+        let new_width = ((width * cos.abs() + height * sin.abs() - 0.01).ceil() as usize).max(1);
+        let new_height = ((width * sin.abs() + height * cos.abs() - 0.01).ceil() as usize).max(1);
+        self.resize_canvas(new_width, new_height, Anchor::Center);
+
+        let transform = rotation_around_center(new_width, new_height, angle);
+        self.map_layers(0..self.layers.len(), false, |data| {
+            transform_pixels(data, new_width, new_height, &transform, interpolation)
+        });
+    }
+
+    pub fn rotate_layer(&mut self, index: usize, turn: QuarterTurn) -> bool {
+        if !self.can_transform_layer(index) {
+            return false;
+        }
+        let (width, height) = (self.width, self.height);
+        self.map_layers(self.node_range(index), true, |data| turn_pixels(data, width, height, turn, (width, height)));
+        true
+    }
+
+    pub fn flip_layer(&mut self, index: usize, axis: FlipAxis) -> bool {
+        if !self.can_transform_layer(index) {
+            return false;
+        }
+        let (width, height) = (self.width, self.height);
+        self.map_layers(self.node_range(index), true, |data| flip_pixels(data, width, height, axis));
+        true
+    }
+
+    /// Rotates a layer by `angle` radians around the center of the canvas.
+    pub fn rotate_layer_by(&mut self, index: usize, angle: f32, interpolation: Interpolation) -> bool {
+        let transform = rotation_around_center(self.width, self.height, angle);
+        self.transform_layer(index, &transform, interpolation)
+    }
+}
+
+/// Each a single undoable action. Whole-image changes commit the floating
+/// selection and drop the selection first.
+impl Document {
+    pub fn rotate_image(&mut self, turn: QuarterTurn) -> bool {
+        self.begin_reshape();
+        self.edit_layers(ActionKind::RotateImage, |canvas| {
+            canvas.rotate_image(turn);
+            true
+        })
+    }
+
+    pub fn flip_image(&mut self, axis: FlipAxis) -> bool {
+        self.begin_reshape();
+        self.edit_layers(ActionKind::FlipImage, |canvas| {
+            canvas.flip_image(axis);
+            true
+        })
+    }
+
+    pub fn rotate_image_by(&mut self, angle: f32, interpolation: Interpolation) -> bool {
+        if angle.rem_euclid(std::f32::consts::TAU).abs() < f32::EPSILON {
+            return false;
+        }
+        self.begin_reshape();
+        self.edit_layers(ActionKind::RotateImage, |canvas| {
+            canvas.rotate_image_by(angle, interpolation);
+            true
+        })
+    }
+
+    pub fn rotate_layer(&mut self, index: usize, turn: QuarterTurn) -> bool {
+        self.canvas.can_transform_layer(index)
+            && self.edit_layers(ActionKind::RotateLayer, |canvas| canvas.rotate_layer(index, turn))
+    }
+
+    pub fn flip_layer(&mut self, index: usize, axis: FlipAxis) -> bool {
+        self.canvas.can_transform_layer(index)
+            && self.edit_layers(ActionKind::FlipLayer, |canvas| canvas.flip_layer(index, axis))
+    }
+
+    pub fn rotate_layer_by(&mut self, index: usize, angle: f32, interpolation: Interpolation) -> bool {
+        self.canvas.can_transform_layer(index)
+            && angle.rem_euclid(std::f32::consts::TAU).abs() >= f32::EPSILON
+            && self.edit_layers(ActionKind::RotateLayer, |canvas| canvas.rotate_layer_by(index, angle, interpolation))
+    }
+}
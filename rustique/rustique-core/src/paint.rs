@@ -0,0 +1,228 @@
+use std::collections::VecDeque;
+
+use crate::brush_system::{shared_dab_size, BrushManager, BrushType, Dab};
+use crate::color::Color;
+use crate::document::Document;
+use crate::history::ActionKind;
+use crate::stroke::StrokeStyle;
+
+/// Painting operations. Brush dabs are composited onto the active layer
+/// through the stroke buffer; a `None` color erases instead. Hidden or
+/// pixel-locked layers are left untouched, and so are pixels outside of the
+/// selection.
+impl Document {
+    pub fn draw_point(&mut self, brushes: &mut BrushManager, x: i32, y: i32, color: Option<Color>, pressure: f32) {
+        if !self.canvas.active_layer_editable() {
+            return;
+        }
+
+        let style = StrokeStyle::from_brush(brushes.active_brush(), color);
+        let dab = brushes.dab(x, y, pressure);
+        self.stamp_dab(&dab, &style, 1.0);
+    }
+
+    pub fn draw_line(&mut self, brushes: &mut BrushManager, start: (i32, i32), end: (i32, i32), color: Option<Color>, pressure: f32) {
+        if !self.canvas.active_layer_editable() {
+            return;
+        }
+
+        let style = StrokeStyle::from_brush(brushes.active_brush(), color);
+        for dab in brushes.line_dabs(start, end, pressure) {
+            self.stamp_dab(&dab, &style, 1.0);
+        }
+    }
+
+    /// Sub-pixel stroke: dabs are placed at fractional positions along the
+    /// segment and spread over the four neighbouring pixels.
+    pub fn draw_smooth_line(&mut self, brushes: &mut BrushManager, start: (i32, i32), end: (i32, i32), color: Option<Color>, pressure: f32) {
+        if !self.canvas.active_layer_editable() {
+            return;
+        }
+
+        let (x0, y0) = start;
+        let (x1, y1) = end;
+
+        let dx = (x1 - x0) as f32;
+        let dy = (y1 - y0) as f32;
+        let distance = (dx * dx + dy * dy).sqrt();
+
+        if distance < 1.0 {
+            self.draw_point(brushes, x0, y0, color, pressure);
+            return;
+        }
+
+        let active_brush = brushes.active_brush();
+        let base_spacing = active_brush.spacing * brushes.current_size;
+
+        let spacing = match active_brush.brush_type {
+            BrushType::Flat | BrushType::Bright => {
+                (base_spacing * 0.2).max(0.2)
+            },
+            BrushType::Rigger => {
+                (base_spacing * 0.1).max(0.1)
+            },
+            BrushType::Fan => {
+                (base_spacing * 0.6).max(0.6)
+            },
+            BrushType::Mop => {
+                (base_spacing * 0.3).max(0.3)
+            },
+            _ => {
+                (base_spacing * 0.3).max(0.3)
+            }
+        };
+
+        let num_points = (distance / spacing).ceil() as i32;
+
+        for i in 0..=num_points {
+            let t = if num_points > 0 { i as f32 / num_points as f32 } else { 0.0 };
+
+            let x = x0 as f32 + dx * t;
+            let y = y0 as f32 + dy * t;
+
+            self.draw_smooth_point(brushes, x, y, color);
+        }
+    }
+
+    pub fn draw_smooth_point(&mut self, brushes: &mut BrushManager, x: f32, y: f32, color: Option<Color>) {
+        let x0 = x.floor() as i32;
+        let y0 = y.floor() as i32;
+        let x1 = x0 + 1;
+        let y1 = y0 + 1;
+
+        let wx = x - x.floor();
+        let wy = y - y.floor();
+
+        let weights = [
+            (1.0 - wx) * (1.0 - wy),
+            wx * (1.0 - wy),
+            (1.0 - wx) * wy,
+            wx * wy,
+        ];
+
+        let positions = [(x0, y0), (x1, y0), (x0, y1), (x1, y1)];
+
+        for (&(px, py), &weight) in positions.iter().zip(weights.iter()) {
+            if weight > 0.0 {
+                self.draw_weighted_point(brushes, px, py, color, weight);
+            }
+        }
+    }
+
+    pub fn draw_weighted_point(&mut self, brushes: &mut BrushManager, x: i32, y: i32, color: Option<Color>, weight: f32) {
+        if !self.canvas.active_layer_editable() {
+            return;
+        }
+
+        brushes.update_angle(x as f32, y as f32);
+
+        let base_size = brushes.current_size;
+        let mask_size = match brushes.active_brush().brush_type {
+            BrushType::Rigger => ((base_size * 1.5).max(5.0) as usize) | 1,
+            BrushType::Mop => ((base_size * 2.5).max(9.0) as usize) | 1,
+            _ => ((base_size * 2.0).max(7.0) as usize) | 1,
+        };
+        let mask_size = shared_dab_size(mask_size);
+
+        let dab = Dab {
+            x,
+            y,
+            size: mask_size,
+            mask: brushes.brush_mask(mask_size),
+            opacity: 1.0,
+        };
+        let style = StrokeStyle::from_brush(brushes.active_brush(), color);
+        self.stamp_dab(&dab, &style, weight);
+    }
+
+    /// Flood fills the region of identical pixels under `(x, y)` on the active
+    /// layer. Returns `false` when no pixel changed, e.g. when the layer locks
+    /// kept every one of them.
+    pub fn paint_bucket(&mut self, x: usize, y: usize, fill_color: Option<Color>) -> bool {
+        let width = self.canvas.width;
+        let height = self.canvas.height;
+
+        if x >= width || y >= height || !self.canvas.active_layer_editable() || !self.is_selected(x, y) {
+            return false;
+        }
+
+        let fill_color = self.canvas.active_target().normalize(fill_color);
+        let target_color = self.canvas.get_from_active_layer(x, y);
+        if target_color == fill_color {
+            return false;
+        }
+
+        let mut changed = false;
+        let mut queue = VecDeque::with_capacity(1024);
+        let mut visited = vec![false; width * height];
+        queue.push_back((x, y));
+
+        while let Some((cx, cy)) = queue.pop_front() {
+            let idx = cy * width + cx;
+            if visited[idx] || !self.is_selected(cx, cy) || self.canvas.get_from_active_layer(cx, cy) != target_color {
+                continue;
+            }
+
+            visited[idx] = true;
+            changed |= self.record_change(ActionKind::BucketFill, cx, cy, fill_color);
+
+            if cx > 0 { queue.push_back((cx - 1, cy)); }
+            if cx + 1 < width { queue.push_back((cx + 1, cy)); }
+            if cy > 0 { queue.push_back((cx, cy - 1)); }
+            if cy + 1 < height { queue.push_back((cx, cy + 1)); }
+        }
+
+        changed
+    }
+
+    pub fn pick_color(&self, x: usize, y: usize) -> Option<Color> {
+        self.canvas.get(x, y)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::layer::LayerLocks;
+
+    const RED: Color = Color::from_rgb(255, 0, 0);
+
+    #[test]
+    fn bucket_fills_the_contiguous_region() {
+        let mut document = Document::new(3, 3);
+        for y in 0..3 {
+            document.canvas.set(1, y, Some(Color::BLACK));
+        }
+        assert!(document.paint_bucket(0, 0, Some(RED)));
+        assert_eq!(document.canvas.get(0, 2), Some(RED));
+        assert_eq!(document.canvas.get(2, 0), None);
+        assert!(!document.paint_bucket(0, 0, Some(RED)));
+    }
+
+    #[test]
+    fn bucket_reports_nothing_done_when_locks_keep_every_pixel() {
+        let mut document = Document::new(2, 2);
+        document.canvas.set_layer_locks(0, LayerLocks { alpha: true, ..LayerLocks::default() });
+        assert!(!document.paint_bucket(0, 0, Some(RED)));
+        assert!(!document.history.has_pending_changes());
+    }
+
+    #[test]
+    fn strokes_with_custom_tips_are_remembered() {
+        let mut brushes = BrushManager::new();
+        let tip = crate::brush_system::BrushTip::new("Square".to_string(), 2, 2, vec![255; 4]).unwrap();
+        let id = tip.id();
+        brushes.active_brush_index = brushes.add_tip(tip);
+        brushes.current_size = 4.0;
+
+        let mut document = Document::new(16, 16);
+        document.draw_point(&mut brushes, 8, 8, Some(RED), 1.0);
+        assert!(document.used_tips().contains(&id));
+        document.save_state();
+
+        brushes.active_brush_index = 0;
+        document.draw_point(&mut brushes, 2, 2, Some(RED), 1.0);
+        document.save_state();
+        assert_eq!(document.used_tips().into_iter().collect::<Vec<_>>(), [id]);
+    }
+}
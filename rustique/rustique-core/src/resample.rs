@@ -0,0 +1,287 @@
+use rayon::prelude::*;
+use serde::{Serialize, Deserialize};
+
+use crate::color::Color;
+use crate::transform::Affine;
+
+/// How pixel values are computed between source pixel centers.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, Serialize, Deserialize)]
+pub enum Interpolation {
+    /// Keeps hard pixel edges, for pixel art.
+    Nearest,
+    #[default]
+    Bilinear,
+    Bicubic,
+    /// Sharpest, for photographs.
+    Lanczos,
+}
+
+impl Interpolation {
+    pub fn all() -> [Interpolation; 4] {
+        [Interpolation::Nearest, Interpolation::Bilinear, Interpolation::Bicubic, Interpolation::Lanczos]
+    }
+
+    /// Half width of the filter, in source pixels.
+    fn radius(self) -> f32 {
+        match self {
+            Interpolation::Nearest => 0.5,
+            Interpolation::Bilinear => 1.0,
+            Interpolation::Bicubic => 2.0,
+            Interpolation::Lanczos => 3.0,
+        }
+    }
+
+    fn weight(self, t: f32) -> f32 {
+        let t = t.abs();
+        match self {
+            Interpolation::Nearest => if t < 0.5 { 1.0 } else { 0.0 },
+            Interpolation::Bilinear => (1.0 - t).max(0.0),
+            // Catmull-Rom spline.
+            Interpolation::Bicubic => {
+                if t < 1.0 {
+                    1.5 * t * t * t - 2.5 * t * t + 1.0
+                } else if t < 2.0 {
+                    -0.5 * t * t * t + 2.5 * t * t - 4.0 * t + 2.0
+                } else {
+                    0.0
+                }
+            },
+            Interpolation::Lanczos => {
+                if t < 3.0 { sinc(t) * sinc(t / 3.0) } else { 0.0 }
+            },
+        }
+    }
+}
+
+fn sinc(t: f32) -> f32 {
+    if t == 0.0 {
+        return 1.0;
+    }
+    let x = std::f32::consts::PI * t;
+    x.sin() / x
+}
+
+/// Pixels as premultiplied floats, so that transparent pixels do not bleed
+/// their color into their neighbours when filtered.
+struct Premultiplied {
+    width: usize,
+    height: usize,
+    pixels: Vec<[f32; 4]>,
+}
+
+impl Premultiplied {
+    fn new(data: &[Option<Color>], width: usize, height: usize) -> Self {
+        Self { width, height, pixels: premultiply(data) }
+    }
+
+    /// Filtered value around `(x, y)`, in pixel coordinates where pixel
+    /// centers lie at half units. Outside of the image is transparent.
+    fn sample(&self, x: f32, y: f32, interpolation: Interpolation) -> Option<Color> {
+        if interpolation == Interpolation::Nearest {
+            if x < 0.0 || y < 0.0 {
+                return None;
+            }
+            let (px, py) = (x as usize, y as usize);
+            if px >= self.width || py >= self.height {
+                return None;
+            }
+            return to_color(self.pixels[py * self.width + px]);
+        }
+
+        let radius = interpolation.radius();
+        let (cx, cy) = (x - 0.5, y - 0.5);
+        let (x0, x1) = ((cx - radius).ceil() as i64, (cx + radius).floor() as i64);
+        let (y0, y1) = ((cy - radius).ceil() as i64, (cy + radius).floor() as i64);
+        if x1 < 0 || y1 < 0 || x0 >= self.width as i64 || y0 >= self.height as i64 {
+            return None;
+        }
+
+        let mut sum = [0.0_f32; 4];
+        for sy in y0..=y1 {
+            let wy = interpolation.weight(sy as f32 - cy);
+            if wy == 0.0 || sy < 0 || sy >= self.height as i64 {
+                continue;
+            }
+            for sx in x0..=x1 {
+                let wx = interpolation.weight(sx as f32 - cx);
+                if wx == 0.0 || sx < 0 || sx >= self.width as i64 {
+                    continue;
+                }
+                let pixel = self.pixels[sy as usize * self.width + sx as usize];
+                let weight = wx * wy;
+                for (total, value) in sum.iter_mut().zip(pixel) {
+                    *total += value * weight;
+                }
+            }
+        }
+        to_color(sum)
+    }
+}
+
+/// Colors scaled by their alpha, all channels from 0 to 255.
+pub(crate) fn premultiply(data: &[Option<Color>]) -> Vec<[f32; 4]> {
+    data.par_iter()
+        .map(|pixel| {
+            let color = pixel.unwrap_or(Color::TRANSPARENT);
+            let alpha = color.a() as f32 / 255.0;
+            [color.r() as f32 * alpha, color.g() as f32 * alpha, color.b() as f32 * alpha, color.a() as f32]
+        })
+        .collect()
+}
+
+pub(crate) fn to_color(premultiplied: [f32; 4]) -> Option<Color> {
+    let alpha = premultiplied[3].clamp(0.0, 255.0);
+    if alpha < 0.5 {
+        return None;
+    }
+    let channel = |value: f32| (value * 255.0 / alpha).round().clamp(0.0, 255.0) as u8;
+    Some(Color::from_rgba_unmultiplied(
+        channel(premultiplied[0]),
+        channel(premultiplied[1]),
+        channel(premultiplied[2]),
+        alpha.round() as u8,
+    ))
+}
+
+/// Builds a `width` by `height` buffer from `pixel(x, y)`, computing the rows
+/// in parallel.
+pub(crate) fn collect_rows<T: Send>(width: usize, height: usize, pixel: impl Fn(usize, usize) -> T + Sync) -> Vec<T> {
+    let pixel = &pixel;
+    (0..height)
+        .into_par_iter()
+        .flat_map_iter(|y| (0..width).map(move |x| pixel(x, y)))
+        .collect()
+}
+
+/// Renders `data` moved by `transform` into a buffer of the same size.
+/// Every destination pixel samples the source at its transformed-back center.
+pub fn transform_pixels(data: &[Option<Color>], width: usize, height: usize, transform: &Affine, interpolation: Interpolation) -> Vec<Option<Color>> {
+    let source = Premultiplied::new(data, width, height);
+    let Some(inverse) = transform.inverse() else {
+        return vec![None; width * height];
+    };
+
+    collect_rows(width, height, |x, y| {
+        let (sx, sy) = inverse.apply(x as f32 + 0.5, y as f32 + 0.5);
+        source.sample(sx, sy, interpolation)
+    })
+}
+
+/// Source pixels contributing to each of `new_length` destination pixels
+/// along one axis, as a first index and its weights. The filter widens when
+/// shrinking so every source pixel counts.
+fn axis_weights(length: usize, new_length: usize, interpolation: Interpolation) -> Vec<(usize, Vec<f32>)> {
+    let ratio = length as f32 / new_length as f32;
+    let filter_scale = ratio.max(1.0);
+    let support = interpolation.radius() * filter_scale;
+
+    (0..new_length)
+        .map(|i| {
+            let center = (i as f32 + 0.5) * ratio - 0.5;
+            let first = ((center - support).ceil() as i64).max(0) as usize;
+            let last = ((center + support).floor() as i64).clamp(0, length as i64 - 1) as usize;
+            let mut weights: Vec<f32> = (first..=last.max(first))
+                .map(|j| interpolation.weight((j as f32 - center) / filter_scale))
+                .collect();
+            let total: f32 = weights.iter().sum();
+            if total.abs() > f32::EPSILON {
+                weights.iter_mut().for_each(|weight| *weight /= total);
+            } else {
+                weights = vec![1.0];
+            }
+            (first.min(length - 1), weights)
+        })
+        .collect()
+}
+
+/// Scales `data` to `new_width` by `new_height` pixels.
+pub fn resize_pixels(data: &[Option<Color>], width: usize, height: usize, new_width: usize, new_height: usize, interpolation: Interpolation) -> Vec<Option<Color>> {
+    if width == 0 || height == 0 {
+        return vec![None; new_width * new_height];
+    }
+    if interpolation == Interpolation::Nearest {
+        let source_index = |i: usize, length: usize, new_length: usize| ((i * 2 + 1) * length / (new_length * 2)).min(length - 1);
+        return collect_rows(new_width, new_height, |x, y| {
+            data[source_index(y, height, new_height) * width + source_index(x, width, new_width)]
+        });
+    }
+
+    let source = Premultiplied::new(data, width, height);
+    let blend = |pixels: &mut dyn Iterator<Item = [f32; 4]>, weights: &[f32]| {
+        let mut sum = [0.0_f32; 4];
+        for (pixel, weight) in pixels.zip(weights) {
+            for (total, value) in sum.iter_mut().zip(pixel) {
+                *total += value * weight;
+            }
+        }
+        sum
+    };
+
+    let columns = axis_weights(width, new_width, interpolation);
+    let horizontal = collect_rows(new_width, height, |x, y| {
+        let (first, weights) = &columns[x];
+        blend(&mut source.pixels[y * width + first..(y + 1) * width].iter().copied(), weights)
+    });
+
+    let rows = axis_weights(height, new_height, interpolation);
+    collect_rows(new_width, new_height, |x, y| {
+        let (first, weights) = &rows[y];
+        to_color(blend(&mut horizontal[first * new_width + x..].iter().step_by(new_width).copied(), weights))
+    })
+}
+
+/// Runs a pixel operation on mask values, seen as opaque grays. Pixels it
+/// leaves empty are fully shown.
+pub(crate) fn map_mask(mask: &[u8], operation: impl FnOnce(&[Option<Color>]) -> Vec<Option<Color>>) -> Vec<u8> {
+    let gray: Vec<Option<Color>> = mask.iter().map(|&value| Some(Color::from_gray(value))).collect();
+    operation(&gray)
+        .into_iter()
+        .map(|pixel| pixel.map_or(255, |color| color.r()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A 5 by 4 pattern of opaque colors with a few empty pixels.
+    fn pattern() -> Vec<Option<Color>> {
+        (0..20u8)
+            .map(|i| (i % 7 != 3).then(|| Color::from_rgb(i * 12, 255 - i * 9, i.wrapping_mul(37))))
+            .collect()
+    }
+
+    #[test]
+    fn resizing_to_the_same_size_keeps_the_pixels() {
+        let data = pattern();
+        for interpolation in Interpolation::all() {
+            assert_eq!(resize_pixels(&data, 5, 4, 5, 4, interpolation), data, "{:?}", interpolation);
+        }
+    }
+
+    #[test]
+    fn identity_transform_keeps_the_pixels() {
+        let data = pattern();
+        for interpolation in Interpolation::all() {
+            assert_eq!(transform_pixels(&data, 5, 4, &Affine::IDENTITY, interpolation), data, "{:?}", interpolation);
+        }
+    }
+
+    #[test]
+    fn nearest_doubling_repeats_each_pixel() {
+        let data = vec![Some(Color::BLACK), Some(Color::WHITE)];
+        let doubled = resize_pixels(&data, 2, 1, 4, 2, Interpolation::Nearest);
+        let row = [Some(Color::BLACK), Some(Color::BLACK), Some(Color::WHITE), Some(Color::WHITE)];
+        assert_eq!(doubled, [row, row].concat());
+    }
+
+    #[test]
+    fn translations_by_whole_pixels_shift_the_image() {
+        let data = pattern();
+        let moved = transform_pixels(&data, 5, 4, &Affine::translate(1.0, 0.0), Interpolation::Bilinear);
+        for y in 0..4 {
+            assert_eq!(moved[y * 5], None);
+            assert_eq!(&moved[y * 5 + 1..y * 5 + 5], &data[y * 5..y * 5 + 4]);
+        }
+    }
+}
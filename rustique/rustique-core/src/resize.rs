@@ -0,0 +1,118 @@
+use std::sync::Arc;
+
+use crate::canvas::CanvasState;
+use crate::document::Document;
+use crate::history::ActionKind;
+use crate::resample::{resize_pixels, Interpolation};
+
+/// Where the existing content stays when the canvas is resized.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum Anchor {
+    TopLeft,
+    Top,
+    TopRight,
+    Left,
+    #[default]
+    Center,
+    Right,
+    BottomLeft,
+    Bottom,
+    BottomRight,
+}
+
+impl Anchor {
+    /// All anchors, row by row as they are laid out on the canvas.
+    pub fn all() -> [Anchor; 9] {
+        [
+            Anchor::TopLeft, Anchor::Top, Anchor::TopRight,
+            Anchor::Left, Anchor::Center, Anchor::Right,
+            Anchor::BottomLeft, Anchor::Bottom, Anchor::BottomRight,
+        ]
+    }
+
+    /// Position of the old content in the resized canvas.
+    fn offset(self, width: usize, height: usize, new_width: usize, new_height: usize) -> (i64, i64) {
+        let index = Anchor::all().iter().position(|&anchor| anchor == self).unwrap_or(4) as i64;
+        let (column, row) = (index % 3, index / 3);
+        (
+            (new_width as i64 - width as i64) * column / 2,
+            (new_height as i64 - height as i64) * row / 2,
+        )
+    }
+}
+
+/// Copies `data` into a buffer of `new_size`, offset by `(dx, dy)`.
+fn moved<T: Copy>(data: &[T], (width, height): (usize, usize), (new_width, new_height): (usize, usize), (dx, dy): (i64, i64), empty: T) -> Vec<T> {
+    let mut moved = vec![empty; new_width * new_height];
+    for y in 0..height {
+        let new_y = y as i64 + dy;
+        if new_y < 0 || new_y >= new_height as i64 {
+            continue;
+        }
+        for x in 0..width {
+            let new_x = x as i64 + dx;
+            if new_x >= 0 && new_x < new_width as i64 {
+                moved[new_y as usize * new_width + new_x as usize] = data[y * width + x];
+            }
+        }
+    }
+    moved
+}
+
+impl CanvasState {
+    /// Changes the canvas size, moving the content of every layer by
+    /// `(dx, dy)`. Uncovered areas are transparent and fully shown by masks.
+    pub fn reframe(&mut self, new_width: usize, new_height: usize, dx: i64, dy: i64) {
+        let (size, new_size) = ((self.width, self.height), (new_width, new_height));
+        for layer in &mut self.layers {
+            if !layer.is_group() {
+                layer.data = Arc::new(moved(&layer.data, size, new_size, (dx, dy), None));
+            }
+            if let Some(mask) = &mut layer.mask {
+                mask.data = moved(&mask.data, size, new_size, (dx, dy), 255);
+            }
+        }
+        self.width = new_width;
+        self.height = new_height;
+    }
+
+    pub fn resize_canvas(&mut self, new_width: usize, new_height: usize, anchor: Anchor) {
+        let (dx, dy) = anchor.offset(self.width, self.height, new_width, new_height);
+        self.reframe(new_width, new_height, dx, dy);
+    }
+
+    /// Scales every layer and mask to the new size.
+    pub fn resize_image(&mut self, new_width: usize, new_height: usize, interpolation: Interpolation) {
+        let (width, height) = (self.width, self.height);
+        self.map_layers(0..self.layers.len(), false, |data| resize_pixels(data, width, height, new_width, new_height, interpolation));
+        self.width = new_width;
+        self.height = new_height;
+    }
+}
+
+/// Size changes, each a single undoable action.
+impl Document {
+    fn prepare_resize(&mut self, new_width: usize, new_height: usize) -> bool {
+        if new_width == 0 || new_height == 0 || (new_width, new_height) == (self.width(), self.height()) {
+            return false;
+        }
+        self.begin_reshape();
+        true
+    }
+
+    pub fn resize_canvas(&mut self, new_width: usize, new_height: usize, anchor: Anchor) -> bool {
+        self.prepare_resize(new_width, new_height)
+            && self.edit_layers(ActionKind::CanvasSize, |canvas| {
+                canvas.resize_canvas(new_width, new_height, anchor);
+                true
+            })
+    }
+
+    pub fn resize_image(&mut self, new_width: usize, new_height: usize, interpolation: Interpolation) -> bool {
+        self.prepare_resize(new_width, new_height)
+            && self.edit_layers(ActionKind::ImageSize, |canvas| {
+                canvas.resize_image(new_width, new_height, interpolation);
+                true
+            })
+    }
+}
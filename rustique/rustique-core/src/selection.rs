@@ -0,0 +1,342 @@
+use std::collections::VecDeque;
+
+use serde::{Serialize, Deserialize};
+
+use crate::canvas::CanvasState;
+use crate::color::Color;
+use crate::document::Document;
+
+/// How a new selection shape is combined with the current selection.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, Serialize, Deserialize)]
+pub enum SelectionMode {
+    #[default]
+    Replace,
+    Add,
+    Subtract,
+    Intersect,
+}
+
+impl SelectionMode {
+    pub fn all() -> [SelectionMode; 4] {
+        [SelectionMode::Replace, SelectionMode::Add, SelectionMode::Subtract, SelectionMode::Intersect]
+    }
+}
+
+/// Pixels of the canvas editing tools are allowed to change. A pixel is
+/// selected when its center lies inside the selected shapes.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Selection {
+    pub width: usize,
+    pub height: usize,
+    mask: Vec<bool>,
+}
+
+impl Selection {
+    pub fn empty(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            mask: vec![false; width * height],
+        }
+    }
+
+    pub fn all(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            mask: vec![true; width * height],
+        }
+    }
+
+    /// Rectangle spanned by two corners, in canvas coordinates.
+    pub fn rectangle(width: usize, height: usize, a: (f32, f32), b: (f32, f32)) -> Self {
+        let (min_x, max_x) = (a.0.min(b.0), a.0.max(b.0));
+        let (min_y, max_y) = (a.1.min(b.1), a.1.max(b.1));
+        Self::from_fn(width, height, |x, y| x >= min_x && x <= max_x && y >= min_y && y <= max_y)
+    }
+
+    /// Ellipse inscribed in the rectangle spanned by two corners.
+    pub fn ellipse(width: usize, height: usize, a: (f32, f32), b: (f32, f32)) -> Self {
+        let center = ((a.0 + b.0) / 2.0, (a.1 + b.1) / 2.0);
+        let radius = ((a.0 - b.0).abs() / 2.0, (a.1 - b.1).abs() / 2.0);
+        if radius.0 <= 0.0 || radius.1 <= 0.0 {
+            return Self::empty(width, height);
+        }
+        Self::from_fn(width, height, |x, y| {
+            let dx = (x - center.0) / radius.0;
+            let dy = (y - center.1) / radius.1;
+            dx * dx + dy * dy <= 1.0
+        })
+    }
+
+    /// Closed polygon, used by the freehand and polygonal lassos. Pixels are
+    /// inside according to the even-odd rule.
+    pub fn polygon(width: usize, height: usize, points: &[(f32, f32)]) -> Self {
+        let mut selection = Self::empty(width, height);
+        if points.len() < 3 {
+            return selection;
+        }
+
+        let mut crossings = Vec::new();
+        for y in 0..height {
+            let center_y = y as f32 + 0.5;
+            crossings.clear();
+            for (i, &(x0, y0)) in points.iter().enumerate() {
+                let (x1, y1) = points[(i + 1) % points.len()];
+                if (y0 <= center_y) != (y1 <= center_y) {
+                    crossings.push(x0 + (center_y - y0) / (y1 - y0) * (x1 - x0));
+                }
+            }
+            crossings.sort_by(|a, b| a.total_cmp(b));
+
+            for span in crossings.chunks_exact(2) {
+                let start = (span[0] - 0.5).ceil().max(0.0) as usize;
+                let end = ((span[1] - 0.5).floor() + 1.0).clamp(0.0, width as f32) as usize;
+                for x in start..end {
+                    selection.mask[y * width + x] = true;
+                }
+            }
+        }
+        selection
+    }
+
+    /// Contiguous pixels of the active layer around `(x, y)` whose channels
+    /// all differ from the clicked one by at most `tolerance`.
+    pub fn magic_wand(canvas: &CanvasState, x: usize, y: usize, tolerance: u8) -> Self {
+        let (width, height) = (canvas.width, canvas.height);
+        let mut selection = Self::empty(width, height);
+        if x >= width || y >= height {
+            return selection;
+        }
+
+        let reference = canvas.get_from_active_layer(x, y).unwrap_or(Color::TRANSPARENT);
+        let matches = |color: Option<Color>| {
+            let color = color.unwrap_or(Color::TRANSPARENT);
+            color.to_array().iter().zip(reference.to_array()).all(|(a, b)| a.abs_diff(b) <= tolerance)
+        };
+
+        let mut queue = VecDeque::from([(x, y)]);
+        while let Some((cx, cy)) = queue.pop_front() {
+            let idx = cy * width + cx;
+            if selection.mask[idx] || !matches(canvas.get_from_active_layer(cx, cy)) {
+                continue;
+            }
+            selection.mask[idx] = true;
+
+            if cx > 0 { queue.push_back((cx - 1, cy)); }
+            if cx + 1 < width { queue.push_back((cx + 1, cy)); }
+            if cy > 0 { queue.push_back((cx, cy - 1)); }
+            if cy + 1 < height { queue.push_back((cx, cy + 1)); }
+        }
+        selection
+    }
+
+    fn from_fn(width: usize, height: usize, inside: impl Fn(f32, f32) -> bool) -> Self {
+        let mut mask = Vec::with_capacity(width * height);
+        for y in 0..height {
+            for x in 0..width {
+                mask.push(inside(x as f32 + 0.5, y as f32 + 0.5));
+            }
+        }
+        Self { width, height, mask }
+    }
+
+    pub fn contains(&self, x: usize, y: usize) -> bool {
+        x < self.width && y < self.height && self.mask[y * self.width + x]
+    }
+
+    pub fn is_empty(&self) -> bool {
+        !self.mask.contains(&true)
+    }
+
+    /// Smallest rectangle holding the selected pixels, as `(x, y, width,
+    /// height)`.
+    pub fn bounds(&self) -> Option<(usize, usize, usize, usize)> {
+        let mut min = (usize::MAX, usize::MAX);
+        let mut max = (0, 0);
+        for (idx, _) in self.mask.iter().enumerate().filter(|(_, selected)| **selected) {
+            let (x, y) = (idx % self.width, idx / self.width);
+            min = (min.0.min(x), min.1.min(y));
+            max = (max.0.max(x), max.1.max(y));
+        }
+        (min.0 != usize::MAX).then(|| (min.0, min.1, max.0 - min.0 + 1, max.1 - min.1 + 1))
+    }
+
+    /// Merges `other` into the selection according to `mode`.
+    pub fn combine(&mut self, other: &Selection, mode: SelectionMode) {
+        for (selected, &other) in self.mask.iter_mut().zip(&other.mask) {
+            *selected = match mode {
+                SelectionMode::Replace => other,
+                SelectionMode::Add => *selected || other,
+                SelectionMode::Subtract => *selected && !other,
+                SelectionMode::Intersect => *selected && other,
+            };
+        }
+    }
+
+    pub fn invert(&mut self) {
+        for selected in &mut self.mask {
+            *selected = !*selected;
+        }
+    }
+
+    /// Border between selected and unselected pixels, as horizontal and
+    /// vertical segments between pixel corners.
+    pub fn outline(&self) -> Vec<[(usize, usize); 2]> {
+        let mut segments = Vec::new();
+
+        // Horizontal edges above each row, then vertical edges left of each column.
+        for y in 0..=self.height {
+            let mut start = None;
+            for x in 0..=self.width {
+                let edge = x < self.width && self.contains_signed(x, y as isize - 1) != self.contains_signed(x, y as isize);
+                match (edge, start) {
+                    (true, None) => start = Some(x),
+                    (false, Some(x0)) => {
+                        segments.push([(x0, y), (x, y)]);
+                        start = None;
+                    },
+                    _ => {},
+                }
+            }
+        }
+        for x in 0..=self.width {
+            let mut start = None;
+            for y in 0..=self.height {
+                let edge = y < self.height && self.contains_signed(x as isize - 1, y) != self.contains_signed(x as isize, y);
+                match (edge, start) {
+                    (true, None) => start = Some(y),
+                    (false, Some(y0)) => {
+                        segments.push([(x, y0), (x, y)]);
+                        start = None;
+                    },
+                    _ => {},
+                }
+            }
+        }
+        segments
+    }
+
+    fn contains_signed(&self, x: impl TryInto<usize>, y: impl TryInto<usize>) -> bool {
+        match (x.try_into(), y.try_into()) {
+            (Ok(x), Ok(y)) => self.contains(x, y),
+            _ => false,
+        }
+    }
+}
+
+/// Selection commands. Without a selection the whole canvas is editable.
+impl Document {
+    /// Combines `shape` with the current selection. An empty result removes
+    /// the selection.
+    pub fn select(&mut self, shape: Selection, mode: SelectionMode) {
+        let selection = match (self.selection.take(), mode) {
+            (None, SelectionMode::Subtract) => None,
+            (None, _) | (Some(_), SelectionMode::Replace) => Some(shape),
+            (Some(mut selection), mode) => {
+                selection.combine(&shape, mode);
+                Some(selection)
+            },
+        };
+        self.selection = selection.filter(|selection| !selection.is_empty());
+    }
+
+    pub fn select_all(&mut self) {
+        self.selection = Some(Selection::all(self.canvas.width, self.canvas.height));
+    }
+
+    pub fn deselect(&mut self) {
+        self.selection = None;
+    }
+
+    pub fn invert_selection(&mut self) {
+        let selection = match self.selection.take() {
+            Some(mut selection) => {
+                selection.invert();
+                Some(selection)
+            },
+            None => None,
+        };
+        self.selection = selection.filter(|selection| !selection.is_empty());
+    }
+
+    /// Whether editing tools may change the pixel at `(x, y)`.
+    pub fn is_selected(&self, x: usize, y: usize) -> bool {
+        self.selection.as_ref().is_none_or(|selection| selection.contains(x, y))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn selected(selection: &Selection) -> Vec<(usize, usize)> {
+        (0..selection.height)
+            .flat_map(|y| (0..selection.width).map(move |x| (x, y)))
+            .filter(|&(x, y)| selection.contains(x, y))
+            .collect()
+    }
+
+    #[test]
+    fn rectangles_select_the_pixels_whose_center_they_cover() {
+        let selection = Selection::rectangle(4, 4, (3.0, 2.0), (1.0, 0.0));
+        assert_eq!(selected(&selection), [(1, 0), (2, 0), (1, 1), (2, 1)]);
+        assert_eq!(selection.bounds(), Some((1, 0, 2, 2)));
+    }
+
+    #[test]
+    fn modes_combine_with_the_current_selection() {
+        let left = Selection::rectangle(4, 1, (0.0, 0.0), (2.0, 1.0));
+        let right = Selection::rectangle(4, 1, (1.0, 0.0), (3.0, 1.0));
+        let combined = |mode| {
+            let mut selection = left.clone();
+            selection.combine(&right, mode);
+            selected(&selection)
+        };
+        assert_eq!(combined(SelectionMode::Replace), [(1, 0), (2, 0)]);
+        assert_eq!(combined(SelectionMode::Add), [(0, 0), (1, 0), (2, 0)]);
+        assert_eq!(combined(SelectionMode::Subtract), [(0, 0)]);
+        assert_eq!(combined(SelectionMode::Intersect), [(1, 0)]);
+    }
+
+    #[test]
+    fn polygons_fill_by_the_even_odd_rule() {
+        let triangle = Selection::polygon(4, 4, &[(0.0, 0.0), (4.0, 0.0), (0.0, 4.0)]);
+        assert_eq!(selected(&triangle), [(0, 0), (1, 0), (2, 0), (3, 0), (0, 1), (1, 1), (2, 1), (0, 2), (1, 2), (0, 3)]);
+
+        // A square with a square hole, drawn as a single outline.
+        let frame = Selection::polygon(6, 6, &[
+            (0.0, 0.0), (6.0, 0.0), (6.0, 6.0), (0.0, 6.0), (0.0, 0.0),
+            (2.0, 2.0), (4.0, 2.0), (4.0, 4.0), (2.0, 4.0), (2.0, 2.0),
+        ]);
+        assert!(!frame.contains(2, 2) && !frame.contains(3, 3));
+        assert!(frame.contains(1, 3) && frame.contains(4, 3) && frame.contains(3, 0));
+
+        assert!(Selection::polygon(4, 4, &[(0.0, 0.0), (4.0, 4.0)]).is_empty());
+    }
+
+    #[test]
+    fn document_selections_limit_editing() {
+        let mut document = Document::new(3, 1);
+        assert!(document.is_selected(2, 0));
+
+        document.select(Selection::rectangle(3, 1, (0.0, 0.0), (1.0, 1.0)), SelectionMode::Replace);
+        assert!(document.is_selected(0, 0) && !document.is_selected(2, 0));
+        document.select(Selection::rectangle(3, 1, (0.0, 0.0), (1.0, 1.0)), SelectionMode::Subtract);
+        assert!(document.selection.is_none());
+
+        document.select(Selection::rectangle(3, 1, (2.0, 0.0), (3.0, 1.0)), SelectionMode::Add);
+        document.invert_selection();
+        assert_eq!(document.selection.as_ref().and_then(Selection::bounds), Some((0, 0, 2, 1)));
+    }
+
+    #[test]
+    fn magic_wand_stops_at_different_colors() {
+        let mut canvas = CanvasState::new(3, 1);
+        canvas.set(1, 0, Some(Color::BLACK));
+        let selection = Selection::magic_wand(&canvas, 0, 0, 0);
+        assert_eq!(selected(&selection), [(0, 0)]);
+        let selection = Selection::magic_wand(&canvas, 0, 0, 255);
+        assert_eq!(selected(&selection).len(), 3);
+    }
+}
@@ -0,0 +1,104 @@
+use std::collections::HashMap;
+
+use crate::brush_system::{BrushProperties, BrushType};
+use crate::color::Color;
+use crate::compositing::{blend, BlendMode};
+use crate::history::ActionKind;
+
+/// What a stroke lays down. A `None` color erases instead of painting.
+#[derive(Clone, Copy, Debug)]
+pub struct StrokeStyle {
+    pub color: Option<Color>,
+    pub opacity: f32,
+    pub blend_mode: BlendMode,
+    pub brush_type: BrushType,
+}
+
+impl StrokeStyle {
+    pub fn from_brush(brush: &BrushProperties, color: Option<Color>) -> Self {
+        Self {
+            color,
+            opacity: brush.opacity,
+            blend_mode: brush.blend_mode,
+            brush_type: brush.brush_type,
+        }
+    }
+
+    /// How strokes of this style are named in the history.
+    pub fn action(&self) -> ActionKind {
+        match self.color {
+            Some(_) => ActionKind::BrushStroke(self.brush_type),
+            None => ActionKind::Erase,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub(crate) struct StrokePixel {
+    original: Option<Color>,
+    coverage: f32,
+}
+
+/// Coverage accumulated by the stroke in progress. Every pixel remembers what
+/// the layer held before the stroke started, so overlapping dabs build up
+/// towards the stroke opacity but never past it.
+#[derive(Default)]
+pub struct StrokeBuffer {
+    pixels: HashMap<usize, StrokePixel>,
+}
+
+impl StrokeBuffer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pixels.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.pixels.clear();
+    }
+
+    /// Adds a dab of strength `dab_alpha` at `idx` and returns the new pixel
+    /// value. `current` is only read the first time the stroke touches the
+    /// pixel.
+    pub fn apply_dab(&mut self, idx: usize, current: Option<Color>, style: &StrokeStyle, dab_alpha: f32) -> Option<Color> {
+        let (pixel, result) = self.dab_result(idx, current, style, dab_alpha);
+        self.store(idx, pixel);
+        result
+    }
+
+    /// What [`StrokeBuffer::apply_dab`] would store and return, leaving the
+    /// buffer untouched so that many pixels can be worked out at once.
+    pub(crate) fn dab_result(&self, idx: usize, current: Option<Color>, style: &StrokeStyle, dab_alpha: f32) -> (StrokePixel, Option<Color>) {
+        let mut pixel = self.pixels.get(&idx).copied().unwrap_or(StrokePixel {
+            original: current,
+            coverage: 0.0,
+        });
+        pixel.coverage += dab_alpha.clamp(0.0, 1.0) * (1.0 - pixel.coverage);
+        let strength = style.opacity.clamp(0.0, 1.0) * pixel.coverage;
+
+        let result = match style.color {
+            Some(color) => {
+                let src = color.with_alpha((color.a() as f32 * strength).round() as u8);
+                Some(blend(src, pixel.original.unwrap_or(Color::TRANSPARENT), style.blend_mode))
+            },
+            None => pixel.original.map(|original| {
+                original.with_alpha((original.a() as f32 * (1.0 - strength)).round() as u8)
+            }),
+        };
+
+        (pixel, result.filter(|color| color.a() > 0))
+    }
+
+    /// Whether `pixel` is already what the buffer holds at `idx`, as when
+    /// the stroke fully covered it before.
+    pub(crate) fn holds(&self, idx: usize, pixel: &StrokePixel) -> bool {
+        self.pixels.get(&idx) == Some(pixel)
+    }
+
+    pub(crate) fn store(&mut self, idx: usize, pixel: StrokePixel) {
+        self.pixels.insert(idx, pixel);
+    }
+}
@@ -0,0 +1,154 @@
+use std::ops::Range;
+use std::sync::Arc;
+
+use crate::canvas::CanvasState;
+use crate::color::Color;
+use crate::document::Document;
+use crate::history::ActionKind;
+use crate::resample::{map_mask, transform_pixels, Interpolation};
+
+/// 2D affine map `(x, y) -> (a x + c y + tx, b x + d y + ty)`.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Affine {
+    pub a: f32,
+    pub b: f32,
+    pub c: f32,
+    pub d: f32,
+    pub tx: f32,
+    pub ty: f32,
+}
+
+impl Default for Affine {
+    fn default() -> Self {
+        Self::IDENTITY
+    }
+}
+
+impl Affine {
+    pub const IDENTITY: Affine = Affine { a: 1.0, b: 0.0, c: 0.0, d: 1.0, tx: 0.0, ty: 0.0 };
+
+    pub fn translate(tx: f32, ty: f32) -> Self {
+        Self { tx, ty, ..Self::IDENTITY }
+    }
+
+    pub fn scale(sx: f32, sy: f32) -> Self {
+        Self { a: sx, d: sy, ..Self::IDENTITY }
+    }
+
+    /// Rotation by `angle` radians, clockwise on screen since y points down.
+    pub fn rotate(angle: f32) -> Self {
+        let (sin, cos) = angle.sin_cos();
+        Self { a: cos, b: sin, c: -sin, d: cos, ..Self::IDENTITY }
+    }
+
+    /// Shear moving x by `kx` times y and y by `ky` times x.
+    pub fn skew(kx: f32, ky: f32) -> Self {
+        Self { c: kx, b: ky, ..Self::IDENTITY }
+    }
+
+    /// The transform applying `self` first, then `next`.
+    pub fn then(&self, next: &Affine) -> Affine {
+        Affine {
+            a: next.a * self.a + next.c * self.b,
+            b: next.b * self.a + next.d * self.b,
+            c: next.a * self.c + next.c * self.d,
+            d: next.b * self.c + next.d * self.d,
+            tx: next.a * self.tx + next.c * self.ty + next.tx,
+            ty: next.b * self.tx + next.d * self.ty + next.ty,
+        }
+    }
+
+    pub fn inverse(&self) -> Option<Affine> {
+        let det = self.a * self.d - self.b * self.c;
+        if det.abs() < f32::EPSILON {
+            return None;
+        }
+        let (a, b, c, d) = (self.d / det, -self.b / det, -self.c / det, self.a / det);
+        Some(Affine {
+            a,
+            b,
+            c,
+            d,
+            tx: -(a * self.tx + c * self.ty),
+            ty: -(b * self.tx + d * self.ty),
+        })
+    }
+
+    pub fn apply(&self, x: f32, y: f32) -> (f32, f32) {
+        (self.a * x + self.c * y + self.tx, self.b * x + self.d * y + self.ty)
+    }
+}
+
+impl CanvasState {
+    /// Smallest rectangle holding the non-empty pixels of a layer, or of all
+    /// the layers of a group, as `(x, y, width, height)`.
+    pub fn content_bounds(&self, index: usize) -> Option<(usize, usize, usize, usize)> {
+        let mut min = (usize::MAX, usize::MAX);
+        let mut max = (0, 0);
+        for layer in &self.layers[self.node_range(index)] {
+            for (idx, _) in layer.data.iter().enumerate().filter(|(_, pixel)| pixel.is_some()) {
+                let (x, y) = (idx % self.width, idx / self.width);
+                min = (min.0.min(x), min.1.min(y));
+                max = (max.0.max(x), max.1.max(y));
+            }
+        }
+        (min.0 != usize::MAX).then(|| (min.0, min.1, max.0 - min.0 + 1, max.1 - min.1 + 1))
+    }
+
+    /// Whether the layer content may be moved: neither it nor its groups
+    /// lock its pixels or position.
+    pub fn can_transform_layer(&self, index: usize) -> bool {
+        index < self.layers.len()
+            && !std::iter::once(index)
+                .chain(self.ancestors(index))
+                .any(|i| self.layers[i].locks.pixels || self.layers[i].locks.position)
+    }
+
+    /// Replaces the pixels and masks of the layers in `range` by `operation`
+    /// applied to them, masks going through it as grays. Layers locking their
+    /// pixels or position are skipped when `respect_locks` is set.
+    pub(crate) fn map_layers(&mut self, range: Range<usize>, respect_locks: bool, operation: impl Fn(&[Option<Color>]) -> Vec<Option<Color>>) {
+        for layer in &mut self.layers[range] {
+            if respect_locks && (layer.locks.pixels || layer.locks.position) {
+                continue;
+            }
+            if !layer.is_group() {
+                layer.data = Arc::new(operation(&layer.data));
+            }
+            if let Some(mask) = &mut layer.mask {
+                mask.data = map_mask(&mask.data, &operation);
+            }
+        }
+    }
+
+    /// Applies `transform` to the pixels and mask of the layer at `index`,
+    /// or of every unlocked layer of the group.
+    pub fn transform_layer(&mut self, index: usize, transform: &Affine, interpolation: Interpolation) -> bool {
+        if !self.can_transform_layer(index) {
+            return false;
+        }
+
+        let (width, height) = (self.width, self.height);
+        self.map_layers(self.node_range(index), true, |data| transform_pixels(data, width, height, transform, interpolation));
+        true
+    }
+}
+
+impl Document {
+    /// Moves, scales, rotates or skews the content of a layer as a single
+    /// undoable action.
+    pub fn transform_layer(&mut self, index: usize, transform: &Affine, interpolation: Interpolation) -> bool {
+        if !self.canvas.can_transform_layer(index) {
+            return false;
+        }
+        self.edit_layers(ActionKind::Transform, |canvas| canvas.transform_layer(index, transform, interpolation))
+    }
+
+    /// The canvas as it would look once the layer is transformed. Untouched
+    /// layers share their pixels with the document.
+    pub fn preview_transform(&self, index: usize, transform: &Affine, interpolation: Interpolation) -> CanvasState {
+        let mut preview = self.canvas.clone();
+        preview.transform_layer(index, transform, interpolation);
+        preview
+    }
+}
@@ -0,0 +1,269 @@
+use eframe::egui;
+use egui::{Color32, Pos2, Rect, Sense, Shape, Stroke, Ui, Vec2};
+
+use rustique_core::{Adjustment, AdjustmentKind, Curve, Document};
+
+use crate::live_preview::LivePreview;
+use crate::localization::{Language, get_text};
+use crate::size_dialog::DialogOutcome;
+use crate::ui_theme::RustiqueTheme;
+
+/// Side of the curve editor and width of the histogram, in screen pixels.
+const GRAPH_SIZE: f32 = 256.0;
+const HISTOGRAM_HEIGHT: f32 = 80.0;
+/// Distance in screen pixels within which a curve point is picked.
+const POINT_RADIUS: f32 = 6.0;
+
+pub fn adjustment_name_key(kind: AdjustmentKind) -> &'static str {
+    match kind {
+        AdjustmentKind::BrightnessContrast => "brightness_contrast",
+        AdjustmentKind::HueSaturation => "hue_saturation",
+        AdjustmentKind::Levels => "levels",
+        AdjustmentKind::Curves => "curves",
+        AdjustmentKind::Invert => "invert_colors",
+        AdjustmentKind::Desaturate => "desaturate",
+        AdjustmentKind::Posterize => "posterize",
+        AdjustmentKind::Threshold => "threshold",
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum CurveChannel {
+    Rgb,
+    Red,
+    Green,
+    Blue,
+}
+
+impl CurveChannel {
+    fn all() -> [CurveChannel; 4] {
+        [CurveChannel::Rgb, CurveChannel::Red, CurveChannel::Green, CurveChannel::Blue]
+    }
+
+    fn name_key(self) -> &'static str {
+        match self {
+            CurveChannel::Rgb => "channel_rgb",
+            CurveChannel::Red => "channel_red",
+            CurveChannel::Green => "channel_green",
+            CurveChannel::Blue => "channel_blue",
+        }
+    }
+
+    fn color(self) -> Color32 {
+        match self {
+            CurveChannel::Rgb => RustiqueTheme::ACCENT_SECONDARY,
+            CurveChannel::Red => Color32::from_rgb(230, 80, 80),
+            CurveChannel::Green => Color32::from_rgb(80, 200, 100),
+            CurveChannel::Blue => Color32::from_rgb(90, 130, 240),
+        }
+    }
+}
+
+/// Settings of an adjustment, previewed on the canvas while they change.
+pub struct AdjustmentDialog {
+    adjustment: Adjustment,
+    /// Luminance histogram of the active layer, shown by Levels and Threshold.
+    histogram: Option<[u32; 256]>,
+    channel: CurveChannel,
+    /// Curve point being dragged.
+    dragged_point: Option<usize>,
+    pub preview: LivePreview,
+}
+
+impl AdjustmentDialog {
+    pub fn new(kind: AdjustmentKind, document: &Document) -> Self {
+        let histogram = matches!(kind, AdjustmentKind::Levels | AdjustmentKind::Threshold).then(|| {
+            let canvas = &document.canvas;
+            canvas.histogram(canvas.active_layer_index, canvas.active_target()).luminance
+        });
+        Self {
+            adjustment: Adjustment::new(kind),
+            histogram,
+            channel: CurveChannel::Rgb,
+            dragged_point: None,
+            preview: LivePreview::default(),
+        }
+    }
+
+    /// Rebuilds the preview after a change. Returns `true` when it did.
+    pub fn update_preview(&mut self, document: &Document) -> bool {
+        self.preview.update(|| document.preview_adjustment(&self.adjustment))
+    }
+
+    pub fn show(&mut self, ctx: &egui::Context, language: Language, document: &mut Document) -> DialogOutcome {
+        let mut outcome = DialogOutcome::Open;
+        let before = self.adjustment.clone();
+
+        egui::Window::new(get_text(adjustment_name_key(self.adjustment.kind()), language))
+            .collapsible(false)
+            .resizable(false)
+            .show(ctx, |ui| {
+                self.settings_ui(ui, language);
+
+                ui.add_space(RustiqueTheme::SPACING_SM);
+                self.preview.checkbox(ui, language);
+                ui.horizontal(|ui| {
+                    if ui.button("OK").clicked() {
+                        outcome = if document.adjust(&self.adjustment) { DialogOutcome::Applied } else { DialogOutcome::Cancelled };
+                    }
+                    if ui.button(get_text("cancel", language)).clicked() {
+                        outcome = DialogOutcome::Cancelled;
+                    }
+                    if ui.button(get_text("reset", language)).clicked() {
+                        self.adjustment = Adjustment::new(self.adjustment.kind());
+                    }
+                });
+            });
+
+        if self.adjustment != before {
+            self.preview.invalidate();
+        }
+        outcome
+    }
+
+    fn settings_ui(&mut self, ui: &mut Ui, language: Language) {
+        let slider = |ui: &mut Ui, key: &str, value: &mut f32, range| {
+            ui.label(get_text(key, language));
+            ui.add(egui::Slider::new(value, range));
+            ui.end_row();
+        };
+
+        match &mut self.adjustment {
+            Adjustment::BrightnessContrast { brightness, contrast } => {
+                egui::Grid::new("adjustment_fields").num_columns(2).show(ui, |ui| {
+                    slider(ui, "brightness", brightness, -100.0..=100.0);
+                    slider(ui, "contrast", contrast, -100.0..=100.0);
+                });
+            },
+            Adjustment::HueSaturation { hue, saturation, lightness } => {
+                egui::Grid::new("adjustment_fields").num_columns(2).show(ui, |ui| {
+                    slider(ui, "hue", hue, -180.0..=180.0);
+                    slider(ui, "saturation", saturation, -100.0..=100.0);
+                    slider(ui, "lightness", lightness, -100.0..=100.0);
+                });
+            },
+            Adjustment::Levels { input_black, input_white, gamma, output_black, output_white } => {
+                if let Some(histogram) = &self.histogram {
+                    paint_histogram(ui, histogram);
+                }
+                ui.label(RustiqueTheme::muted_text(&get_text("input_levels", language)));
+                ui.horizontal(|ui| {
+                    ui.add(egui::DragValue::new(input_black).clamp_range(0..=*input_white - 1));
+                    ui.add(egui::DragValue::new(gamma).speed(0.01).clamp_range(0.1..=10.0).prefix("γ "));
+                    ui.add(egui::DragValue::new(input_white).clamp_range(*input_black + 1..=255));
+                });
+                ui.label(RustiqueTheme::muted_text(&get_text("output_levels", language)));
+                ui.horizontal(|ui| {
+                    ui.add(egui::DragValue::new(output_black).clamp_range(0..=255));
+                    ui.add(egui::DragValue::new(output_white).clamp_range(0..=255));
+                });
+            },
+            Adjustment::Curves(curves) => {
+                ui.horizontal(|ui| {
+                    for channel in CurveChannel::all() {
+                        if ui.selectable_label(self.channel == channel, get_text(channel.name_key(), language)).clicked() {
+                            self.channel = channel;
+                            self.dragged_point = None;
+                        }
+                    }
+                });
+                let curve = match self.channel {
+                    CurveChannel::Rgb => &mut curves.master,
+                    CurveChannel::Red => &mut curves.red,
+                    CurveChannel::Green => &mut curves.green,
+                    CurveChannel::Blue => &mut curves.blue,
+                };
+                curve_editor(ui, curve, self.channel.color(), &mut self.dragged_point);
+                ui.label(RustiqueTheme::muted_text(&get_text("curves_hint", language)));
+            },
+            Adjustment::Posterize { levels } => {
+                ui.horizontal(|ui| {
+                    ui.label(get_text("posterize_levels", language));
+                    ui.add(egui::Slider::new(levels, 2..=32));
+                });
+            },
+            Adjustment::Threshold { level } => {
+                if let Some(histogram) = &self.histogram {
+                    paint_histogram(ui, histogram);
+                }
+                ui.horizontal(|ui| {
+                    ui.label(get_text("threshold_level", language));
+                    ui.add(egui::Slider::new(level, 0..=255));
+                });
+            },
+            Adjustment::Invert | Adjustment::Desaturate => {},
+        }
+    }
+}
+
+fn paint_histogram(ui: &mut Ui, histogram: &[u32; 256]) {
+    let (rect, _) = ui.allocate_exact_size(Vec2::new(GRAPH_SIZE, HISTOGRAM_HEIGHT), Sense::hover());
+    let painter = ui.painter_at(rect);
+    painter.rect_filled(rect, 0.0, Color32::from_gray(30));
+    let max = histogram.iter().copied().max().unwrap_or(0).max(1) as f32;
+    let bar_width = rect.width() / 256.0;
+    for (value, &count) in histogram.iter().enumerate() {
+        let height = count as f32 / max * rect.height();
+        let x = rect.min.x + value as f32 * bar_width;
+        painter.rect_filled(
+            Rect::from_min_max(Pos2::new(x, rect.max.y - height), Pos2::new(x + bar_width, rect.max.y)),
+            0.0,
+            Color32::from_gray(180),
+        );
+    }
+}
+
+/// Graph of `curve` whose points are added, dragged and removed with the
+/// pointer.
+fn curve_editor(ui: &mut Ui, curve: &mut Curve, color: Color32, dragged_point: &mut Option<usize>) {
+    let (response, painter) = ui.allocate_painter(Vec2::splat(GRAPH_SIZE), Sense::click_and_drag());
+    let rect = response.rect;
+    let to_screen = |(x, y): (u8, u8)| {
+        Pos2::new(rect.min.x + x as f32 / 255.0 * rect.width(), rect.max.y - y as f32 / 255.0 * rect.height())
+    };
+    let to_value = |pos: Pos2| {
+        let value = |t: f32| (t * 255.0).round().clamp(0.0, 255.0) as u8;
+        (value((pos.x - rect.min.x) / rect.width()), value((rect.max.y - pos.y) / rect.height()))
+    };
+
+    let pointer = response.interact_pointer_pos();
+    let picked = pointer.and_then(|pointer| {
+        curve.points().iter().position(|&point| to_screen(point).distance(pointer) <= POINT_RADIUS)
+    });
+    if let Some(pointer) = pointer {
+        let (input, output) = to_value(pointer);
+        if response.drag_started() {
+            *dragged_point = Some(picked.unwrap_or_else(|| curve.add_point(input, output)));
+        } else if response.clicked() && picked.is_none() {
+            curve.add_point(input, output);
+        } else if response.secondary_clicked()
+            && let Some(index) = picked
+        {
+            curve.remove_point(index);
+        }
+        if response.dragged()
+            && let Some(index) = *dragged_point
+        {
+            curve.move_point(index, input, output);
+        }
+    }
+    if response.drag_released() {
+        *dragged_point = None;
+    }
+
+    painter.rect_filled(rect, 0.0, Color32::from_gray(30));
+    let grid = Stroke::new(1.0, Color32::from_gray(60));
+    for step in 1..4 {
+        let t = step as f32 / 4.0;
+        painter.line_segment([Pos2::new(rect.min.x + t * rect.width(), rect.min.y), Pos2::new(rect.min.x + t * rect.width(), rect.max.y)], grid);
+        painter.line_segment([Pos2::new(rect.min.x, rect.min.y + t * rect.height()), Pos2::new(rect.max.x, rect.min.y + t * rect.height())], grid);
+    }
+    painter.line_segment([rect.left_bottom(), rect.right_top()], grid);
+
+    let table = curve.table();
+    let line = (0..=255u8).map(|input| to_screen((input, table[input as usize]))).collect();
+    painter.add(Shape::line(line, Stroke::new(2.0, color)));
+    for &point in curve.points() {
+        painter.circle(to_screen(point), POINT_RADIUS / 2.0 + 1.0, Color32::WHITE, Stroke::new(1.0, color));
+    }
+}
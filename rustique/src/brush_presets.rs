@@ -0,0 +1,126 @@
+use eframe::egui;
+use egui::Ui;
+use rfd::FileDialog;
+
+use rustique_core::FileError;
+
+use crate::brush_system::{self, BrushManager, BrushType, PresetPack};
+use crate::localization::{Language, get_text};
+use crate::ui_theme::RustiqueTheme;
+
+/// Height of the preset list before it scrolls, in screen pixels.
+const LIST_HEIGHT: f32 = 120.0;
+
+/// Browser of the brush presets: applies, saves and deletes them, resets the
+/// active brush and shares presets as JSON packs. Every change is written to
+/// the presets of the user at once.
+#[derive(Default)]
+pub struct PresetBrowser {
+    /// Name typed for the next preset.
+    new_name: String,
+    /// Preset applied last.
+    applied: Option<usize>,
+}
+
+impl PresetBrowser {
+    pub fn show(&mut self, ui: &mut Ui, language: Language, brushes: &mut BrushManager, brush_size: &mut i32) -> Result<(), FileError> {
+        let mut presets_changed = false;
+
+        ui.label(RustiqueTheme::muted_text(&get_text("brush_presets", language)));
+        if brushes.presets.is_empty() {
+            ui.label(RustiqueTheme::muted_text(&get_text("no_presets", language)));
+        }
+
+        let mut applied = None;
+        let mut removed = None;
+        egui::ScrollArea::vertical()
+            .id_source("brush_presets")
+            .max_height(LIST_HEIGHT)
+            .show(ui, |ui| {
+                for (i, preset) in brushes.presets.iter().enumerate() {
+                    // Presets of custom tips that are not loaded cannot be applied.
+                    let available = match preset.properties.brush_type {
+                        BrushType::Custom(id) => brushes.has_tip(id),
+                        _ => true,
+                    };
+                    ui.horizontal(|ui| {
+                        if ui.small_button("✕").on_hover_text(get_text("delete", language)).clicked() {
+                            removed = Some(i);
+                        }
+                        if ui.add_enabled(available, egui::SelectableLabel::new(self.applied == Some(i), &preset.name)).clicked() {
+                            applied = Some(i);
+                        }
+                    });
+                }
+            });
+
+        if let Some(index) = applied
+            && let Some(size) = brushes.apply_preset(index)
+        {
+            set_brush_size(brushes, brush_size, size);
+            self.applied = Some(index);
+        }
+        if let Some(index) = removed {
+            brushes.presets.remove(index);
+            self.applied = None;
+            presets_changed = true;
+        }
+
+        ui.horizontal(|ui| {
+            ui.add(egui::TextEdit::singleline(&mut self.new_name)
+                .hint_text(get_text("preset_name", language))
+                .desired_width(120.0));
+            let name = self.new_name.trim().to_string();
+            if ui.add_enabled(!name.is_empty(), egui::Button::new(get_text("save_preset", language))).clicked() {
+                brushes.save_preset(name, *brush_size as f32);
+                self.new_name.clear();
+                self.applied = None;
+                presets_changed = true;
+            }
+        });
+
+        let mut result = Ok(());
+        ui.horizontal_wrapped(|ui| {
+            if ui.button(get_text("reset_brush", language)).clicked() {
+                let size = brushes.reset_active_brush();
+                set_brush_size(brushes, brush_size, size);
+                self.applied = None;
+            }
+            if ui.button(get_text("import_presets", language)).clicked()
+                && let Some(path) = FileDialog::new()
+                    .set_title(&get_text("import_presets", language))
+                    .add_filter("Brush Presets", &["json"])
+                    .pick_file()
+            {
+                match PresetPack::load(&path) {
+                    Ok(pack) => {
+                        brushes.add_preset_pack(pack);
+                        self.applied = None;
+                        presets_changed = true;
+                    },
+                    Err(e) => result = Err(e),
+                }
+            }
+            if ui.button(get_text("export_presets", language)).clicked()
+                && let Some(path) = FileDialog::new()
+                    .set_title(&get_text("export_presets", language))
+                    .add_filter("Brush Presets", &["json"])
+                    .set_file_name("brush_presets.json")
+                    .save_file()
+            {
+                result = brushes.preset_pack().save(&path);
+            }
+        });
+
+        if presets_changed {
+            brush_system::save_user_presets(&brushes.preset_pack())?;
+        }
+        result
+    }
+}
+
+/// Makes `size` the brush size of the toolbar and of the brushes.
+fn set_brush_size(brushes: &mut BrushManager, brush_size: &mut i32, size: f32) {
+    *brush_size = (size.round() as i32).clamp(1, 500);
+    brushes.current_size = *brush_size as f32;
+}
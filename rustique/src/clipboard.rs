@@ -0,0 +1,49 @@
+use std::borrow::Cow;
+
+use rustique_core::ClipboardImage;
+
+/// Images copied by the user. They go to the desktop clipboard when there is
+/// one, and are always kept in-process as well so that copies work without it
+/// and remember where on the canvas they come from.
+pub struct AppClipboard {
+    system: Option<arboard::Clipboard>,
+    local: Option<ClipboardImage>,
+}
+
+impl Default for AppClipboard {
+    fn default() -> Self {
+        Self {
+            system: arboard::Clipboard::new().ok(),
+            local: None,
+        }
+    }
+}
+
+impl AppClipboard {
+    pub fn set(&mut self, image: ClipboardImage) {
+        if let Some(system) = &mut self.system {
+            let _ = system.set_image(arboard::ImageData {
+                width: image.width,
+                height: image.height,
+                bytes: Cow::Owned(image.to_rgba()),
+            });
+        }
+        self.local = Some(image);
+    }
+
+    /// The desktop clipboard image if there is one, the last in-process copy
+    /// otherwise.
+    pub fn get(&mut self) -> Option<ClipboardImage> {
+        let system = self
+            .system
+            .as_mut()
+            .and_then(|system| system.get_image().ok())
+            .and_then(|image| ClipboardImage::from_rgba(image.width, image.height, &image.bytes));
+
+        match (system, &self.local) {
+            (Some(system), Some(local)) if system.same_pixels(local) => Some(local.clone()),
+            (Some(system), _) => Some(system),
+            (None, local) => local.clone(),
+        }
+    }
+}
@@ -0,0 +1,236 @@
+use eframe::egui;
+use egui::{Color32, Painter, PointerButton, Pos2, Rect, Response, Stroke, Ui, Vec2};
+use egui::emath::RectTransform;
+
+use rustique_core::Document;
+
+use crate::localization::{Language, get_text};
+use crate::transform_tool::{HANDLE_SIZE, handles};
+use crate::ui_theme::RustiqueTheme;
+
+/// Shade over the parts of the canvas that are cropped away.
+const OUTSIDE_SHADE: Color32 = Color32::from_black_alpha(140);
+
+#[derive(Clone, Copy)]
+enum CropDrag {
+    Move,
+    /// Dragging the handle at `(hx, hy)` while `anchor`, on the opposite
+    /// side, stays in place.
+    Resize { hx: f32, hy: f32, anchor: Pos2 },
+}
+
+/// Crop rectangle drawn on the canvas, then moved or resized with its
+/// handles. It can keep a fixed aspect ratio.
+pub struct CropTool {
+    pub lock_ratio: bool,
+    pub ratio: (u32, u32),
+    rect: Option<Rect>,
+    drag: Option<CropDrag>,
+}
+
+impl Default for CropTool {
+    fn default() -> Self {
+        Self {
+            lock_ratio: false,
+            ratio: (1, 1),
+            rect: None,
+            drag: None,
+        }
+    }
+}
+
+impl CropTool {
+    pub fn is_active(&self) -> bool {
+        self.rect.is_some()
+    }
+
+    pub fn cancel(&mut self) {
+        self.rect = None;
+        self.drag = None;
+    }
+
+    /// The rectangle rounded to whole pixels, as `(x, y, width, height)`.
+    fn pixel_rect(&self) -> Option<(usize, usize, usize, usize)> {
+        let rect = self.rect?;
+        let (min, max) = (rect.min.round(), rect.max.round());
+        let (width, height) = ((max.x - min.x) as usize, (max.y - min.y) as usize);
+        (width > 0 && height > 0).then_some((min.x as usize, min.y as usize, width, height))
+    }
+
+    /// Crops `document` to the rectangle. Returns `true` when it changed.
+    pub fn apply(&mut self, document: &mut Document) -> bool {
+        let rect = self.pixel_rect();
+        self.cancel();
+        rect.is_some_and(|(x, y, width, height)| document.crop(x, y, width, height))
+    }
+
+    /// Handles the pointer on the canvas of `document`.
+    pub fn handle_input(&mut self, response: &Response, to_canvas: &RectTransform, document: &Document) {
+        let bounds = Rect::from_min_size(Pos2::ZERO, Vec2::new(document.width() as f32, document.height() as f32));
+        if response.drag_released() && self.drag.take().is_some() && self.pixel_rect().is_none() {
+            self.rect = None;
+        }
+        let Some(screen_pos) = response.interact_pointer_pos() else {
+            return;
+        };
+        let pointer = bounds.clamp(to_canvas.transform_pos(screen_pos));
+
+        if response.drag_started_by(PointerButton::Primary) {
+            let to_screen = to_canvas.inverse();
+            self.drag = Some(match self.rect {
+                Some(rect) => {
+                    let handle = handles().find(|&(hx, hy)| {
+                        let pos = rect.center() + Vec2::new(hx, hy) * rect.size() / 2.0;
+                        to_screen.transform_pos(pos).distance(screen_pos) <= HANDLE_SIZE
+                    });
+                    match handle {
+                        Some((hx, hy)) => CropDrag::Resize { hx, hy, anchor: rect.center() - Vec2::new(hx, hy) * rect.size() / 2.0 },
+                        None if rect.contains(pointer) => CropDrag::Move,
+                        None => self.start_rect(pointer),
+                    }
+                },
+                None => self.start_rect(pointer),
+            });
+        }
+
+        if response.dragged_by(PointerButton::Primary)
+            && let (Some(drag), Some(rect)) = (self.drag, self.rect)
+        {
+            self.rect = Some(match drag {
+                CropDrag::Move => {
+                    let moved = rect.translate(response.drag_delta() * to_canvas.scale());
+                    let shift = Vec2::new(
+                        (bounds.min.x - moved.min.x).max(0.0) + (bounds.max.x - moved.max.x).min(0.0),
+                        (bounds.min.y - moved.min.y).max(0.0) + (bounds.max.y - moved.max.y).min(0.0),
+                    );
+                    moved.translate(shift)
+                },
+                CropDrag::Resize { hx, hy, anchor } => self.resized(rect, hx, hy, anchor, pointer, bounds),
+            });
+        }
+    }
+
+    fn start_rect(&mut self, pointer: Pos2) -> CropDrag {
+        self.rect = Some(Rect::from_min_max(pointer, pointer));
+        CropDrag::Resize { hx: 1.0, hy: 1.0, anchor: pointer }
+    }
+
+    /// `rect` with the handle at `(hx, hy)` moved to `pointer`.
+    fn resized(&self, rect: Rect, hx: f32, hy: f32, anchor: Pos2, pointer: Pos2, bounds: Rect) -> Rect {
+        let mut size = Vec2::new(
+            if hx != 0.0 { pointer.x - anchor.x } else { rect.width() },
+            if hy != 0.0 { pointer.y - anchor.y } else { rect.height() },
+        );
+
+        let span = |h: f32, anchor: f32, size: f32, center: f32| {
+            if h != 0.0 { (anchor, anchor + size) } else { (center - size / 2.0, center + size / 2.0) }
+        };
+        let center = rect.center();
+
+        if !self.lock_ratio {
+            let (x0, x1) = span(hx, anchor.x, size.x, center.x);
+            let (y0, y1) = span(hy, anchor.y, size.y, center.y);
+            return Rect::from_two_pos(Pos2::new(x0, y0), Pos2::new(x1, y1)).intersect(bounds);
+        }
+
+        let ratio = self.ratio.0 as f32 / self.ratio.1 as f32;
+        if hx != 0.0 && hy != 0.0 {
+            if size.x.abs() / ratio > size.y.abs() {
+                size.y = size.x.abs() / ratio * size.y.signum();
+            } else {
+                size.x = size.y.abs() * ratio * size.x.signum();
+            }
+        } else if hx != 0.0 {
+            size.y = size.x.abs() / ratio;
+        } else {
+            size.x = size.y.abs() * ratio;
+        }
+
+        // Shrink both sides alike to stay on the canvas, keeping the ratio.
+        // Sides following a handle grow from the anchor, the others from the
+        // middle of the rectangle.
+        let room = |h: f32, size: f32, anchor: f32, center: f32, min: f32, max: f32| {
+            if h == 0.0 {
+                2.0 * (center - min).min(max - center)
+            } else if size < 0.0 {
+                anchor - min
+            } else {
+                max - anchor
+            }
+        };
+        let room = Vec2::new(
+            room(hx, size.x, anchor.x, center.x, bounds.min.x, bounds.max.x),
+            room(hy, size.y, anchor.y, center.y, bounds.min.y, bounds.max.y),
+        );
+        let factor = (room.x / size.x.abs()).min(room.y / size.y.abs()).clamp(0.0, 1.0);
+        if factor.is_finite() {
+            size *= factor;
+        }
+
+        let (x0, x1) = span(hx, anchor.x, size.x, center.x);
+        let (y0, y1) = span(hy, anchor.y, size.y, center.y);
+        Rect::from_two_pos(Pos2::new(x0, y0), Pos2::new(x1, y1))
+    }
+
+    /// Shades what is cropped away and draws the rectangle with its handles.
+    pub fn paint(&self, painter: &Painter, to_screen: &RectTransform) {
+        let Some((x, y, width, height)) = self.pixel_rect() else {
+            return;
+        };
+        let canvas = *to_screen.to();
+        let rect = to_screen.transform_rect(Rect::from_min_size(Pos2::new(x as f32, y as f32), Vec2::new(width as f32, height as f32)));
+        for outside in [
+            Rect::from_min_max(canvas.min, Pos2::new(canvas.max.x, rect.min.y)),
+            Rect::from_min_max(Pos2::new(canvas.min.x, rect.max.y), canvas.max),
+            Rect::from_min_max(Pos2::new(canvas.min.x, rect.min.y), Pos2::new(rect.min.x, rect.max.y)),
+            Rect::from_min_max(Pos2::new(rect.max.x, rect.min.y), Pos2::new(canvas.max.x, rect.max.y)),
+        ] {
+            painter.rect_filled(outside, 0.0, OUTSIDE_SHADE);
+        }
+
+        let stroke = Stroke::new(1.0, RustiqueTheme::ACCENT_SECONDARY);
+        painter.rect_stroke(rect, 0.0, stroke);
+        for (hx, hy) in handles() {
+            let pos = rect.center() + Vec2::new(hx, hy) * rect.size() / 2.0;
+            painter.rect(Rect::from_center_size(pos, Vec2::splat(HANDLE_SIZE)), 0.0, Color32::WHITE, stroke);
+        }
+    }
+
+    /// Ratio lock, crop size and the commands of the tool. Returns `true`
+    /// when the document was cropped.
+    pub fn settings_ui(&mut self, ui: &mut Ui, language: Language, document: &mut Document) -> bool {
+        ui.horizontal(|ui| {
+            ui.checkbox(&mut self.lock_ratio, get_text("lock_ratio", language));
+            ui.add_enabled(self.lock_ratio, egui::DragValue::new(&mut self.ratio.0).clamp_range(1..=100));
+            ui.label(":");
+            ui.add_enabled(self.lock_ratio, egui::DragValue::new(&mut self.ratio.1).clamp_range(1..=100));
+        });
+
+        let mut changed = false;
+        if let Some((_, _, width, height)) = self.pixel_rect() {
+            ui.add_space(RustiqueTheme::SPACING_XS);
+            ui.label(RustiqueTheme::muted_text(&format!("{width} × {height} px")));
+            ui.horizontal(|ui| {
+                if ui.button(get_text("crop", language)).clicked() {
+                    changed = self.apply(document);
+                }
+                if ui.button(get_text("cancel", language)).clicked() {
+                    self.cancel();
+                }
+            });
+        }
+
+        ui.add_space(RustiqueTheme::SPACING_XS);
+        ui.horizontal_wrapped(|ui| {
+            if ui.button(get_text("crop_to_content", language)).clicked() {
+                self.cancel();
+                changed |= document.crop_to_layer_content();
+            }
+            if ui.button(get_text("trim", language)).clicked() {
+                self.cancel();
+                changed |= document.trim();
+            }
+        });
+        changed
+    }
+}
@@ -0,0 +1,115 @@
+use eframe::egui;
+use egui::Ui;
+
+use rustique_core::{Document, Filter, FilterKind};
+
+use crate::live_preview::LivePreview;
+use crate::localization::{Language, get_text};
+use crate::size_dialog::DialogOutcome;
+use crate::ui_theme::RustiqueTheme;
+
+/// Largest blur radius the dialog offers, in pixels.
+const MAX_RADIUS: f32 = 250.0;
+
+pub fn filter_name_key(kind: FilterKind) -> &'static str {
+    match kind {
+        FilterKind::GaussianBlur => "gaussian_blur",
+        FilterKind::BoxBlur => "box_blur",
+        FilterKind::MotionBlur => "motion_blur",
+        FilterKind::UnsharpMask => "unsharp_mask",
+        FilterKind::EdgeDetect => "edge_detect",
+        FilterKind::Emboss => "emboss",
+    }
+}
+
+/// Settings of a filter, previewed on the canvas. Large filters take a
+/// while, so the preview waits for sliders to be released.
+pub struct FilterDialog {
+    filter: Filter,
+    /// A slider is being dragged.
+    dragging: bool,
+    pub preview: LivePreview,
+}
+
+impl FilterDialog {
+    pub fn new(kind: FilterKind) -> Self {
+        Self {
+            filter: Filter::new(kind),
+            dragging: false,
+            preview: LivePreview::default(),
+        }
+    }
+
+    /// Rebuilds the preview once no slider is dragged. Returns `true` when
+    /// it did.
+    pub fn update_preview(&mut self, document: &Document) -> bool {
+        !self.dragging && self.preview.update(|| document.preview_filter(&self.filter))
+    }
+
+    pub fn show(&mut self, ctx: &egui::Context, language: Language, document: &mut Document) -> DialogOutcome {
+        let mut outcome = DialogOutcome::Open;
+        let before = self.filter;
+
+        egui::Window::new(get_text(filter_name_key(self.filter.kind()), language))
+            .collapsible(false)
+            .resizable(false)
+            .show(ctx, |ui| {
+                egui::Grid::new("filter_fields").num_columns(2).show(ui, |ui| {
+                    self.dragging = self.settings_ui(ui, language);
+                });
+
+                ui.add_space(RustiqueTheme::SPACING_SM);
+                self.preview.checkbox(ui, language);
+                ui.horizontal(|ui| {
+                    if ui.button("OK").clicked() {
+                        outcome = if document.apply_filter(&self.filter) { DialogOutcome::Applied } else { DialogOutcome::Cancelled };
+                    }
+                    if ui.button(get_text("cancel", language)).clicked() {
+                        outcome = DialogOutcome::Cancelled;
+                    }
+                    if ui.button(get_text("reset", language)).clicked() {
+                        self.filter = Filter::new(self.filter.kind());
+                    }
+                });
+            });
+
+        if self.filter != before {
+            self.preview.invalidate();
+        }
+        outcome
+    }
+
+    /// Returns `true` while one of the sliders is dragged.
+    fn settings_ui(&mut self, ui: &mut Ui, language: Language) -> bool {
+        let mut dragged = false;
+        let mut row = |ui: &mut Ui, key: &str, slider: egui::Slider| {
+            ui.label(get_text(key, language));
+            dragged |= ui.add(slider).dragged();
+            ui.end_row();
+        };
+
+        match &mut self.filter {
+            Filter::GaussianBlur { radius } => {
+                row(ui, "radius", egui::Slider::new(radius, 0.0..=MAX_RADIUS).logarithmic(true).suffix(" px"));
+            },
+            Filter::BoxBlur { radius } => {
+                row(ui, "radius", egui::Slider::new(radius, 0..=MAX_RADIUS as usize).logarithmic(true).suffix(" px"));
+            },
+            Filter::MotionBlur { length, angle } => {
+                row(ui, "length", egui::Slider::new(length, 0.0..=MAX_RADIUS).logarithmic(true).suffix(" px"));
+                row(ui, "angle", egui::Slider::new(angle, -180.0..=180.0).suffix("°"));
+            },
+            Filter::UnsharpMask { radius, amount, threshold } => {
+                row(ui, "radius", egui::Slider::new(radius, 0.0..=MAX_RADIUS).logarithmic(true).suffix(" px"));
+                row(ui, "amount", egui::Slider::new(amount, 0.0..=500.0).suffix("%"));
+                row(ui, "threshold_level", egui::Slider::new(threshold, 0..=255));
+            },
+            Filter::Emboss { angle, strength } => {
+                row(ui, "angle", egui::Slider::new(angle, -180.0..=180.0).suffix("°"));
+                row(ui, "strength", egui::Slider::new(strength, 0.1..=10.0));
+            },
+            Filter::EdgeDetect => {},
+        }
+        dragged
+    }
+}
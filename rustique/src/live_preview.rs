@@ -0,0 +1,54 @@
+use eframe::egui;
+use egui::Ui;
+
+use rustique_core::CanvasState;
+
+use crate::localization::{Language, get_text};
+
+/// Canvas shown in place of the document while a dialog is open, rebuilt
+/// when the dialog settings or the document below change.
+pub struct LivePreview {
+    enabled: bool,
+    canvas: Option<CanvasState>,
+    dirty: bool,
+}
+
+impl Default for LivePreview {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            canvas: None,
+            dirty: true,
+        }
+    }
+}
+
+impl LivePreview {
+    /// The preview to show, `None` when it is turned off.
+    pub fn canvas(&self) -> Option<&CanvasState> {
+        self.canvas.as_ref()
+    }
+
+    /// Asks for the preview to be rebuilt.
+    pub fn invalidate(&mut self) {
+        self.dirty = true;
+    }
+
+    /// Rebuilds the preview with `render` if it was invalidated. Returns
+    /// `true` when it did.
+    pub fn update(&mut self, render: impl FnOnce() -> CanvasState) -> bool {
+        if !self.dirty {
+            return false;
+        }
+        self.dirty = false;
+        self.canvas = self.enabled.then(render);
+        true
+    }
+
+    /// The checkbox turning the preview on and off.
+    pub fn checkbox(&mut self, ui: &mut Ui, language: Language) {
+        if ui.checkbox(&mut self.enabled, get_text("preview", language)).changed() {
+            self.dirty = true;
+        }
+    }
+}
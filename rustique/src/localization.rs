@@ -39,6 +39,8 @@ fn get_french_text(key: &str) -> String {
         ("color_picker", "Pipette"),
         ("line", "Ligne"),
         ("brush_size", "Taille du pinceau:"),
+        ("brush_opacity", "Opacité du pinceau:"),
+        ("brush_hardness", "Dureté du pinceau:"),
         ("eraser_size", "Taille de la gomme:"),
         ("colors", "Couleurs:"),
         ("primary", "Primaire:"),
@@ -100,6 +102,168 @@ fn get_french_text(key: &str) -> String {
         ("load_texture", "Charger Texture"),
         ("select_brush_texture", "Sélectionner une texture de pinceau"),
         ("custom_brushes", "Pinceaux personnalisés"),
+        ("invalid_brush_file", "Fichier de pinceau invalide"),
+        ("brush_presets", "Préréglages:"),
+        ("no_presets", "Aucun préréglage enregistré"),
+        ("preset_name", "Nom du préréglage"),
+        ("save_preset", "Enregistrer"),
+        ("reset_brush", "Réinitialiser le pinceau"),
+        ("import_presets", "Importer des préréglages"),
+        ("export_presets", "Exporter les préréglages"),
+        ("invalid_preset_file", "Fichier de préréglages invalide"),
+        ("blend_mode", "Mode de fusion:"),
+        ("layer_opacity", "Opacité du calque:"),
+        ("layer_locks", "Verrous:"),
+        ("group_layer", "Grouper le calque"),
+        ("menu_layer", "Calque"),
+        ("duplicate_layer", "Dupliquer le calque"),
+        ("merge_down", "Fusionner avec le calque inférieur"),
+        ("merge_visible", "Fusionner les calques visibles"),
+        ("flatten_image", "Aplatir l'image"),
+        ("layer_mask", "Masque:"),
+        ("add_mask", "Ajouter"),
+        ("remove_mask", "Supprimer"),
+        ("edit_mask", "Éditer"),
+        ("mask_enabled", "Actif"),
+        ("clip_to_below", "Masque d'écrêtage sur le calque inférieur"),
+        ("history_memory", "Mémoire de l'historique"),
+        ("history", "Historique"),
+        ("history_initial", "État initial"),
+        ("action_brush_stroke", "Coup de pinceau"),
+        ("action_erase", "Gomme"),
+        ("action_bucket_fill", "Remplissage"),
+        ("action_add_layer", "Nouveau calque"),
+        ("action_remove_layer", "Suppression du calque"),
+        ("action_move_layer", "Déplacement du calque"),
+        ("action_group_layer", "Groupement du calque"),
+        ("action_rename_layer", "Renommage du calque"),
+        ("action_layer_visibility", "Visibilité du calque"),
+        ("action_layer_opacity", "Opacité du calque"),
+        ("action_layer_blend_mode", "Mode de fusion du calque"),
+        ("action_layer_locks", "Verrous du calque"),
+        ("action_add_mask", "Ajout d'un masque"),
+        ("action_remove_mask", "Suppression du masque"),
+        ("action_toggle_mask", "Activation du masque"),
+        ("action_clipping", "Masque d'écrêtage"),
+        ("selection", "Sélection"),
+        ("menu_select", "Sélection"),
+        ("tool_select", "Outil de sélection"),
+        ("tool_magic_wand", "Baguette magique"),
+        ("select_rectangle", "Rectangle"),
+        ("select_ellipse", "Ellipse"),
+        ("select_lasso", "Lasso"),
+        ("select_polygon", "Lasso polygonal"),
+        ("selection_replace", "Remplacer"),
+        ("selection_add", "Ajouter"),
+        ("selection_subtract", "Soustraire"),
+        ("selection_intersect", "Intersection"),
+        ("tolerance", "Tolérance"),
+        ("select_all", "Tout sélectionner"),
+        ("deselect", "Désélectionner"),
+        ("invert_selection", "Inverser la sélection"),
+        ("menu_edit", "Édition"),
+        ("cut", "Couper"),
+        ("copy", "Copier"),
+        ("paste", "Coller"),
+        ("paste_as_layer", "Coller comme nouveau calque"),
+        ("commit_paste", "Valider le collage"),
+        ("cancel_paste", "Annuler le collage"),
+        ("pasted_layer", "Calque collé"),
+        ("action_cut", "Couper"),
+        ("action_paste", "Coller"),
+        ("action_paste_layer", "Coller comme calque"),
+        ("tool_transform", "Transformation libre"),
+        ("transform", "Transformation"),
+        ("transform_unavailable", "Le calque actif est verrouillé"),
+        ("interpolation_nearest", "Plus proche voisin"),
+        ("interpolation_bilinear", "Bilinéaire"),
+        ("interpolation_bicubic", "Bicubique"),
+        ("flip_horizontal", "Miroir horizontal"),
+        ("flip_vertical", "Miroir vertical"),
+        ("apply", "Appliquer"),
+        ("action_transform", "Transformation"),
+        ("menu_image", "Image"),
+        ("canvas_size", "Taille du canevas"),
+        ("image_size", "Taille de l'image"),
+        ("current_size", "Taille actuelle"),
+        ("keep_ratio", "Conserver les proportions"),
+        ("anchor", "Ancrage"),
+        ("resampling", "Rééchantillonnage"),
+        ("interpolation_lanczos", "Lanczos"),
+        ("action_canvas_size", "Taille du canevas"),
+        ("action_image_size", "Taille de l'image"),
+        ("rotate_cw90", "Rotation 90° horaire"),
+        ("rotate_180", "Rotation 180°"),
+        ("rotate_ccw90", "Rotation 90° antihoraire"),
+        ("rotate_arbitrary", "Rotation libre"),
+        ("angle", "Angle:"),
+        ("angle_hint", "Les valeurs positives tournent dans le sens horaire"),
+        ("active_layer", "Calque actif"),
+        ("action_rotate_image", "Rotation de l'image"),
+        ("action_flip_image", "Miroir de l'image"),
+        ("action_rotate_layer", "Rotation du calque"),
+        ("action_flip_layer", "Miroir du calque"),
+        ("tool_crop", "Recadrage"),
+        ("crop", "Recadrer"),
+        ("lock_ratio", "Proportions fixes"),
+        ("crop_to_content", "Recadrer sur le contenu du calque"),
+        ("trim", "Rogner les bords"),
+        ("action_crop", "Recadrage"),
+        ("menu_adjustments", "Réglages"),
+        ("brightness_contrast", "Luminosité/Contraste"),
+        ("brightness", "Luminosité:"),
+        ("contrast", "Contraste:"),
+        ("hue_saturation", "Teinte/Saturation/Luminosité"),
+        ("hue", "Teinte:"),
+        ("saturation", "Saturation:"),
+        ("lightness", "Luminosité:"),
+        ("levels", "Niveaux"),
+        ("input_levels", "Niveaux d'entrée"),
+        ("output_levels", "Niveaux de sortie"),
+        ("curves", "Courbes"),
+        ("curves_hint", "Cliquez pour ajouter un point, faites-le glisser pour le déplacer, clic droit pour le supprimer"),
+        ("channel_rgb", "RVB"),
+        ("channel_red", "Rouge"),
+        ("channel_green", "Vert"),
+        ("channel_blue", "Bleu"),
+        ("invert_colors", "Inverser les couleurs"),
+        ("desaturate", "Désaturer"),
+        ("posterize", "Postériser"),
+        ("posterize_levels", "Niveaux:"),
+        ("threshold", "Seuil"),
+        ("threshold_level", "Seuil:"),
+        ("preview", "Aperçu"),
+        ("reset", "Réinitialiser"),
+        ("adjustment_unavailable", "Le calque actif ne peut pas être modifié"),
+        ("menu_filters", "Filtres"),
+        ("gaussian_blur", "Flou gaussien"),
+        ("box_blur", "Flou moyen"),
+        ("motion_blur", "Flou de mouvement"),
+        ("unsharp_mask", "Accentuation"),
+        ("edge_detect", "Détection des contours"),
+        ("emboss", "Estampage"),
+        ("radius", "Rayon:"),
+        ("length", "Longueur:"),
+        ("amount", "Quantité:"),
+        ("strength", "Intensité:"),
+        ("lock_pixels", "Verrouiller les pixels"),
+        ("lock_alpha", "Verrouiller la transparence"),
+        ("lock_position", "Verrouiller la position"),
+        ("blend_normal", "Normal"),
+        ("blend_add", "Addition"),
+        ("blend_multiply", "Produit"),
+        ("blend_screen", "Superposition"),
+        ("blend_overlay", "Incrustation"),
+        ("blend_darken", "Obscurcir"),
+        ("blend_lighten", "Éclaircir"),
+        ("blend_color_dodge", "Densité couleur -"),
+        ("blend_color_burn", "Densité couleur +"),
+        ("blend_soft_light", "Lumière tamisée"),
+        ("blend_hue", "Teinte"),
+        ("blend_saturation", "Saturation"),
+        ("blend_color", "Couleur"),
+        ("blend_luminosity", "Luminosité"),
+        ("blend_behind", "Derrière"),
         ("delete", "Supprimer"),
     ].iter().cloned().collect();
     
@@ -132,6 +296,8 @@ fn get_english_text(key: &str) -> String {
         ("color_picker", "Color Picker"),
         ("line", "Line"),
         ("brush_size", "Brush Size:"),
+        ("brush_opacity", "Brush Opacity:"),
+        ("brush_hardness", "Brush Hardness:"),
         ("eraser_size", "Eraser Size:"),
         ("colors", "Colors:"),
         ("primary", "Primary:"),
@@ -192,6 +358,168 @@ fn get_english_text(key: &str) -> String {
         ("load_texture", "Load Texture"),
         ("select_brush_texture", "Select brush texture"),
         ("custom_brushes", "Custom Brushes"),
+        ("invalid_brush_file", "Invalid brush file"),
+        ("brush_presets", "Presets:"),
+        ("no_presets", "No saved presets"),
+        ("preset_name", "Preset name"),
+        ("save_preset", "Save"),
+        ("reset_brush", "Reset brush"),
+        ("import_presets", "Import presets"),
+        ("export_presets", "Export presets"),
+        ("invalid_preset_file", "Invalid preset file"),
+        ("blend_mode", "Blend Mode:"),
+        ("layer_opacity", "Layer Opacity:"),
+        ("layer_locks", "Locks:"),
+        ("group_layer", "Group layer"),
+        ("menu_layer", "Layer"),
+        ("duplicate_layer", "Duplicate Layer"),
+        ("merge_down", "Merge Down"),
+        ("merge_visible", "Merge Visible"),
+        ("flatten_image", "Flatten Image"),
+        ("layer_mask", "Mask:"),
+        ("add_mask", "Add"),
+        ("remove_mask", "Remove"),
+        ("edit_mask", "Edit"),
+        ("mask_enabled", "Enabled"),
+        ("clip_to_below", "Clip to layer below"),
+        ("history_memory", "History memory"),
+        ("history", "History"),
+        ("history_initial", "Initial state"),
+        ("action_brush_stroke", "Brush stroke"),
+        ("action_erase", "Eraser"),
+        ("action_bucket_fill", "Bucket fill"),
+        ("action_add_layer", "Add layer"),
+        ("action_remove_layer", "Remove layer"),
+        ("action_move_layer", "Move layer"),
+        ("action_group_layer", "Group layer"),
+        ("action_rename_layer", "Rename layer"),
+        ("action_layer_visibility", "Layer visibility"),
+        ("action_layer_opacity", "Layer opacity"),
+        ("action_layer_blend_mode", "Layer blend mode"),
+        ("action_layer_locks", "Layer locks"),
+        ("action_add_mask", "Add layer mask"),
+        ("action_remove_mask", "Remove layer mask"),
+        ("action_toggle_mask", "Enable layer mask"),
+        ("action_clipping", "Clipping mask"),
+        ("selection", "Selection"),
+        ("menu_select", "Select"),
+        ("tool_select", "Selection Tool"),
+        ("tool_magic_wand", "Magic Wand"),
+        ("select_rectangle", "Rectangle"),
+        ("select_ellipse", "Ellipse"),
+        ("select_lasso", "Lasso"),
+        ("select_polygon", "Polygonal lasso"),
+        ("selection_replace", "Replace"),
+        ("selection_add", "Add"),
+        ("selection_subtract", "Subtract"),
+        ("selection_intersect", "Intersect"),
+        ("tolerance", "Tolerance"),
+        ("select_all", "Select All"),
+        ("deselect", "Deselect"),
+        ("invert_selection", "Invert Selection"),
+        ("menu_edit", "Edit"),
+        ("cut", "Cut"),
+        ("copy", "Copy"),
+        ("paste", "Paste"),
+        ("paste_as_layer", "Paste as New Layer"),
+        ("commit_paste", "Commit Paste"),
+        ("cancel_paste", "Cancel Paste"),
+        ("pasted_layer", "Pasted Layer"),
+        ("action_cut", "Cut"),
+        ("action_paste", "Paste"),
+        ("action_paste_layer", "Paste as layer"),
+        ("tool_transform", "Free Transform"),
+        ("transform", "Transform"),
+        ("transform_unavailable", "The active layer is locked"),
+        ("interpolation_nearest", "Nearest neighbor"),
+        ("interpolation_bilinear", "Bilinear"),
+        ("interpolation_bicubic", "Bicubic"),
+        ("flip_horizontal", "Flip Horizontal"),
+        ("flip_vertical", "Flip Vertical"),
+        ("apply", "Apply"),
+        ("action_transform", "Transform"),
+        ("menu_image", "Image"),
+        ("canvas_size", "Canvas Size"),
+        ("image_size", "Image Size"),
+        ("current_size", "Current size"),
+        ("keep_ratio", "Keep proportions"),
+        ("anchor", "Anchor"),
+        ("resampling", "Resampling"),
+        ("interpolation_lanczos", "Lanczos"),
+        ("action_canvas_size", "Canvas size"),
+        ("action_image_size", "Image size"),
+        ("rotate_cw90", "Rotate 90° Clockwise"),
+        ("rotate_180", "Rotate 180°"),
+        ("rotate_ccw90", "Rotate 90° Counter-clockwise"),
+        ("rotate_arbitrary", "Arbitrary Rotation"),
+        ("angle", "Angle:"),
+        ("angle_hint", "Positive values rotate clockwise"),
+        ("active_layer", "Active Layer"),
+        ("action_rotate_image", "Rotate image"),
+        ("action_flip_image", "Flip image"),
+        ("action_rotate_layer", "Rotate layer"),
+        ("action_flip_layer", "Flip layer"),
+        ("tool_crop", "Crop"),
+        ("crop", "Crop"),
+        ("lock_ratio", "Fixed ratio"),
+        ("crop_to_content", "Crop to Layer Content"),
+        ("trim", "Trim"),
+        ("action_crop", "Crop"),
+        ("menu_adjustments", "Adjustments"),
+        ("brightness_contrast", "Brightness/Contrast"),
+        ("brightness", "Brightness:"),
+        ("contrast", "Contrast:"),
+        ("hue_saturation", "Hue/Saturation/Lightness"),
+        ("hue", "Hue:"),
+        ("saturation", "Saturation:"),
+        ("lightness", "Lightness:"),
+        ("levels", "Levels"),
+        ("input_levels", "Input levels"),
+        ("output_levels", "Output levels"),
+        ("curves", "Curves"),
+        ("curves_hint", "Click to add a point, drag it to move it, right-click to remove it"),
+        ("channel_rgb", "RGB"),
+        ("channel_red", "Red"),
+        ("channel_green", "Green"),
+        ("channel_blue", "Blue"),
+        ("invert_colors", "Invert Colors"),
+        ("desaturate", "Desaturate"),
+        ("posterize", "Posterize"),
+        ("posterize_levels", "Levels:"),
+        ("threshold", "Threshold"),
+        ("threshold_level", "Threshold:"),
+        ("preview", "Preview"),
+        ("reset", "Reset"),
+        ("adjustment_unavailable", "The active layer cannot be edited"),
+        ("menu_filters", "Filters"),
+        ("gaussian_blur", "Gaussian Blur"),
+        ("box_blur", "Box Blur"),
+        ("motion_blur", "Motion Blur"),
+        ("unsharp_mask", "Unsharp Mask"),
+        ("edge_detect", "Edge Detection"),
+        ("emboss", "Emboss"),
+        ("radius", "Radius:"),
+        ("length", "Length:"),
+        ("amount", "Amount:"),
+        ("strength", "Strength:"),
+        ("lock_pixels", "Lock pixels"),
+        ("lock_alpha", "Lock transparency"),
+        ("lock_position", "Lock position"),
+        ("blend_normal", "Normal"),
+        ("blend_add", "Add"),
+        ("blend_multiply", "Multiply"),
+        ("blend_screen", "Screen"),
+        ("blend_overlay", "Overlay"),
+        ("blend_darken", "Darken"),
+        ("blend_lighten", "Lighten"),
+        ("blend_color_dodge", "Color Dodge"),
+        ("blend_color_burn", "Color Burn"),
+        ("blend_soft_light", "Soft Light"),
+        ("blend_hue", "Hue"),
+        ("blend_saturation", "Saturation"),
+        ("blend_color", "Color"),
+        ("blend_luminosity", "Luminosity"),
+        ("blend_behind", "Behind"),
         ("delete", "Delete"),
     ].iter().cloned().collect();
     
@@ -4,30 +4,49 @@ mod brush_system;
 mod ui_theme;
 mod ui_icons;
 mod assets;
+mod selection_tool;
+mod clipboard;
+mod transform_tool;
+mod size_dialog;
+mod rotate_dialog;
+mod crop_tool;
+mod adjustment_dialog;
+mod filter_dialog;
+mod live_preview;
+mod brush_presets;
 
 use eframe::egui;
 use egui::{Color32, TextureHandle, TextureOptions, Rect, Pos2, Vec2, Stroke, RichText};
-use image::{ImageBuffer, Rgba, ImageFormat};
-use std::collections::VecDeque;
+use rayon::prelude::*;
 use rfd::FileDialog;
+use std::path::Path;
 use std::time::Instant;
-use std::path::PathBuf;
-use std::fs;
-use std::io::Write;
 use serde::{Serialize, Deserialize};
 
+use rustique_core::{ActionKind, Adjustment, AdjustmentKind, Color, Document, DocumentSettings, EditTarget, FileError, FileFormat, Filter, FilterKind, FlipAxis, LayerKind, LayerLocks, QuarterTurn, RustiqueFile};
+use rustique_core::{compositing, file_format};
+
 use main_menu::MainMenu;
 use localization::{Language, get_text};
-use brush_system::BrushManager;
+use brush_system::{BlendMode, BrushManager, BrushType, PresetPack};
 use assets::Assets;
 use ui_theme::RustiqueTheme;
 use ui_icons::ToolIcons;
+use selection_tool::SelectionTool;
+use clipboard::AppClipboard;
+use transform_tool::TransformTool;
+use size_dialog::{DialogOutcome, SizeDialog, SizeDialogKind};
+use rotate_dialog::RotateDialog;
+use crop_tool::CropTool;
+use adjustment_dialog::{AdjustmentDialog, adjustment_name_key};
+use filter_dialog::{FilterDialog, filter_name_key};
+use brush_presets::PresetBrowser;
 
-const MAX_UNDO_STEPS: usize = 20;
 const CHECKERBOARD_SIZE: usize = 8;
 const WINDOW_WIDTH: f32 = 1200.0;
 const WINDOW_HEIGHT: f32 = 800.0;
 const MAX_SAVED_COLORS: usize = 16;
+const MEGABYTE: usize = 1024 * 1024;
 
 #[derive(PartialEq, Clone, Copy, Serialize, Deserialize)]
 enum Tool {
@@ -36,45 +55,10 @@ enum Tool {
     PaintBucket,
     ColorPicker,
     Line,
-}
-
-#[derive(Debug, Clone, Copy, PartialEq)]
-enum FileFormat {
-    Png,
-    Jpeg,
-    Bmp,
-    Tiff,
-    Gif,
-    WebP,
-    Rustiq,
-    Unknown,
-}
-
-impl FileFormat {
-    fn from_extension(ext: &str) -> Self {
-        match ext.to_lowercase().as_str() {
-            "png" => FileFormat::Png,
-            "jpg" | "jpeg" => FileFormat::Jpeg,
-            "bmp" => FileFormat::Bmp,
-            "tiff" | "tif" => FileFormat::Tiff,
-            "gif" => FileFormat::Gif,
-            "webp" => FileFormat::WebP,
-            "rustiq" => FileFormat::Rustiq,
-            _ => FileFormat::Unknown,
-        }
-    }
-    
-    fn get_image_format(&self) -> Option<ImageFormat> {
-        match self {
-            FileFormat::Png => Some(ImageFormat::Png),
-            FileFormat::Jpeg => Some(ImageFormat::Jpeg),
-            FileFormat::Bmp => Some(ImageFormat::Bmp),
-            FileFormat::Tiff => Some(ImageFormat::Tiff),
-            FileFormat::Gif => Some(ImageFormat::Gif),
-            FileFormat::WebP => Some(ImageFormat::WebP),
-            _ => None,
-        }
-    }
+    Select,
+    MagicWand,
+    Transform,
+    Crop,
 }
 
 enum AppState {
@@ -82,99 +66,54 @@ enum AppState {
     Canvas(PaintApp),
 }
 
-#[derive(Clone, PartialEq)]
-struct Layer {
-    name: String,
-    data: Vec<Option<Color32>>,
-    visible: bool,
+fn to_color32(color: Color) -> Color32 {
+    Color32::from_rgba_unmultiplied(color.r(), color.g(), color.b(), color.a())
 }
 
-#[derive(Serialize, Deserialize)]
-struct LayerData {
-    name: String,
-    data: Vec<Option<[u8; 4]>>,
-    visible: bool,
+fn from_color32(color: Color32) -> Color {
+    let [r, g, b, a] = color.to_srgba_unmultiplied();
+    Color::from_rgba_unmultiplied(r, g, b, a)
 }
 
-#[derive(Serialize, Deserialize)]
-struct RustiqueFile {
-    width: usize,
-    height: usize,
-    layers: Vec<LayerData>,
-    active_layer_index: usize,
-    primary_color: [u8; 4],
-    secondary_color: [u8; 4],
-    saved_colors: Vec<[u8; 4]>,
-    brush_size: i32,
-    eraser_size: i32,
-}
-
-#[derive(Clone)]
-struct CanvasState {
-    width: usize,
-    height: usize,
-    layers: Vec<Layer>,
-    active_layer_index: usize,
-}
-
-impl CanvasState {
-    fn new(width: usize, height: usize) -> Self {
-        let default_layer = Layer {
-            name: "Background".to_string(),
-            data: vec![None; width * height],
-            visible: true,
-        };
-        
-        Self {
-            width,
-            height,
-            layers: vec![default_layer],
-            active_layer_index: 0,
-        }
-    }
-    
-    #[inline]
-    fn get(&self, x: usize, y: usize) -> Option<Color32> {
-        if x < self.width && y < self.height {
-            for layer_index in (0..self.layers.len()).rev() {
-                let layer = &self.layers[layer_index];
-                if layer.visible {
-                    let idx = y * self.width + x;
-                    if let Some(color) = layer.data[idx] {
-                        return Some(color);
-                    }
-                }
-            }
-        }
-        None
-    }
-    
-    #[inline]
-    fn get_from_active_layer(&self, x: usize, y: usize) -> Option<Color32> {
-        if x < self.width && y < self.height && self.active_layer_index < self.layers.len() {
-            let idx = y * self.width + x;
-            self.layers[self.active_layer_index].data[idx]
-        } else {
-            None
-        }
-    }
-    
-    #[inline]
-    fn set(&mut self, x: usize, y: usize, color: Option<Color32>) {
-        if x < self.width && y < self.height && self.active_layer_index < self.layers.len() {
-            let idx = y * self.width + x;
-            self.layers[self.active_layer_index].data[idx] = color;
-        }
-    }
-}
-
-#[derive(Clone)]
-struct CanvasChange {
-    x: usize,
-    y: usize,
-    layer_index: usize,
-    old_color: Option<Color32>,
-    new_color: Option<Color32>,
+fn action_name(action: ActionKind, language: Language) -> String {
+    let key = match action {
+        ActionKind::BrushStroke(brush_type) => {
+            return format!("{} ({})", get_text("action_brush_stroke", language), brush_system::brush_type_name(brush_type, language));
+        },
+        ActionKind::Erase => "action_erase",
+        ActionKind::BucketFill => "action_bucket_fill",
+        ActionKind::AddLayer => "action_add_layer",
+        ActionKind::RemoveLayer => "action_remove_layer",
+        ActionKind::MoveLayer => "action_move_layer",
+        ActionKind::GroupLayer => "action_group_layer",
+        ActionKind::RenameLayer => "action_rename_layer",
+        ActionKind::LayerVisibility => "action_layer_visibility",
+        ActionKind::LayerOpacity => "action_layer_opacity",
+        ActionKind::LayerBlendMode => "action_layer_blend_mode",
+        ActionKind::LayerLocks => "action_layer_locks",
+        ActionKind::AddLayerMask => "action_add_mask",
+        ActionKind::RemoveLayerMask => "action_remove_mask",
+        ActionKind::ToggleLayerMask => "action_toggle_mask",
+        ActionKind::LayerClipping => "action_clipping",
+        ActionKind::DuplicateLayer => "duplicate_layer",
+        ActionKind::MergeDown => "merge_down",
+        ActionKind::MergeVisible => "merge_visible",
+        ActionKind::Flatten => "flatten_image",
+        ActionKind::Cut => "action_cut",
+        ActionKind::Paste => "action_paste",
+        ActionKind::PasteAsLayer => "action_paste_layer",
+        ActionKind::Transform => "action_transform",
+        ActionKind::CanvasSize => "action_canvas_size",
+        ActionKind::ImageSize => "action_image_size",
+        ActionKind::RotateImage => "action_rotate_image",
+        ActionKind::FlipImage => "action_flip_image",
+        ActionKind::RotateLayer => "action_rotate_layer",
+        ActionKind::FlipLayer => "action_flip_layer",
+        ActionKind::Crop => "action_crop",
+        ActionKind::Adjustment(kind) => adjustment_name_key(kind),
+        ActionKind::Filter(kind) => filter_name_key(kind),
+    };
+    get_text(key, language)
 }
 
 enum SaveDialog {
@@ -185,10 +124,7 @@ enum SaveDialog {
 }
 
 struct PaintApp {
-    current_state: CanvasState,
-    undo_stack: Vec<Vec<CanvasChange>>,
-    redo_stack: Vec<Vec<CanvasChange>>,
-    current_changes: Vec<CanvasChange>,
+    document: Document,
     current_tool: Tool,
     primary_color: Color32,
     secondary_color: Color32,
@@ -197,6 +133,7 @@ struct PaintApp {
     brush_size: i32,
     eraser_size: i32,
     brush_manager: BrushManager,
+    preset_browser: PresetBrowser,
     last_position: Option<(i32, i32)>,
     is_drawing: bool,
     last_action_time: Instant,
@@ -219,112 +156,54 @@ struct PaintApp {
     last_cursor_time: Option<f64>,
     velocity_sensitivity: f32,
     max_velocity_for_min_pressure: f32,
+    selection_tool: SelectionTool,
+    /// Drag of the floating selection not yet moved by a whole pixel.
+    floating_drag: Vec2,
+    transform_tool: TransformTool,
+    crop_tool: CropTool,
+    size_dialog: Option<SizeDialog>,
+    rotate_dialog: Option<RotateDialog>,
+    adjustment_dialog: Option<AdjustmentDialog>,
+    filter_dialog: Option<FilterDialog>,
 }
 
 impl PaintApp {
     fn new(width: u32, height: u32, language: Language) -> Self {
-        let initial_state = CanvasState::new(width as usize, height as usize);
-        Self {
-            current_state: initial_state,
-            undo_stack: Vec::new(),
-            redo_stack: Vec::new(),
-            current_changes: Vec::new(),
-            current_tool: Tool::Brush,
-            primary_color: Color32::BLACK,
-            secondary_color: Color32::WHITE,
-            using_secondary_color: false,
-            saved_colors: Vec::new(),
-            brush_size: 3,
-            eraser_size: 3,
-            brush_manager: BrushManager::new(),
-            last_position: None,
-            is_drawing: false,
-            last_action_time: Instant::now(),
-            texture: None,
-            texture_dirty: true,
-            zoom: 1.0,
-            pan: Vec2::ZERO,
-            line_start: None,
-            line_end: None,
-            is_drawing_line: false,
-            is_first_click_line: true,
-            has_unsaved_changes: false,
-            last_save_path: None,
-            save_dialog: SaveDialog::Hidden,
-            language,
-            current_pressure: 1.0,
-            pressure_smoothing: 0.8,
-            pressure_enabled: false,
-            last_cursor_pos: None,
-            last_cursor_time: None,
-            velocity_sensitivity: 0.9,
-            max_velocity_for_min_pressure: 1900.0,
-        }
+        Self::with_document(Document::new(width as usize, height as usize), DocumentSettings::default(), language)
     }
 
-    fn from_rustiq_file(file: RustiqueFile, language: Language) -> Self {
-        let mut canvas = CanvasState {
-            width: file.width,
-            height: file.height,
-            layers: Vec::with_capacity(file.layers.len()),
-            active_layer_index: file.active_layer_index,
-        };
-        
-        for layer_data in file.layers {
-            let mut layer = Layer {
-                name: layer_data.name,
-                data: Vec::with_capacity(layer_data.data.len()),
-                visible: layer_data.visible,
-            };
-            
-            for pixel_opt in layer_data.data {
-                if let Some(rgba) = pixel_opt {
-                    layer.data.push(Some(Color32::from_rgba_unmultiplied(rgba[0], rgba[1], rgba[2], rgba[3])));
-                } else {
-                    layer.data.push(None);
-                }
+    fn with_document(mut document: Document, settings: DocumentSettings, language: Language) -> Self {
+        let mut brush_manager = BrushManager::new();
+        if let Some(dir) = brush_system::user_brush_dir() {
+            for brush in brush_system::load_brush_library(&dir) {
+                brush_manager.import_brush(brush);
             }
-            
-            canvas.layers.push(layer);
         }
-        
-        let primary_color = Color32::from_rgba_unmultiplied(
-            file.primary_color[0],
-            file.primary_color[1],
-            file.primary_color[2],
-            file.primary_color[3]
-        );
-        
-        let secondary_color = Color32::from_rgba_unmultiplied(
-            file.secondary_color[0],
-            file.secondary_color[1],
-            file.secondary_color[2],
-            file.secondary_color[3]
-        );
-        
-        let mut saved_colors = Vec::with_capacity(file.saved_colors.len());
-        for color_data in file.saved_colors {
-            saved_colors.push(Color32::from_rgba_unmultiplied(
-                color_data[0],
-                color_data[1],
-                color_data[2],
-                color_data[3]
-            ));
+        for tip in settings.brush_tips {
+            document.mark_tip_used(tip.id());
+            brush_manager.add_tip(tip);
         }
-        
+        for hose in settings.brush_hoses {
+            document.mark_tip_used(hose.id());
+            brush_manager.add_hose(hose);
+        }
+        if let Some(path) = brush_system::user_presets_path()
+            && let Ok(pack) = PresetPack::load(path)
+        {
+            brush_manager.add_preset_pack(pack);
+        }
+
         Self {
-            current_state: canvas,
-            undo_stack: Vec::new(),
-            redo_stack: Vec::new(),
-            current_changes: Vec::new(),
+            document,
             current_tool: Tool::Brush,
-            primary_color,
-            secondary_color,
+            primary_color: to_color32(settings.primary_color),
+            secondary_color: to_color32(settings.secondary_color),
             using_secondary_color: false,
-            saved_colors,
-            brush_size: file.brush_size,
-            eraser_size: file.eraser_size,
-            brush_manager: BrushManager::new(),
+            saved_colors: settings.saved_colors.into_iter().map(to_color32).collect(),
+            brush_size: settings.brush_size,
+            eraser_size: settings.eraser_size,
+            brush_manager,
+            preset_browser: PresetBrowser::default(),
             last_position: None,
             is_drawing: false,
             last_action_time: Instant::now(),
@@ -347,18 +226,50 @@ impl PaintApp {
             last_cursor_time: None,
             velocity_sensitivity: 0.9,
             max_velocity_for_min_pressure: 1900.0,
+            selection_tool: SelectionTool::default(),
+            floating_drag: Vec2::ZERO,
+            transform_tool: TransformTool::default(),
+            crop_tool: CropTool::default(),
+            size_dialog: None,
+            rotate_dialog: None,
+            adjustment_dialog: None,
+            filter_dialog: None,
+        }
+    }
+
+    fn from_rustiq_file(file: RustiqueFile, language: Language) -> Self {
+        let (canvas, settings) = file.into_parts();
+        Self::with_document(Document::from_canvas(canvas), settings, language)
+    }
+
+    fn document_settings(&self) -> DocumentSettings {
+        let (brush_tips, brush_hoses) = self.brush_manager.embedded_tips(&self.document.used_tips());
+        DocumentSettings {
+            primary_color: from_color32(self.primary_color),
+            secondary_color: from_color32(self.secondary_color),
+            saved_colors: self.saved_colors.iter().copied().map(from_color32).collect(),
+            brush_size: self.brush_size,
+            eraser_size: self.eraser_size,
+            brush_tips,
+            brush_hoses,
         }
     }
 
-    fn detect_format(path: &str) -> FileFormat {
-        PathBuf::from(path)
-            .extension()
-            .and_then(|ext| ext.to_str())
-            .map(FileFormat::from_extension)
-            .unwrap_or(FileFormat::Unknown)
+    fn file_error_message(error: FileError, language: Language) -> String {
+        match error {
+            FileError::UnsupportedFormat(path) => format!("{}: {}", get_text("format_not_supported", language), path),
+            FileError::Read(e) => format!("{}: {}", get_text("error_reading_file", language), e),
+            FileError::Parse(e) => format!("{}: {}", get_text("error_reading_rustiq", language), e),
+            FileError::ImageDecode(e) => format!("{}: {}", get_text("unable_to_open_image", language), e),
+            FileError::ImageEncode(e) => format!("{}: {}", get_text("error_saving_image", language), e),
+            FileError::Serialize(e) => format!("Erreur de sérialisation: {}", e),
+            FileError::Create(e) => format!("Erreur de création du fichier: {}", e),
+            FileError::Write(e) => format!("Erreur d'écriture: {}", e),
+            FileError::InvalidBrush(reason) => format!("{}: {}", get_text("invalid_brush_file", language), reason),
+            FileError::InvalidPresets(e) => format!("{}: {}", get_text("invalid_preset_file", language), e),
+        }
     }
     
-    
     fn update_pressure_from_velocity(&mut self, cursor_pos: Pos2, current_time: f64) {
         if !self.pressure_enabled {
             self.current_pressure = 1.0;
@@ -394,189 +305,49 @@ impl PaintApp {
     }
     
     fn save_file(&mut self, path: &str) -> Result<(), String> {
-        let format = Self::detect_format(path);
-        
-        match format {
-            FileFormat::Rustiq => self.save_as_rustiq(path),
-            FileFormat::Unknown => {
-                Err(format!("{}: {}", get_text("format_not_supported", self.language), path))
+        self.commit_floating();
+        let result = match FileFormat::detect(path) {
+            FileFormat::Rustiq => {
+                RustiqueFile::from_canvas(&self.document.canvas, &self.document_settings()).save(path)
             },
-            _ => {
-                if let Some(image_format) = format.get_image_format() {
-                    self.save_as_image(path, image_format)
-                } else {
-                    Err(format!("{}: {}", get_text("format_not_supported", self.language), path))
-                }
+            format => match format.get_image_format() {
+                Some(image_format) => file_format::export_image(&self.document.canvas, path, image_format),
+                None => Err(FileError::UnsupportedFormat(path.to_string())),
             }
-        }
-    }
-    
-    fn save_as_image(&mut self, path: &str, format: ImageFormat) -> Result<(), String> {
-        let width = self.current_state.width;
-        let height = self.current_state.height;
-        let mut img = ImageBuffer::new(width as u32, height as u32);
-        
-        for y in 0..height {
-            for x in 0..width {
-                let color = self.current_state.get(x, y).unwrap_or(Color32::TRANSPARENT);
-                img.put_pixel(x as u32, y as u32, Rgba([color.r(), color.g(), color.b(), color.a()]));
-            }
-        }
+        };
 
-        match img.save_with_format(path, format) {
+        match result {
             Ok(_) => {
                 self.has_unsaved_changes = false;
                 self.last_save_path = Some(path.to_string());
                 Ok(())
             },
-            Err(e) => Err(format!("{}: {}", get_text("error_saving_image", self.language), e)),
+            Err(e) => Err(Self::file_error_message(e, self.language)),
         }
     }
     
     fn open_file(path: &str, language: Language) -> Result<Self, String> {
-        let format = Self::detect_format(path);
-        
-        match format {
+        let mut app = match FileFormat::detect(path) {
             FileFormat::Rustiq => {
-                match fs::read_to_string(path) {
-                    Ok(content) => {
-                        match serde_json::from_str::<RustiqueFile>(&content) {
-                            Ok(file) => {
-                                let mut app = Self::from_rustiq_file(file, language);
-                                app.last_save_path = Some(path.to_string());
-                                Ok(app)
-                            },
-                            Err(e) => Err(format!("{}: {}", get_text("error_reading_rustiq", language), e))
-                        }
-                    },
-                    Err(e) => Err(format!("{}: {}", get_text("error_reading_file", language), e))
-                }
+                let file = RustiqueFile::load(path).map_err(|e| Self::file_error_message(e, language))?;
+                Self::from_rustiq_file(file, language)
             },
             FileFormat::Unknown => {
-                Err(format!("{}: {}", get_text("format_not_supported", language), path))
+                return Err(Self::file_error_message(FileError::UnsupportedFormat(path.to_string()), language));
             },
             _ => {
-                match image::open(path) {
-                    Ok(img) => {
-                        let width = img.width() as usize;
-                        let height = img.height() as usize;
-                        let mut canvas = CanvasState::new(width, height);
-                        
-                        let rgba_img = img.to_rgba8();
-                        for y in 0..height {
-                            for x in 0..width {
-                                let pixel = rgba_img.get_pixel(x as u32, y as u32);
-                                if pixel[3] > 0 {
-                                    let color = Color32::from_rgba_unmultiplied(pixel[0], pixel[1], pixel[2], pixel[3]);
-                                    canvas.set(x, y, Some(color));
-                                }
-                            }
-                        }
-                        
-                        let app = Self {
-                            current_state: canvas,
-                            undo_stack: Vec::new(),
-                            redo_stack: Vec::new(),
-                            current_changes: Vec::new(),
-                            current_tool: Tool::Brush,
-                            primary_color: Color32::BLACK,
-                            secondary_color: Color32::WHITE,
-                            using_secondary_color: false,
-                            saved_colors: Vec::new(),
-                            brush_size: 3,
-                            eraser_size: 3,
-                            brush_manager: BrushManager::new(),
-                            last_position: None,
-                            is_drawing: false,
-                            last_action_time: Instant::now(),
-                            texture: None,
-                            texture_dirty: true,
-                            zoom: 1.0,
-                            pan: Vec2::ZERO,
-                            line_start: None,
-                            line_end: None,
-                            is_drawing_line: false,
-                            is_first_click_line: true,
-                            has_unsaved_changes: false,
-                            last_save_path: Some(path.to_string()),
-                            save_dialog: SaveDialog::Hidden,
-                            language,
-                            current_pressure: 1.0,
-                            pressure_smoothing: 0.3,
-                            pressure_enabled: true,
-                            last_cursor_pos: None,
-                            last_cursor_time: None,
-                            velocity_sensitivity: 0.8,
-                            max_velocity_for_min_pressure: 800.0,
-                        };
-                        
-                        Ok(app)
-                    },
-                    Err(e) => Err(format!("{}: {}", get_text("unable_to_open_image", language), e))
-                }
+                let canvas = file_format::import_image(path).map_err(|e| Self::file_error_message(e, language))?;
+                let mut app = Self::with_document(Document::from_canvas(canvas), DocumentSettings::default(), language);
+                app.pressure_smoothing = 0.3;
+                app.pressure_enabled = true;
+                app.velocity_sensitivity = 0.8;
+                app.max_velocity_for_min_pressure = 800.0;
+                app
             }
-        }
-    }
-    
-    fn save_as_rustiq(&mut self, path: &str) -> Result<(), String> {
-        let mut layers = Vec::with_capacity(self.current_state.layers.len());
-        
-        for layer in &self.current_state.layers {
-            let mut layer_data = Vec::with_capacity(layer.data.len());
-            
-            for &pixel_opt in &layer.data {
-                match pixel_opt {
-                    Some(color) => {
-                        layer_data.push(Some([color.r(), color.g(), color.b(), color.a()]));
-                    },
-                    None => {
-                        layer_data.push(None);
-                    }
-                }
-            }
-            
-            layers.push(LayerData {
-                name: layer.name.clone(),
-                data: layer_data,
-                visible: layer.visible,
-            });
-        }
-        
-        let mut saved_colors = Vec::with_capacity(self.saved_colors.len());
-        for &color in &self.saved_colors {
-            saved_colors.push([color.r(), color.g(), color.b(), color.a()]);
-        }
-        
-        let rustiq_file = RustiqueFile {
-            width: self.current_state.width,
-            height: self.current_state.height,
-            layers,
-            active_layer_index: self.current_state.active_layer_index,
-            primary_color: [self.primary_color.r(), self.primary_color.g(), self.primary_color.b(), self.primary_color.a()],
-            secondary_color: [self.secondary_color.r(), self.secondary_color.g(), self.secondary_color.b(), self.secondary_color.a()],
-            saved_colors,
-            brush_size: self.brush_size,
-            eraser_size: self.eraser_size,
-        };
-        
-        let json = match serde_json::to_string(&rustiq_file) {
-            Ok(json) => json,
-            Err(e) => return Err(format!("Erreur de sérialisation: {}", e)),
         };
-        
-        match fs::File::create(path) {
-            Ok(mut file) => {
-                match file.write_all(json.as_bytes()) {
-                    Ok(_) => {
-                        self.has_unsaved_changes = false;
-                        self.last_save_path = Some(path.to_string());
-                        Ok(())
-                    },
-                    Err(e) => Err(format!("Erreur d'écriture: {}", e)),
-                }
-            },
-            Err(e) => Err(format!("Erreur de création du fichier: {}", e)),
-        }
+
+        app.last_save_path = Some(path.to_string());
+        Ok(app)
     }
     
     fn quick_save(&mut self) -> Result<(), String> {
@@ -588,71 +359,125 @@ impl PaintApp {
         }
     }
 
-    fn add_layer(&mut self, name: String) {
-        self.current_state.layers.push(Layer {
-            name,
-            data: vec![None; self.current_state.width * self.current_state.height],
-            visible: true,
-        });
-        self.current_state.active_layer_index = self.current_state.layers.len() - 1;
+    fn mark_layers_changed(&mut self) {
         self.texture_dirty = true;
         self.has_unsaved_changes = true;
     }
+
+    fn add_layer(&mut self, name: String) {
+        if self.document.add_layer(name) {
+            self.mark_layers_changed();
+        }
+    }
     
     fn remove_layer(&mut self, index: usize) {
-        if self.current_state.layers.len() > 1 && index < self.current_state.layers.len() {
-            self.current_state.layers.remove(index);
-            if self.current_state.active_layer_index >= self.current_state.layers.len() {
-                self.current_state.active_layer_index = self.current_state.layers.len() - 1;
-            }
-            self.texture_dirty = true;
-            self.has_unsaved_changes = true;
+        if self.document.remove_layer(index) {
+            self.mark_layers_changed();
         }
     }
     
     fn move_layer_up(&mut self, index: usize) {
-        if index > 0 && index < self.current_state.layers.len() {
-            self.current_state.layers.swap(index, index - 1);
-            if self.current_state.active_layer_index == index {
-                self.current_state.active_layer_index -= 1;
-            } else if self.current_state.active_layer_index == index - 1 {
-                self.current_state.active_layer_index += 1;
-            }
-            self.texture_dirty = true;
-            self.has_unsaved_changes = true;
+        if self.document.move_layer_up(index) {
+            self.mark_layers_changed();
         }
     }
     
     fn move_layer_down(&mut self, index: usize) {
-        if index < self.current_state.layers.len() - 1 {
-            self.current_state.layers.swap(index, index + 1);
-            if self.current_state.active_layer_index == index {
-                self.current_state.active_layer_index += 1;
-            } else if self.current_state.active_layer_index == index + 1 {
-                self.current_state.active_layer_index -= 1;
-            }
-            self.texture_dirty = true;
-            self.has_unsaved_changes = true;
+        if self.document.move_layer_down(index) {
+            self.mark_layers_changed();
         }
     }
     
     fn toggle_layer_visibility(&mut self, index: usize) {
-        if index < self.current_state.layers.len() {
-            self.current_state.layers[index].visible = !self.current_state.layers[index].visible;
-            self.texture_dirty = true;
-            self.has_unsaved_changes = true;
+        if self.document.toggle_layer_visibility(index) {
+            self.mark_layers_changed();
+        }
+    }
+    
+    fn duplicate_layer(&mut self, index: usize) {
+        if self.document.duplicate_layer(index) {
+            self.mark_layers_changed();
+        }
+    }
+    
+    fn merge_down(&mut self, index: usize) {
+        if self.document.merge_down(index) {
+            self.mark_layers_changed();
         }
     }
     
+    fn merge_visible(&mut self) {
+        if self.document.merge_visible() {
+            self.mark_layers_changed();
+        }
+    }
+    
+    fn flatten(&mut self) {
+        if self.document.flatten() {
+            self.mark_layers_changed();
+        }
+    }
+    
+    fn add_layer_mask(&mut self, index: usize) {
+        if self.document.add_layer_mask(index) {
+            self.document.canvas.edit_target = EditTarget::Mask;
+            self.mark_layers_changed();
+        }
+    }
+    
+    fn remove_layer_mask(&mut self, index: usize) {
+        if self.document.remove_layer_mask(index) {
+            self.mark_layers_changed();
+        }
+    }
+    
+    fn toggle_layer_mask_enabled(&mut self, index: usize) {
+        if self.document.toggle_layer_mask_enabled(index) {
+            self.mark_layers_changed();
+        }
+    }
+    
+    fn toggle_layer_clipping(&mut self, index: usize) {
+        if self.document.toggle_layer_clipping(index) {
+            self.mark_layers_changed();
+        }
+    }
+    
+    fn group_layer(&mut self, index: usize) {
+        let group_count = self.document.canvas.layers.iter().filter(|layer| layer.is_group()).count();
+        if self.document.group_layer(index, format!("Group {}", group_count + 1)) {
+            self.mark_layers_changed();
+        }
+    }
+    
+    fn toggle_group_expanded(&mut self, index: usize) {
+        self.document.canvas.toggle_group_expanded(index);
+    }
+    
     fn set_active_layer(&mut self, index: usize) {
-        if index < self.current_state.layers.len() {
-            self.current_state.active_layer_index = index;
+        self.document.canvas.set_active_layer(index);
+    }
+    
+    fn set_layer_opacity(&mut self, index: usize, opacity: f32) {
+        if self.document.set_layer_opacity(index, opacity) {
+            self.mark_layers_changed();
+        }
+    }
+    
+    fn set_layer_blend_mode(&mut self, index: usize, blend_mode: BlendMode) {
+        if self.document.set_layer_blend_mode(index, blend_mode) {
+            self.mark_layers_changed();
+        }
+    }
+    
+    fn set_layer_locks(&mut self, index: usize, locks: LayerLocks) {
+        if self.document.set_layer_locks(index, locks) {
+            self.has_unsaved_changes = true;
         }
     }
     
     fn rename_layer(&mut self, index: usize, name: String) {
-        if index < self.current_state.layers.len() {
-            self.current_state.layers[index].name = name;
+        if self.document.rename_layer(index, name) {
             self.has_unsaved_changes = true;
         }
     }
@@ -671,357 +496,230 @@ impl PaintApp {
             self.saved_colors.remove(index);
         }
     }
-    
-    fn record_change(&mut self, x: usize, y: usize, new_color: Option<Color32>) {
-        if x < self.current_state.width && y < self.current_state.height {
-            let old_color = self.current_state.get_from_active_layer(x, y);
-            if old_color != new_color {
-                self.current_changes.push(CanvasChange {
-                    x, 
-                    y, 
-                    layer_index: self.current_state.active_layer_index,
-                    old_color, 
-                    new_color
-                });
-                self.current_state.set(x, y, new_color);
-                self.has_unsaved_changes = true;
-            }
-        }
-    }
 
     fn save_state(&mut self) {
-        if !self.current_changes.is_empty() {
-            self.undo_stack.push(std::mem::take(&mut self.current_changes));
-            self.current_changes = Vec::new();
-            if self.undo_stack.len() > MAX_UNDO_STEPS {
-                self.undo_stack.remove(0);
-            }
-            self.redo_stack.clear();
+        if self.document.save_state() {
             self.is_drawing = false;
             self.has_unsaved_changes = true;
         }
     }
 
     fn undo(&mut self) {
-        if let Some(changes) = self.undo_stack.pop() {
-            let mut redo_changes = Vec::with_capacity(changes.len());
-            
-            for change in changes.iter().rev() {
-                let layer_index_backup = self.current_state.active_layer_index;
-                self.current_state.active_layer_index = change.layer_index;
-                
-                redo_changes.push(CanvasChange {
-                    x: change.x,
-                    y: change.y,
-                    layer_index: change.layer_index,
-                    old_color: change.old_color,
-                    new_color: change.new_color,
-                });
-                
-                self.current_state.set(change.x, change.y, change.old_color);
-                self.current_state.active_layer_index = layer_index_backup;
-            }
-            
-            self.redo_stack.push(redo_changes);
+        self.transform_tool.cancel();
+        if self.document.undo() {
+            self.selection_tool.selection_changed();
             self.texture_dirty = true;
             self.has_unsaved_changes = true;
         }
     }
 
     fn redo(&mut self) {
-        if let Some(changes) = self.redo_stack.pop() {
-            let mut undo_changes = Vec::with_capacity(changes.len());
-            
-            for change in changes.iter().rev() {
-                let layer_index_backup = self.current_state.active_layer_index;
-                self.current_state.active_layer_index = change.layer_index;
-                
-                let current_color = self.current_state.get_from_active_layer(change.x, change.y);
-                undo_changes.push(CanvasChange {
-                    x: change.x,
-                    y: change.y,
-                    layer_index: change.layer_index,
-                    old_color: current_color,
-                    new_color: change.new_color,
-                });
-                
-                self.current_state.set(change.x, change.y, change.new_color);
-                self.current_state.active_layer_index = layer_index_backup;
-            }
-            
-            self.undo_stack.push(undo_changes);
+        self.transform_tool.cancel();
+        if self.document.redo() {
+            self.selection_tool.selection_changed();
             self.texture_dirty = true;
             self.has_unsaved_changes = true;
         }
     }
 
-    fn draw_line(&mut self, start: (i32, i32), end: (i32, i32), _color: Color32) {
-        let color = if self.using_secondary_color { self.secondary_color } else { self.primary_color };
-        
-        let current_size = if self.current_tool == Tool::Eraser {
-            self.eraser_size as f32
-        } else {
-            self.brush_size as f32
-        };
-        
-        if self.brush_manager.current_size != current_size {
-            self.brush_manager.current_size = current_size;
+    fn select_all(&mut self) {
+        self.document.select_all();
+        self.selection_tool.selection_changed();
+    }
+
+    fn deselect(&mut self) {
+        self.document.deselect();
+        self.selection_tool.selection_changed();
+    }
+
+    fn invert_selection(&mut self) {
+        self.document.invert_selection();
+        self.selection_tool.selection_changed();
+    }
+
+    fn copy(&mut self, clipboard: &mut AppClipboard) {
+        if let Some(image) = self.document.copy() {
+            clipboard.set(image);
         }
-        
-        if self.current_state.active_layer_index < self.current_state.layers.len() &&
-           !self.current_state.layers[self.current_state.active_layer_index].visible {
-            return;
+    }
+
+    fn cut(&mut self, clipboard: &mut AppClipboard) {
+        if let Some(image) = self.document.cut() {
+            clipboard.set(image);
+            self.mark_layers_changed();
         }
-        
-        let pressure = self.get_effective_pressure();
-        let is_eraser = self.current_tool == Tool::Eraser;
-        
-        let mut changes = Vec::new();
+    }
+
+    fn paste(&mut self, clipboard: &mut AppClipboard) {
+        if let Some(image) = clipboard.get() {
+            self.document.paste(image);
+            self.selection_tool.selection_changed();
+            self.mark_layers_changed();
+        }
+    }
+
+    fn paste_as_layer(&mut self, clipboard: &mut AppClipboard) {
+        if let Some(image) = clipboard.get()
+            && self.document.paste_as_layer(image, get_text("pasted_layer", self.language))
         {
-            let mut record_change = |x: usize, y: usize, new_color: Option<Color32>| {
-                if is_eraser {
-                    changes.push((x, y, None));
-                } else {
-                    changes.push((x, y, new_color));
-                }
-            };
-            
-            self.brush_manager.draw_line(start, end, color, pressure, &mut record_change);
+            self.mark_layers_changed();
         }
-        
-        for (x, y, color) in changes {
-            self.record_change(x, y, color);
+    }
+
+    fn commit_floating(&mut self) {
+        if self.document.floating.is_some() {
+            self.document.commit_floating();
+            self.mark_layers_changed();
         }
-        
-        self.last_action_time = Instant::now();
-        self.texture_dirty = true;
     }
-    
-    fn draw_smooth_line(&mut self, start: (i32, i32), end: (i32, i32), _color: Color32) {
-        if self.brush_manager.current_size != self.brush_size as f32 {
-            self.brush_manager.current_size = self.brush_size as f32;
+
+    fn cancel_floating(&mut self) {
+        if self.document.cancel_floating() {
+            self.texture_dirty = true;
         }
-        
-        if self.current_state.active_layer_index < self.current_state.layers.len() &&
-           !self.current_state.layers[self.current_state.active_layer_index].visible {
-            return;
+    }
+
+    fn apply_transform(&mut self) {
+        if self.transform_tool.apply(&mut self.document) {
+            self.mark_layers_changed();
         }
-        
-        let (x0, y0) = start;
-        let (x1, y1) = end;
-        
-        let dx = (x1 - x0) as f32;
-        let dy = (y1 - y0) as f32;
-        let distance = (dx * dx + dy * dy).sqrt();
-        
-        if distance < 1.0 {
-            self.draw_point(x0, y0, false);
-            return;
+    }
+
+    fn cancel_transform(&mut self) {
+        self.transform_tool.cancel();
+        self.texture_dirty = true;
+    }
+
+    /// Refreshes everything tied to the canvas geometry after it changed.
+    fn canvas_reshaped(&mut self) {
+        self.transform_tool.cancel();
+        self.crop_tool.cancel();
+        self.selection_tool.selection_changed();
+        self.mark_layers_changed();
+    }
+
+    fn apply_crop(&mut self) {
+        if self.crop_tool.apply(&mut self.document) {
+            self.canvas_reshaped();
         }
-        
-        let active_brush = self.brush_manager.active_brush();
-        let base_spacing = active_brush.spacing * self.brush_manager.current_size;
-        
-        let spacing = match active_brush.brush_type {
-            brush_system::BrushType::Flat | brush_system::BrushType::Bright => {
-                (base_spacing * 0.2).max(0.2)
-            },
-            brush_system::BrushType::Rigger => {
-                (base_spacing * 0.1).max(0.1)
-            },
-            brush_system::BrushType::Fan => {
-                (base_spacing * 0.6).max(0.6)
-            },
-            brush_system::BrushType::Mop => {
-                (base_spacing * 0.3).max(0.3)
-            },
-            _ => {
-                (base_spacing * 0.3).max(0.3)
-            }
-        };
-        
-        let num_points = (distance / spacing).ceil() as i32;
-        
-        for i in 0..=num_points {
-            let t = if num_points > 0 { i as f32 / num_points as f32 } else { 0.0 };
-            
-            let x = x0 as f32 + dx * t;
-            let y = y0 as f32 + dy * t;
-            
-            self.draw_smooth_point(x, y, false);
+    }
+
+    fn crop_to_layer_content(&mut self) {
+        if self.document.crop_to_layer_content() {
+            self.canvas_reshaped();
         }
-        
-        self.last_action_time = Instant::now();
-        self.texture_dirty = true;
     }
-    
-    fn draw_smooth_point(&mut self, x: f32, y: f32, use_secondary: bool) {
-        let color = if use_secondary { self.secondary_color } else { self.primary_color };
-        
-        let current_size = if self.current_tool == Tool::Eraser {
-            self.eraser_size as f32
+
+    fn trim(&mut self) {
+        if self.document.trim() {
+            self.canvas_reshaped();
+        }
+    }
+
+    fn rotate(&mut self, turn: QuarterTurn, whole_image: bool) {
+        let changed = if whole_image {
+            self.document.rotate_image(turn)
         } else {
-            self.brush_size as f32
+            self.document.rotate_layer(self.document.canvas.active_layer_index, turn)
         };
-        
-        if self.brush_manager.current_size != current_size {
-            self.brush_manager.current_size = current_size;
-        }
-        
-        let x0 = x.floor() as i32;
-        let y0 = y.floor() as i32;
-        let x1 = x0 + 1;
-        let y1 = y0 + 1;
-        
-        let wx = x - x.floor();
-        let wy = y - y.floor();
-        
-        let weights = [
-            (1.0 - wx) * (1.0 - wy),
-            wx * (1.0 - wy),
-            (1.0 - wx) * wy,
-            wx * wy,
-        ];
-        
-        let positions = [(x0, y0), (x1, y0), (x0, y1), (x1, y1)];
-        
-        for (i, &(px, py)) in positions.iter().enumerate() {
-            if weights[i] > 0.0 {
-                let weighted_color = Color32::from_rgba_unmultiplied(
-                    color.r(),
-                    color.g(), 
-                    color.b(),
-                    ((color.a() as f32) * weights[i]) as u8
-                );
-                self.draw_weighted_point(px, py, weighted_color);
-            }
+        if changed {
+            self.canvas_reshaped();
         }
     }
-    
-    fn draw_weighted_point(&mut self, x: i32, y: i32, weighted_color: Color32) {
-        let current_size = if self.current_tool == Tool::Eraser {
-            self.eraser_size as f32
+
+    fn flip(&mut self, axis: FlipAxis, whole_image: bool) {
+        let changed = if whole_image {
+            self.document.flip_image(axis)
         } else {
-            self.brush_size as f32
+            self.document.flip_layer(self.document.canvas.active_layer_index, axis)
         };
-        
-        if self.brush_manager.current_size != current_size {
-            self.brush_manager.current_size = current_size;
+        if changed {
+            self.canvas_reshaped();
         }
-        
-        if self.current_state.active_layer_index < self.current_state.layers.len() &&
-           !self.current_state.layers[self.current_state.active_layer_index].visible {
-            return;
+    }
+
+    /// Rotate and flip entries of the Image menu, for the whole image or the
+    /// active layer.
+    fn orientation_menu(&mut self, ui: &mut egui::Ui, whole_image: bool) {
+        for turn in QuarterTurn::all() {
+            let key = match turn {
+                QuarterTurn::Cw90 => "rotate_cw90",
+                QuarterTurn::Cw180 => "rotate_180",
+                QuarterTurn::Cw270 => "rotate_ccw90",
+            };
+            if ui.button(get_text(key, self.language)).clicked() {
+                self.rotate(turn, whole_image);
+                ui.close_menu();
+            }
         }
-        
-        self.brush_manager.update_angle(x as f32, y as f32);
-        
-        let base_size = self.brush_manager.current_size;
-        let active_brush = self.brush_manager.active_brush();
-        
-        let mask_size = match active_brush.brush_type {
-            brush_system::BrushType::Flat | brush_system::BrushType::Bright => {
-                ((base_size * 2.0).max(7.0) as usize) | 1
-            },
-            brush_system::BrushType::Fan | brush_system::BrushType::Angle => {
-                ((base_size * 2.0).max(7.0) as usize) | 1
-            },
-            brush_system::BrushType::Rigger => {
-                ((base_size * 1.5).max(5.0) as usize) | 1
-            },
-            brush_system::BrushType::Mop => {
-                ((base_size * 2.5).max(9.0) as usize) | 1
-            },
-            brush_system::BrushType::Round => {
-                ((base_size * 2.0).max(7.0) as usize) | 1
-            },
-            brush_system::BrushType::Filbert => {
-                ((base_size * 2.0).max(7.0) as usize) | 1
-            },
-        };
-        
-        let mask = self.brush_manager.generate_brush_mask(mask_size);
-        
-        let center = mask_size as i32 / 2;
-        let width = self.current_state.width as i32;
-        let height = self.current_state.height as i32;
-        
-        for dy in 0..mask_size as i32 {
-            for dx in 0..mask_size as i32 {
-                let nx = x + dx - center;
-                let ny = y + dy - center;
-                
-                if nx >= 0 && nx < width && ny >= 0 && ny < height {
-                    let mask_value = mask[(dy as usize) * mask_size + (dx as usize)];
-                    
-                    if mask_value > 0.0 {
-                        let final_alpha = ((weighted_color.a() as f32) * mask_value) as u8;
-                        let new_color = if self.current_tool == Tool::Eraser {
-                            None
-                        } else if final_alpha > 0 {
-                            Some(Color32::from_rgba_unmultiplied(
-                                weighted_color.r(), 
-                                weighted_color.g(), 
-                                weighted_color.b(), 
-                                final_alpha
-                            ))
-                        } else {
-                            None
-                        };
-                        
-                        self.record_change(nx as usize, ny as usize, new_color);
-                    }
-                }
+        if ui.button(format!("{}…", get_text("rotate_arbitrary", self.language))).clicked() {
+            self.rotate_dialog = Some(RotateDialog::new(whole_image));
+            ui.close_menu();
+        }
+        ui.separator();
+        for (axis, key) in [(FlipAxis::Horizontal, "flip_horizontal"), (FlipAxis::Vertical, "flip_vertical")] {
+            if ui.button(get_text(key, self.language)).clicked() {
+                self.flip(axis, whole_image);
+                ui.close_menu();
             }
         }
-        
-        self.texture_dirty = true;
     }
 
-    fn draw_point(&mut self, x: i32, y: i32, _use_secondary: bool) {
-        let color = if self.using_secondary_color { self.secondary_color } else { self.primary_color };
-        
-        let current_size = if self.current_tool == Tool::Eraser {
-            self.eraser_size as f32
-        } else {
-            self.brush_size as f32
-        };
-        
-        if self.brush_manager.current_size != current_size {
-            self.brush_manager.current_size = current_size;
+    /// Opens the dialog of an adjustment, or applies it right away when it
+    /// has no settings.
+    fn open_adjustment(&mut self, kind: AdjustmentKind) {
+        self.apply_transform();
+        if kind.has_settings() {
+            self.adjustment_dialog = Some(AdjustmentDialog::new(kind, &self.document));
+        } else if self.document.adjust(&Adjustment::new(kind)) {
+            self.mark_layers_changed();
         }
-        
-        if self.current_state.active_layer_index < self.current_state.layers.len() &&
-           !self.current_state.layers[self.current_state.active_layer_index].visible {
-            return;
+    }
+
+    /// Opens the dialog of a filter, or applies it right away when it has no
+    /// settings.
+    fn open_filter(&mut self, kind: FilterKind) {
+        self.apply_transform();
+        if kind.has_settings() {
+            self.filter_dialog = Some(FilterDialog::new(kind));
+        } else if self.document.apply_filter(&Filter::new(kind)) {
+            self.mark_layers_changed();
         }
-        
-        let pressure = self.get_effective_pressure();
-        let is_eraser = self.current_tool == Tool::Eraser;
-        
-        let mut changes = Vec::new();
-        {
-            let mut record_change = |x: usize, y: usize, new_color: Option<Color32>| {
-                if is_eraser {
-                    changes.push((x, y, None));
-                } else {
-                    changes.push((x, y, new_color));
-                }
-            };
-            
-            self.brush_manager.draw_point(x, y, color, pressure, &mut record_change);
+    }
+
+    fn jump_to_history(&mut self, position: usize) {
+        self.transform_tool.cancel();
+        if self.document.jump_to(position) {
+            self.selection_tool.selection_changed();
+            self.texture_dirty = true;
+            self.has_unsaved_changes = true;
         }
-        
-        for (x, y, color) in changes {
-            self.record_change(x, y, color);
+    }
+
+    /// Color laid down by the current tool, `None` when erasing.
+    fn stroke_color(&self) -> Option<Color> {
+        if self.current_tool == Tool::Eraser {
+            None
+        } else {
+            let color = if self.using_secondary_color { self.secondary_color } else { self.primary_color };
+            Some(from_color32(color))
         }
-        
-        self.texture_dirty = true;
     }
-    
-    fn draw_point_with_color(&mut self, x: i32, y: i32, fill_color: Option<Color32>) {
-        let color = fill_color.unwrap_or(Color32::TRANSPARENT);
-        
+
+    /// Adds the brush read from an image or a GIMP brush file and selects
+    /// it. New brushes are also copied to the user brush library.
+    fn import_brush(&mut self, path: &Path) -> Result<(), String> {
+        let brush = brush_system::load_brush_file(path).map_err(|e| Self::file_error_message(e, self.language))?;
+        let library = match brush_system::user_brush_dir() {
+            Some(dir) if !self.brush_manager.has_tip(brush.tip.id()) => brush_system::copy_to_library(path, &dir).map(|_| ()),
+            _ => Ok(()),
+        };
+        self.brush_manager.active_brush_index = self.brush_manager.import_brush(brush);
+        self.brush_manager.current_size = self.brush_size as f32;
+        library.map_err(|e| Self::file_error_message(e, self.language))
+    }
+
+    fn sync_brush_size(&mut self) {
         let current_size = if self.current_tool == Tool::Eraser {
             self.eraser_size as f32
         } else {
@@ -1031,162 +729,108 @@ impl PaintApp {
         if self.brush_manager.current_size != current_size {
             self.brush_manager.current_size = current_size;
         }
+    }
+
+    fn draw_line(&mut self, start: (i32, i32), end: (i32, i32), _color: Color32) {
+        self.sync_brush_size();
+        let color = self.stroke_color();
+        let pressure = self.get_effective_pressure();
         
-        if self.current_state.active_layer_index < self.current_state.layers.len() &&
-           !self.current_state.layers[self.current_state.active_layer_index].visible {
-            return;
-        }
-        
-        self.brush_manager.update_angle(x as f32, y as f32);
-        
-        let base_size = self.brush_manager.current_size;
-        let active_brush = self.brush_manager.active_brush();
-        
-        let mask_size = match active_brush.brush_type {
-            brush_system::BrushType::Flat | brush_system::BrushType::Bright => {
-                ((base_size * 2.0).max(7.0) as usize) | 1
-            },
-            brush_system::BrushType::Fan | brush_system::BrushType::Angle => {
-                ((base_size * 2.0).max(7.0) as usize) | 1
-            },
-            brush_system::BrushType::Rigger => {
-                ((base_size * 1.5).max(5.0) as usize) | 1
-            },
-            brush_system::BrushType::Mop => {
-                ((base_size * 2.5).max(9.0) as usize) | 1
-            },
-            brush_system::BrushType::Round => {
-                ((base_size * 2.0).max(7.0) as usize) | 1
-            },
-            brush_system::BrushType::Filbert => {
-                ((base_size * 2.0).max(7.0) as usize) | 1
-            },
-        };
-        
-        let mask = self.brush_manager.generate_brush_mask(mask_size);
-        
-        let center = mask_size as i32 / 2;
-        let width = self.current_state.width as i32;
-        let height = self.current_state.height as i32;
+        self.document.draw_line(&mut self.brush_manager, start, end, color, pressure);
         
-        for dy in 0..mask_size as i32 {
-            for dx in 0..mask_size as i32 {
-                let nx = x + dx - center;
-                let ny = y + dy - center;
-                
-                if nx >= 0 && nx < width && ny >= 0 && ny < height {
-                    let mask_value = mask[(dy as usize) * mask_size + (dx as usize)];
-                    
-                    if mask_value > 0.0 {
-                        let final_color = if self.current_tool == Tool::Eraser || fill_color.is_none() {
-                            None
-                        } else {
-                            let alpha = (color.a() as f32 * mask_value) as u8;
-                            if alpha > 0 {
-                                Some(Color32::from_rgba_unmultiplied(color.r(), color.g(), color.b(), alpha))
-                            } else {
-                                None
-                            }
-                        };
-                        
-                        self.record_change(nx as usize, ny as usize, final_color);
-                    }
-                }
-            }
+        if self.document.history.has_pending_changes() {
+            self.has_unsaved_changes = true;
         }
-        
-        self.texture_dirty = true;
+        self.last_action_time = Instant::now();
     }
 
-    fn paint_bucket(&mut self, x: usize, y: usize, _use_secondary: bool) {
-        if x >= self.current_state.width || y >= self.current_state.height {
-            return;
-        }
-        
-        if self.current_state.active_layer_index < self.current_state.layers.len() &&
-           !self.current_state.layers[self.current_state.active_layer_index].visible {
-            return;
-        }
+    fn draw_point(&mut self, x: i32, y: i32, _use_secondary: bool) {
+        self.sync_brush_size();
+        let color = self.stroke_color();
+        let pressure = self.get_effective_pressure();
         
-        let target_color = self.current_state.get_from_active_layer(x, y);
-        let color = if self.using_secondary_color { self.secondary_color } else { self.primary_color };
-        let fill_color = if self.current_tool == Tool::Eraser {
-            None
-        } else {
-            Some(color)
-        };
+        self.document.draw_point(&mut self.brush_manager, x, y, color, pressure);
         
-        if target_color == fill_color {
-            return;
+        if self.document.history.has_pending_changes() {
+            self.has_unsaved_changes = true;
         }
+    }
+
+    fn paint_bucket(&mut self, x: usize, y: usize, _use_secondary: bool) {
+        let fill_color = self.stroke_color();
         
-        let mut queue = VecDeque::with_capacity(1024);
-        let mut visited = vec![false; self.current_state.width * self.current_state.height];
-        queue.push_back((x, y));
-        
-        while let Some((cx, cy)) = queue.pop_front() {
-            let idx = cy * self.current_state.width + cx;
-            if visited[idx] || self.current_state.get_from_active_layer(cx, cy) != target_color {
-                continue;
-            }
-            
-            visited[idx] = true;
-            self.record_change(cx, cy, fill_color);
-            
-            if cx > 0 { queue.push_back((cx - 1, cy)); }
-            if cx + 1 < self.current_state.width { queue.push_back((cx + 1, cy)); }
-            if cy > 0 { queue.push_back((cx, cy - 1)); }
-            if cy + 1 < self.current_state.height { queue.push_back((cx, cy + 1)); }
+        if self.document.paint_bucket(x, y, fill_color) {
+            self.has_unsaved_changes = true;
+            self.last_action_time = Instant::now();
         }
-        
-        self.last_action_time = Instant::now();
-        self.texture_dirty = true;
     }
 
     fn pick_color(&mut self, x: usize, y: usize, _use_secondary: bool) {
-        if let Some(color) = self.current_state.get(x, y) {
+        if let Some(color) = self.document.pick_color(x, y) {
             if self.using_secondary_color {
-                self.secondary_color = color;
+                self.secondary_color = to_color32(color);
             } else {
-                self.primary_color = color;
+                self.primary_color = to_color32(color);
             }
         }
     }
 
+    /// Redraws the canvas texture: all of it when `texture_dirty` is set or
+    /// the canvas size changed, otherwise only the pixels painted since the
+    /// last frame.
     fn update_texture(&mut self, ctx: &egui::Context) {
-        if self.texture_dirty {
-            let width = self.current_state.width;
-            let height = self.current_state.height;
-            
-            let mut image_data = vec![0_u8; width * height * 4];
-            
-            for y in 0..height {
-                for x in 0..width {
-                    let color = if let Some(pixel) = self.current_state.get(x, y) {
-                        pixel
-                    } else {
-                        let checker_x = x / CHECKERBOARD_SIZE;
-                        let checker_y = y / CHECKERBOARD_SIZE;
-                        if (checker_x + checker_y) % 2 == 0 {
-                            Color32::from_gray(200)
-                        } else {
-                            Color32::from_gray(160)
-                        }
-                    };
-                    
-                    let idx = (y * width + x) * 4;
-                    image_data[idx] = color.r();
-                    image_data[idx + 1] = color.g();
-                    image_data[idx + 2] = color.b();
-                    image_data[idx + 3] = color.a();
-                }
+        let width = self.document.canvas.width;
+        let height = self.document.canvas.height;
+        let damage = self.document.take_damage();
+        let resized = self.texture.as_ref().is_none_or(|texture| texture.size() != [width, height]);
+        
+        let (left, top, area_width, area_height) = if self.texture_dirty || resized {
+            (0, 0, width, height)
+        } else {
+            match damage {
+                Some((x, y, w, h)) => (x.min(width), y.min(height), w.min(width.saturating_sub(x)), h.min(height.saturating_sub(y))),
+                None => return,
             }
-            
-            let color_image = egui::ColorImage::from_rgba_unmultiplied([width, height], &image_data);
-            self.texture = Some(ctx.load_texture("canvas", color_image, TextureOptions::NEAREST));
-            
+        };
+        if area_width == 0 || area_height == 0 {
             self.texture_dirty = false;
+            return;
+        }
+        
+        let preview = self.transform_tool.preview()
+            .or_else(|| self.adjustment_dialog.as_ref().and_then(|dialog| dialog.preview.canvas()))
+            .or_else(|| self.filter_dialog.as_ref().and_then(|dialog| dialog.preview.canvas()));
+        let document = &self.document;
+        
+        let mut pixels = vec![Color32::TRANSPARENT; area_width * area_height];
+        pixels.par_chunks_mut(area_width).enumerate().for_each(|(row, line)| {
+            let y = top + row;
+            for (column, out) in line.iter_mut().enumerate() {
+                let x = left + column;
+                let checker = if (x / CHECKERBOARD_SIZE + y / CHECKERBOARD_SIZE) % 2 == 0 {
+                    Color::from_gray(200)
+                } else {
+                    Color::from_gray(160)
+                };
+                let pixel = match preview {
+                    Some(preview) => preview.get(x, y),
+                    None => document.display_pixel(x, y),
+                };
+                let [r, g, b, a] = match pixel {
+                    Some(pixel) => compositing::source_over(pixel, checker),
+                    None => checker,
+                }.to_array();
+                *out = Color32::from_rgba_unmultiplied(r, g, b, a);
+            }
+        });
+        
+        let color_image = egui::ColorImage { size: [area_width, area_height], pixels };
+        match &mut self.texture {
+            Some(texture) if !resized => texture.set_partial([left, top], color_image, TextureOptions::NEAREST),
+            _ => self.texture = Some(ctx.load_texture("canvas", color_image, TextureOptions::NEAREST)),
         }
+        
+        self.texture_dirty = false;
     }
     
     fn show_save_dialog(&mut self, return_to_menu: bool) {
@@ -1210,10 +854,24 @@ enum PendingAction {
 
 enum LayerAction {
     ToggleVisibility(usize),
+    ToggleExpanded(usize),
     SetActive(usize),
     Edit(usize),
 }
 
+struct LayerRow {
+    index: usize,
+    name: String,
+    visible: bool,
+    is_active: bool,
+    locked: bool,
+    masked: bool,
+    clipped: bool,
+    depth: usize,
+    /// `Some` for groups, with their collapsed state.
+    expanded: Option<bool>,
+}
+
 struct MyApp {
     state: AppState,
     error_message: Option<String>,
@@ -1222,6 +880,7 @@ struct MyApp {
     rename_layer_name: String,
     pending_action: PendingAction,
     language: Language,
+    clipboard: AppClipboard,
 }
 
 impl Default for MyApp {
@@ -1234,6 +893,7 @@ impl Default for MyApp {
             rename_layer_name: String::new(),
             pending_action: PendingAction::None,
             language: Language::French,
+            clipboard: AppClipboard::default(),
         }
     }
 }
@@ -1288,11 +948,14 @@ impl eframe::App for MyApp {
                         LayerAction::ToggleVisibility(idx) => {
                             paint_app.toggle_layer_visibility(*idx);
                         },
+                        LayerAction::ToggleExpanded(idx) => {
+                            paint_app.toggle_group_expanded(*idx);
+                        },
                         LayerAction::SetActive(idx) => {
                             paint_app.set_active_layer(*idx);
                         },
                         LayerAction::Edit(idx) => {
-                            if let Some(layer) = paint_app.current_state.layers.get(*idx) {
+                            if let Some(layer) = paint_app.document.canvas.layers.get(*idx) {
                                 self.rename_layer_index = Some(*idx);
                                 self.rename_layer_name = layer.name.clone();
                             }
@@ -1367,6 +1030,30 @@ impl eframe::App for MyApp {
                         shift && ctx.input(|i| i.key_pressed(egui::Key::Z)) {
                         self.pending_action = PendingAction::RedoAction;
                     }
+                    if ctx.input(|i| i.key_pressed(egui::Key::A)) {
+                        paint_app.select_all();
+                    }
+                    if ctx.input(|i| i.key_pressed(egui::Key::D)) {
+                        paint_app.deselect();
+                    }
+                    if shift && ctx.input(|i| i.key_pressed(egui::Key::I)) {
+                        paint_app.invert_selection();
+                    }
+                    if !ctx.wants_keyboard_input() {
+                        if ctx.input(|i| i.key_pressed(egui::Key::C)) {
+                            paint_app.copy(&mut self.clipboard);
+                        }
+                        if ctx.input(|i| i.key_pressed(egui::Key::X)) {
+                            paint_app.cut(&mut self.clipboard);
+                        }
+                        if ctx.input(|i| i.key_pressed(egui::Key::V)) {
+                            if shift {
+                                paint_app.paste_as_layer(&mut self.clipboard);
+                            } else {
+                                paint_app.paste(&mut self.clipboard);
+                            }
+                        }
+                    }
                     if ctx.input(|i| i.key_pressed(egui::Key::S)) {
                         if let Some(_) = &paint_app.last_save_path {
                             match paint_app.quick_save() {
@@ -1400,6 +1087,37 @@ impl eframe::App for MyApp {
                     }
                 }
                 
+                if paint_app.current_tool != Tool::Transform && paint_app.transform_tool.is_active() {
+                    paint_app.apply_transform();
+                }
+                
+                if paint_app.transform_tool.is_active() && paint_app.document.floating.is_none() && !ctx.wants_keyboard_input() {
+                    if ctx.input(|i| i.key_pressed(egui::Key::Enter)) {
+                        paint_app.apply_transform();
+                    }
+                    if ctx.input(|i| i.key_pressed(egui::Key::Escape)) {
+                        paint_app.cancel_transform();
+                    }
+                }
+                
+                if paint_app.current_tool == Tool::Crop && paint_app.crop_tool.is_active() && !ctx.wants_keyboard_input() {
+                    if ctx.input(|i| i.key_pressed(egui::Key::Enter)) {
+                        paint_app.apply_crop();
+                    }
+                    if ctx.input(|i| i.key_pressed(egui::Key::Escape)) {
+                        paint_app.crop_tool.cancel();
+                    }
+                }
+                
+                if paint_app.document.floating.is_some() && !ctx.wants_keyboard_input() {
+                    if ctx.input(|i| i.key_pressed(egui::Key::Enter)) {
+                        paint_app.commit_floating();
+                    }
+                    if ctx.input(|i| i.key_pressed(egui::Key::Escape)) {
+                        paint_app.cancel_floating();
+                    }
+                }
+                
                 match &mut paint_app.save_dialog {
                     SaveDialog::Hidden => {},
                     SaveDialog::AskingSave { return_to_menu } => {
@@ -1454,6 +1172,71 @@ impl eframe::App for MyApp {
                     }
                 }
                 
+                if let Some(dialog) = &mut paint_app.size_dialog {
+                    match dialog.show(ctx, self.language, &mut paint_app.document) {
+                        DialogOutcome::Open => {},
+                        DialogOutcome::Cancelled => paint_app.size_dialog = None,
+                        DialogOutcome::Applied => {
+                            paint_app.size_dialog = None;
+                            paint_app.canvas_reshaped();
+                        },
+                    }
+                }
+                
+                if let Some(dialog) = &mut paint_app.rotate_dialog {
+                    match dialog.show(ctx, self.language, &mut paint_app.document) {
+                        DialogOutcome::Open => {},
+                        DialogOutcome::Cancelled => paint_app.rotate_dialog = None,
+                        DialogOutcome::Applied => {
+                            paint_app.rotate_dialog = None;
+                            paint_app.canvas_reshaped();
+                        },
+                    }
+                }
+                
+                if let Some(dialog) = &mut paint_app.adjustment_dialog {
+                    match dialog.show(ctx, self.language, &mut paint_app.document) {
+                        DialogOutcome::Open => {
+                            // The layer below the preview changed.
+                            if paint_app.texture_dirty || paint_app.document.has_damage() {
+                                dialog.preview.invalidate();
+                            }
+                            if dialog.update_preview(&paint_app.document) {
+                                paint_app.texture_dirty = true;
+                            }
+                        },
+                        DialogOutcome::Cancelled => {
+                            paint_app.adjustment_dialog = None;
+                            paint_app.texture_dirty = true;
+                        },
+                        DialogOutcome::Applied => {
+                            paint_app.adjustment_dialog = None;
+                            paint_app.mark_layers_changed();
+                        },
+                    }
+                }
+                
+                if let Some(dialog) = &mut paint_app.filter_dialog {
+                    match dialog.show(ctx, self.language, &mut paint_app.document) {
+                        DialogOutcome::Open => {
+                            if paint_app.texture_dirty || paint_app.document.has_damage() {
+                                dialog.preview.invalidate();
+                            }
+                            if dialog.update_preview(&paint_app.document) {
+                                paint_app.texture_dirty = true;
+                            }
+                        },
+                        DialogOutcome::Cancelled => {
+                            paint_app.filter_dialog = None;
+                            paint_app.texture_dirty = true;
+                        },
+                        DialogOutcome::Applied => {
+                            paint_app.filter_dialog = None;
+                            paint_app.mark_layers_changed();
+                        },
+                    }
+                }
+                
                 paint_app.update_texture(ctx);
 
                 egui::SidePanel::left("layers_panel")
@@ -1477,19 +1260,20 @@ impl eframe::App for MyApp {
                                     );
                                     ui.put(add_btn.rect, ToolIcons::add());
                                     if add_btn.clicked() {
-                                        paint_app.add_layer(format!("Layer {}", paint_app.current_state.layers.len() + 1));
+                                        paint_app.add_layer(format!("Layer {}", paint_app.document.canvas.layers.len() + 1));
                                     }
                                     
                                     ui.add_space(RustiqueTheme::SPACING_XS);
                                     
+                                    let can_remove = paint_app.document.canvas.can_remove_layer(paint_app.document.canvas.active_layer_index);
                                     let remove_btn = ui.add(
                                         egui::Button::new("")
-                                            .fill(if paint_app.current_state.layers.len() > 1 { 
+                                            .fill(if can_remove { 
                                                 RustiqueTheme::ERROR 
                                             } else { 
                                                 RustiqueTheme::SURFACE_PRIMARY 
                                             })
-                                            .stroke(egui::Stroke::new(1.0, if paint_app.current_state.layers.len() > 1 { 
+                                            .stroke(egui::Stroke::new(1.0, if can_remove { 
                                                 RustiqueTheme::ERROR 
                                             } else { 
                                                 RustiqueTheme::BORDER_LIGHT 
@@ -1498,8 +1282,22 @@ impl eframe::App for MyApp {
                                             .min_size(btn_size)
                                     );
                                     ui.put(remove_btn.rect, ToolIcons::remove());
-                                    if remove_btn.clicked() && paint_app.current_state.layers.len() > 1 {
-                                        paint_app.remove_layer(paint_app.current_state.active_layer_index);
+                                    if remove_btn.clicked() && can_remove {
+                                        paint_app.remove_layer(paint_app.document.canvas.active_layer_index);
+                                    }
+                                    
+                                    ui.add_space(RustiqueTheme::SPACING_XS);
+                                    
+                                    let group_btn = ui.add(
+                                        egui::Button::new("")
+                                            .fill(RustiqueTheme::SURFACE_SECONDARY)
+                                            .stroke(egui::Stroke::new(1.0, RustiqueTheme::BORDER_LIGHT))
+                                            .rounding(RustiqueTheme::rounding_small())
+                                            .min_size(btn_size)
+                                    );
+                                    ui.put(group_btn.rect, ToolIcons::group());
+                                    if group_btn.on_hover_text(get_text("group_layer", self.language)).clicked() {
+                                        paint_app.group_layer(paint_app.document.canvas.active_layer_index);
                                     }
                                     
                                     ui.add_space(RustiqueTheme::SPACING_SM);
@@ -1513,7 +1311,7 @@ impl eframe::App for MyApp {
                                     );
                                     ui.put(up_btn.rect, ToolIcons::move_up());
                                     if up_btn.clicked() {
-                                        paint_app.move_layer_up(paint_app.current_state.active_layer_index);
+                                        paint_app.move_layer_up(paint_app.document.canvas.active_layer_index);
                                     }
                                     
                                     ui.add_space(RustiqueTheme::SPACING_XS);
@@ -1527,22 +1325,150 @@ impl eframe::App for MyApp {
                                     );
                                     ui.put(down_btn.rect, ToolIcons::move_down());
                                     if down_btn.clicked() {
-                                        paint_app.move_layer_down(paint_app.current_state.active_layer_index);
+                                        paint_app.move_layer_down(paint_app.document.canvas.active_layer_index);
                                     }
                                 });
                                 
                                 ui.add_space(RustiqueTheme::SPACING_SM);
+                                
+                                let active_index = paint_app.document.canvas.active_layer_index;
+                                if let Some((mut opacity, mut blend_mode, mut locks, mut clipped, mask_enabled)) = paint_app.document.canvas.layers
+                                    .get(active_index)
+                                    .map(|layer| (
+                                        layer.opacity,
+                                        layer.blend_mode,
+                                        layer.locks,
+                                        layer.clipped,
+                                        layer.mask.as_ref().map(|mask| mask.enabled),
+                                    ))
+                                {
+                                    ui.horizontal(|ui| {
+                                        ui.label(RustiqueTheme::muted_text(&get_text("layer_opacity", self.language)));
+                                        let response = ui.add(egui::Slider::new(&mut opacity, 0.0..=1.0)
+                                            .custom_formatter(|value, _| format!("{:.0}%", value * 100.0)));
+                                        if response.changed() {
+                                            paint_app.set_layer_opacity(active_index, opacity);
+                                        }
+                                        // Each drag, click or key press is its own undo step.
+                                        if !response.dragged() {
+                                            paint_app.document.history.end_coalescing();
+                                        }
+                                    });
+                                    
+                                    ui.horizontal(|ui| {
+                                        ui.label(RustiqueTheme::muted_text(&get_text("blend_mode", self.language)));
+                                        if brush_system::blend_mode_selector(
+                                            ui,
+                                            "layer_blend_mode",
+                                            &mut blend_mode,
+                                            &BlendMode::layer_modes(),
+                                            self.language,
+                                        ) {
+                                            paint_app.set_layer_blend_mode(active_index, blend_mode);
+                                        }
+                                    });
+                                    
+                                    ui.horizontal(|ui| {
+                                        ui.label(RustiqueTheme::muted_text(&get_text("layer_locks", self.language)));
+                                        
+                                        let lock_toggles = [
+                                            (&mut locks.pixels, ToolIcons::lock_pixels(), "lock_pixels"),
+                                            (&mut locks.alpha, ToolIcons::lock_alpha(), "lock_alpha"),
+                                            (&mut locks.position, ToolIcons::lock_position(), "lock_position"),
+                                        ];
+                                        let mut locks_changed = false;
+                                        for (locked, icon, key) in lock_toggles {
+                                            let lock_btn = ui.add(
+                                                egui::Button::new("")
+                                                    .fill(if *locked { 
+                                                        RustiqueTheme::ACCENT_PRIMARY.linear_multiply(0.3) 
+                                                    } else { 
+                                                        Color32::TRANSPARENT 
+                                                    })
+                                                    .stroke(egui::Stroke::new(1.0, if *locked { 
+                                                        RustiqueTheme::ACCENT_PRIMARY 
+                                                    } else { 
+                                                        RustiqueTheme::BORDER_LIGHT 
+                                                    }))
+                                                    .rounding(RustiqueTheme::rounding_small())
+                                                    .min_size(Vec2::new(24.0, 24.0))
+                                            );
+                                            ui.put(lock_btn.rect, icon);
+                                            if lock_btn.on_hover_text(get_text(key, self.language)).clicked() {
+                                                *locked = !*locked;
+                                                locks_changed = true;
+                                            }
+                                        }
+                                        
+                                        if locks_changed {
+                                            paint_app.set_layer_locks(active_index, locks);
+                                        }
+                                    });
+                                    
+                                    ui.horizontal(|ui| {
+                                        ui.label(RustiqueTheme::muted_text(&get_text("layer_mask", self.language)));
+                                        
+                                        if let Some(mut enabled) = mask_enabled {
+                                            let editing_mask = paint_app.document.canvas.active_target() == EditTarget::Mask;
+                                            if ui.selectable_label(editing_mask, get_text("edit_mask", self.language)).clicked() {
+                                                paint_app.document.canvas.edit_target = if editing_mask {
+                                                    EditTarget::Pixels
+                                                } else {
+                                                    EditTarget::Mask
+                                                };
+                                            }
+                                            if ui.checkbox(&mut enabled, get_text("mask_enabled", self.language)).changed() {
+                                                paint_app.toggle_layer_mask_enabled(active_index);
+                                            }
+                                            if ui.button(get_text("remove_mask", self.language)).clicked() {
+                                                paint_app.remove_layer_mask(active_index);
+                                            }
+                                        } else if ui.button(get_text("add_mask", self.language)).clicked() {
+                                            paint_app.add_layer_mask(active_index);
+                                        }
+                                    });
+                                    
+                                    if ui.checkbox(&mut clipped, get_text("clip_to_below", self.language)).changed() {
+                                        paint_app.toggle_layer_clipping(active_index);
+                                    }
+                                }
+                                
                                 ui.add_space(RustiqueTheme::SPACING_SM);
                                 
-                                let layers_info: Vec<(usize, String, bool, bool)> = paint_app.current_state.layers
+                                let canvas = &paint_app.document.canvas;
+                                let layers_info: Vec<LayerRow> = canvas.layers
                                     .iter()
                                     .enumerate()
-                                    .map(|(i, layer)| (i, layer.name.clone(), layer.visible, i == paint_app.current_state.active_layer_index))
+                                    .filter(|(i, _)| {
+                                        canvas.ancestors(*i).all(|parent| {
+                                            !matches!(canvas.layers[parent].kind, LayerKind::Group { expanded: false })
+                                        })
+                                    })
+                                    .map(|(i, layer)| LayerRow {
+                                        index: i,
+                                        name: layer.name.clone(),
+                                        visible: layer.visible,
+                                        is_active: i == canvas.active_layer_index,
+                                        locked: layer.locks.any(),
+                                        masked: layer.mask.is_some(),
+                                        clipped: layer.clipped,
+                                        depth: layer.depth,
+                                        expanded: match layer.kind {
+                                            LayerKind::Group { expanded } => Some(expanded),
+                                            LayerKind::Raster => None,
+                                        },
+                                    })
                                     .collect();
                                 
-                                for (i, name, visible, is_active) in layers_info.iter().rev() {
+                                for LayerRow { index: i, name, visible, is_active, locked, masked, clipped, depth, expanded } in layers_info.iter().rev() {
                                     RustiqueTheme::card_frame().show(ui, |ui| {
                                         ui.horizontal(|ui| {
+                                            ui.add_space(*depth as f32 * 12.0);
+                                            
+                                            if *clipped {
+                                                ui.add(ToolIcons::clipped().with_color(RustiqueTheme::TEXT_SECONDARY));
+                                            }
+                                            
                                             let visibility_btn = ui.add(
                                                 egui::Button::new("")
                                                     .fill(Color32::TRANSPARENT)
@@ -1563,6 +1489,25 @@ impl eframe::App for MyApp {
                                                 );
                                             }
                                             
+                                            if let Some(expanded) = expanded {
+                                                let expand_btn = ui.add(
+                                                    egui::Button::new("")
+                                                        .fill(Color32::TRANSPARENT)
+                                                        .stroke(egui::Stroke::NONE)
+                                                        .min_size(Vec2::new(18.0, 28.0))
+                                                );
+                                                ui.put(expand_btn.rect, if *expanded {
+                                                    ToolIcons::group_expanded()
+                                                } else {
+                                                    ToolIcons::group_collapsed()
+                                                });
+                                                if expand_btn.clicked() {
+                                                    self.pending_action = PendingAction::HandleLayerAction(
+                                                        LayerAction::ToggleExpanded(*i)
+                                                    );
+                                                }
+                                            }
+                                            
                                             ui.add_space(RustiqueTheme::SPACING_XS);
                                             
                                             let layer_btn = ui.add(
@@ -1581,7 +1526,7 @@ impl eframe::App for MyApp {
                                                         }
                                                     ))
                                                     .rounding(RustiqueTheme::rounding_small())
-                                                    .min_size(Vec2::new(ui.available_width() - 60.0, 28.0))
+                                                    .min_size(Vec2::new(ui.available_width() - 60.0 - 24.0 * (*locked as u8 + *masked as u8) as f32, 28.0))
                                             );
                                             
                                             if layer_btn.clicked() {
@@ -1604,6 +1549,14 @@ impl eframe::App for MyApp {
                                                         LayerAction::Edit(*i)
                                                     );
                                                 }
+                                                
+                                                if *locked {
+                                                    ui.add(ToolIcons::lock_pixels().with_color(RustiqueTheme::TEXT_SECONDARY));
+                                                }
+                                                
+                                                if *masked {
+                                                    ui.add(ToolIcons::layer_mask().with_color(RustiqueTheme::TEXT_SECONDARY));
+                                                }
                                             });
                                         });
                                     });
@@ -1751,6 +1704,42 @@ impl eframe::App for MyApp {
                                 }
                                 line_btn.on_hover_text("Line Tool");
                                 
+                                ui.add_space(RustiqueTheme::SPACING_XS);
+                                
+                                for (tool, icon, hover_key) in [
+                                    (Tool::Select, ToolIcons::select(), "tool_select"),
+                                    (Tool::MagicWand, ToolIcons::magic_wand(), "tool_magic_wand"),
+                                    (Tool::Transform, ToolIcons::transform(), "tool_transform"),
+                                    (Tool::Crop, ToolIcons::crop(), "tool_crop"),
+                                ] {
+                                    let tool_btn = ui.add(
+                                        egui::Button::new("")
+                                            .fill(if paint_app.current_tool == tool { 
+                                                RustiqueTheme::ACCENT_PRIMARY 
+                                            } else { 
+                                                RustiqueTheme::SURFACE_PRIMARY 
+                                            })
+                                            .stroke(egui::Stroke::new(
+                                                if paint_app.current_tool == tool { 2.0 } else { 1.0 },
+                                                if paint_app.current_tool == tool { 
+                                                    RustiqueTheme::ACCENT_PRIMARY 
+                                                } else { 
+                                                    RustiqueTheme::BORDER_LIGHT 
+                                                }
+                                            ))
+                                            .rounding(RustiqueTheme::rounding_small())
+                                            .min_size(tool_size)
+                                    );
+                                    ui.put(tool_btn.rect, icon);
+                                    if tool_btn.clicked() {
+                                        paint_app.current_tool = tool;
+                                        paint_app.selection_tool.cancel();
+                                    }
+                                    tool_btn.on_hover_text(get_text(hover_key, self.language));
+                                    
+                                    ui.add_space(RustiqueTheme::SPACING_XS);
+                                }
+                                
                                 ui.add_space(RustiqueTheme::SPACING_MD);
                                 
                                 ui.separator();
@@ -1818,14 +1807,94 @@ impl eframe::App for MyApp {
                                                 ui.label(RustiqueTheme::body_text(&get_text("brush_system", self.language)));
                                                 ui.add_space(RustiqueTheme::SPACING_XS);
                                                 
-                                                if paint_app.brush_manager.brush_selector_grid(ui, ctx, self.language) {
+                                                if brush_system::brush_selector_grid(&mut paint_app.brush_manager, ui, ctx, self.language) {
                                                     paint_app.brush_manager.current_size = paint_app.brush_size as f32;
                                                 }
+                                                
+                                                ui.add_space(RustiqueTheme::SPACING_XS);
+                                                
+                                                if ui.button(get_text("load_texture", self.language)).clicked()
+                                                    && let Some(path) = FileDialog::new()
+                                                        .set_title(&get_text("select_brush_texture", self.language))
+                                                        .add_filter("Brush Files", &brush_system::BRUSH_FILE_EXTENSIONS)
+                                                        .add_filter("PNG Image", &["png"])
+                                                        .add_filter("GIMP Brush", &["gbr"])
+                                                        .add_filter("GIMP Image Hose", &["gih"])
+                                                        .pick_file()
+                                                    && let Err(e) = paint_app.import_brush(&path)
+                                                {
+                                                    self.error_message = Some(e);
+                                                    self.show_error = true;
+                                                }
+                                                
+                                                ui.add_space(RustiqueTheme::SPACING_SM);
+                                                
+                                                ui.horizontal(|ui| {
+                                                    ui.label(RustiqueTheme::muted_text(&get_text("blend_mode", self.language)));
+                                                    brush_system::blend_mode_selector(
+                                                        ui,
+                                                        "brush_blend_mode",
+                                                        &mut paint_app.brush_manager.active_brush_mut().blend_mode,
+                                                        &BlendMode::all(),
+                                                        self.language,
+                                                    );
+                                                });
+                                                
+                                                ui.add_space(RustiqueTheme::SPACING_SM);
+                                                
+                                                if let Err(e) = paint_app.preset_browser.show(ui, self.language, &mut paint_app.brush_manager, &mut paint_app.brush_size) {
+                                                    self.error_message = Some(PaintApp::file_error_message(e, self.language));
+                                                    self.show_error = true;
+                                                }
                                             });
                                         });
                                         
                                         ui.add_space(RustiqueTheme::SPACING_MD);
                                         
+                                        if matches!(paint_app.current_tool, Tool::Select | Tool::MagicWand) {
+                                            RustiqueTheme::card_frame().show(ui, |ui| {
+                                                ui.vertical(|ui| {
+                                                    ui.label(RustiqueTheme::body_text(&get_text("selection", self.language)));
+                                                    ui.add_space(RustiqueTheme::SPACING_XS);
+                                                    
+                                                    let magic_wand = paint_app.current_tool == Tool::MagicWand;
+                                                    paint_app.selection_tool.settings_ui(ui, self.language, magic_wand);
+                                                });
+                                            });
+                                            
+                                            ui.add_space(RustiqueTheme::SPACING_MD);
+                                        }
+                                        
+                                        if paint_app.current_tool == Tool::Crop {
+                                            RustiqueTheme::card_frame().show(ui, |ui| {
+                                                ui.vertical(|ui| {
+                                                    ui.label(RustiqueTheme::body_text(&get_text("crop", self.language)));
+                                                    ui.add_space(RustiqueTheme::SPACING_XS);
+                                                    
+                                                    if paint_app.crop_tool.settings_ui(ui, self.language, &mut paint_app.document) {
+                                                        paint_app.canvas_reshaped();
+                                                    }
+                                                });
+                                            });
+                                            
+                                            ui.add_space(RustiqueTheme::SPACING_MD);
+                                        }
+                                        
+                                        if paint_app.current_tool == Tool::Transform {
+                                            RustiqueTheme::card_frame().show(ui, |ui| {
+                                                ui.vertical(|ui| {
+                                                    ui.label(RustiqueTheme::body_text(&get_text("transform", self.language)));
+                                                    ui.add_space(RustiqueTheme::SPACING_XS);
+                                                    
+                                                    if paint_app.transform_tool.settings_ui(ui, self.language, &mut paint_app.document) {
+                                                        paint_app.mark_layers_changed();
+                                                    }
+                                                });
+                                            });
+                                            
+                                            ui.add_space(RustiqueTheme::SPACING_MD);
+                                        }
+                                        
                                         RustiqueTheme::card_frame().show(ui, |ui| {
                                             ui.vertical(|ui| {
                                                 ui.label(RustiqueTheme::body_text(&get_text("tool_settings", self.language)));
@@ -1841,6 +1910,24 @@ impl eframe::App for MyApp {
                                                 
                                                 ui.add_space(RustiqueTheme::SPACING_XS);
                                                 
+                                                ui.horizontal(|ui| {
+                                                    ui.label(RustiqueTheme::muted_text(&get_text("brush_opacity", self.language)));
+                                                    ui.add(egui::Slider::new(&mut paint_app.brush_manager.active_brush_mut().opacity, 0.0..=1.0)
+                                                        .custom_formatter(|value, _| format!("{:.0}%", value * 100.0)));
+                                                });
+                                                
+                                                ui.add_space(RustiqueTheme::SPACING_XS);
+                                                
+                                                ui.horizontal(|ui| {
+                                                    ui.label(RustiqueTheme::muted_text(&get_text("brush_hardness", self.language)));
+                                                    let active_brush = paint_app.brush_manager.active_brush_mut();
+                                                    let bitmap_tip = matches!(active_brush.brush_type, BrushType::Custom(_));
+                                                    ui.add_enabled(!bitmap_tip, egui::Slider::new(&mut active_brush.hardness, 0.0..=1.0)
+                                                        .custom_formatter(|value, _| format!("{:.0}%", value * 100.0)));
+                                                });
+                                                
+                                                ui.add_space(RustiqueTheme::SPACING_XS);
+                                                
                                                 ui.horizontal(|ui| {
                                                     ui.label(RustiqueTheme::muted_text(&get_text("eraser_size", self.language)));
                                                     ui.add(egui::DragValue::new(&mut paint_app.eraser_size)
@@ -2039,6 +2126,42 @@ impl eframe::App for MyApp {
                                         
                                         ui.add_space(RustiqueTheme::SPACING_MD);
                                         
+                                        RustiqueTheme::card_frame().show(ui, |ui| {
+                                            ui.vertical(|ui| {
+                                                ui.label(RustiqueTheme::body_text(&get_text("history", self.language)));
+                                                ui.add_space(RustiqueTheme::SPACING_XS);
+                                                
+                                                let history = &paint_app.document.history;
+                                                let position = history.position();
+                                                let mut rows = vec![get_text("history_initial", self.language)];
+                                                rows.extend(history.actions().map(|action| action_name(action, self.language)));
+                                                
+                                                let mut clicked_position = None;
+                                                egui::ScrollArea::vertical()
+                                                    .id_source("history_list")
+                                                    .max_height(200.0)
+                                                    .stick_to_bottom(true)
+                                                    .show(ui, |ui| {
+                                                        for (i, name) in rows.iter().enumerate() {
+                                                            let text = if i > position {
+                                                                RustiqueTheme::muted_text(name)
+                                                            } else {
+                                                                RichText::new(name)
+                                                            };
+                                                            if ui.selectable_label(i == position, text).clicked() {
+                                                                clicked_position = Some(i);
+                                                            }
+                                                        }
+                                                    });
+                                                
+                                                if let Some(position) = clicked_position {
+                                                    paint_app.jump_to_history(position);
+                                                }
+                                            });
+                                        });
+                                        
+                                        ui.add_space(RustiqueTheme::SPACING_MD);
+                                        
                                         RustiqueTheme::card_frame().show(ui, |ui| {
                                             ui.vertical(|ui| {
                                                 ui.label(RustiqueTheme::body_text("Quick Actions"));
@@ -2130,9 +2253,169 @@ impl eframe::App for MyApp {
                                 redo_clicked = true;
                             }
                             
+                            ui.add_space(RustiqueTheme::SPACING_LG);
+                            
+                            ui.menu_button(get_text("menu_edit", self.language), |ui| {
+                                if ui.button(format!("{}  (Ctrl+X)", get_text("cut", self.language))).clicked() {
+                                    paint_app.cut(&mut self.clipboard);
+                                    ui.close_menu();
+                                }
+                                if ui.button(format!("{}  (Ctrl+C)", get_text("copy", self.language))).clicked() {
+                                    paint_app.copy(&mut self.clipboard);
+                                    ui.close_menu();
+                                }
+                                if ui.button(format!("{}  (Ctrl+V)", get_text("paste", self.language))).clicked() {
+                                    paint_app.paste(&mut self.clipboard);
+                                    ui.close_menu();
+                                }
+                                if ui.button(format!("{}  (Ctrl+Shift+V)", get_text("paste_as_layer", self.language))).clicked() {
+                                    paint_app.paste_as_layer(&mut self.clipboard);
+                                    ui.close_menu();
+                                }
+                                ui.separator();
+                                let floating = paint_app.document.floating.is_some();
+                                if ui.add_enabled(floating, egui::Button::new(format!("{}  (Enter)", get_text("commit_paste", self.language)))).clicked() {
+                                    paint_app.commit_floating();
+                                    ui.close_menu();
+                                }
+                                if ui.add_enabled(floating, egui::Button::new(format!("{}  (Esc)", get_text("cancel_paste", self.language)))).clicked() {
+                                    paint_app.cancel_floating();
+                                    ui.close_menu();
+                                }
+                            });
+                            
+                            ui.menu_button(get_text("menu_image", self.language), |ui| {
+                                if ui.button(format!("{}…", get_text("canvas_size", self.language))).clicked() {
+                                    paint_app.size_dialog = Some(SizeDialog::new(SizeDialogKind::Canvas, &paint_app.document));
+                                    ui.close_menu();
+                                }
+                                if ui.button(format!("{}…", get_text("image_size", self.language))).clicked() {
+                                    paint_app.size_dialog = Some(SizeDialog::new(SizeDialogKind::Image, &paint_app.document));
+                                    ui.close_menu();
+                                }
+                                if ui.button(get_text("crop_to_content", self.language)).clicked() {
+                                    paint_app.crop_to_layer_content();
+                                    ui.close_menu();
+                                }
+                                if ui.button(get_text("trim", self.language)).clicked() {
+                                    paint_app.trim();
+                                    ui.close_menu();
+                                }
+                                ui.separator();
+                                paint_app.orientation_menu(ui, true);
+                                ui.separator();
+                                let layer_movable = paint_app.document.canvas.can_transform_layer(paint_app.document.canvas.active_layer_index);
+                                ui.add_enabled_ui(layer_movable, |ui| {
+                                    ui.menu_button(get_text("active_layer", self.language), |ui| {
+                                        paint_app.orientation_menu(ui, false);
+                                    });
+                                });
+                            });
+                            
+                            ui.menu_button(get_text("menu_adjustments", self.language), |ui| {
+                                let editable = paint_app.document.canvas.active_layer_editable();
+                                ui.add_enabled_ui(editable, |ui| {
+                                    for kind in AdjustmentKind::all() {
+                                        if matches!(kind, AdjustmentKind::Invert | AdjustmentKind::Posterize) {
+                                            ui.separator();
+                                        }
+                                        let mut label = get_text(adjustment_name_key(kind), self.language);
+                                        if kind.has_settings() {
+                                            label.push('…');
+                                        }
+                                        if ui.button(label).clicked() {
+                                            paint_app.open_adjustment(kind);
+                                            ui.close_menu();
+                                        }
+                                    }
+                                }).response.on_disabled_hover_text(get_text("adjustment_unavailable", self.language));
+                            });
+                            
+                            ui.menu_button(get_text("menu_filters", self.language), |ui| {
+                                let editable = paint_app.document.canvas.active_layer_editable();
+                                ui.add_enabled_ui(editable, |ui| {
+                                    for kind in FilterKind::all() {
+                                        if kind == FilterKind::EdgeDetect {
+                                            ui.separator();
+                                        }
+                                        let mut label = get_text(filter_name_key(kind), self.language);
+                                        if kind.has_settings() {
+                                            label.push('…');
+                                        }
+                                        if ui.button(label).clicked() {
+                                            paint_app.open_filter(kind);
+                                            ui.close_menu();
+                                        }
+                                    }
+                                }).response.on_disabled_hover_text(get_text("adjustment_unavailable", self.language));
+                            });
+                            
+                            ui.menu_button(get_text("menu_layer", self.language), |ui| {
+                                let active_index = paint_app.document.canvas.active_layer_index;
+                                
+                                if ui.button(get_text("duplicate_layer", self.language)).clicked() {
+                                    paint_app.duplicate_layer(active_index);
+                                    ui.close_menu();
+                                }
+                                if ui.add_enabled(
+                                    paint_app.document.canvas.merge_down_target(active_index).is_some(),
+                                    egui::Button::new(get_text("merge_down", self.language)),
+                                ).clicked() {
+                                    paint_app.merge_down(active_index);
+                                    ui.close_menu();
+                                }
+                                if ui.button(get_text("merge_visible", self.language)).clicked() {
+                                    paint_app.merge_visible();
+                                    ui.close_menu();
+                                }
+                                ui.separator();
+                                if ui.button(get_text("flatten_image", self.language)).clicked() {
+                                    paint_app.flatten();
+                                    ui.close_menu();
+                                }
+                            });
+                            
+                            ui.menu_button(get_text("menu_select", self.language), |ui| {
+                                if ui.button(format!("{}  (Ctrl+A)", get_text("select_all", self.language))).clicked() {
+                                    paint_app.select_all();
+                                    ui.close_menu();
+                                }
+                                if ui.add_enabled(
+                                    paint_app.document.selection.is_some(),
+                                    egui::Button::new(format!("{}  (Ctrl+D)", get_text("deselect", self.language))),
+                                ).clicked() {
+                                    paint_app.deselect();
+                                    ui.close_menu();
+                                }
+                                if ui.add_enabled(
+                                    paint_app.document.selection.is_some(),
+                                    egui::Button::new(format!("{}  (Ctrl+Shift+I)", get_text("invert_selection", self.language))),
+                                ).clicked() {
+                                    paint_app.invert_selection();
+                                    ui.close_menu();
+                                }
+                            });
+                            
                             ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
                                 ui.add_space(RustiqueTheme::SPACING_SM);
                                 ui.label(RustiqueTheme::muted_text("Ctrl+Z: Undo | Ctrl+Y: Redo | Ctrl+S: Save"));
+                                
+                                ui.add_space(RustiqueTheme::SPACING_LG);
+                                
+                                let history = &mut paint_app.document.history;
+                                let mut budget_mb = history.memory_budget() / MEGABYTE;
+                                if ui.add(
+                                    egui::DragValue::new(&mut budget_mb)
+                                        .clamp_range(16..=4096)
+                                        .suffix(" MB")
+                                ).changed() {
+                                    history.set_memory_budget(budget_mb * MEGABYTE);
+                                }
+                                ui.label(RustiqueTheme::muted_text(&format!(
+                                    "{}: {:.1} /",
+                                    get_text("history_memory", self.language),
+                                    history.memory_usage() as f32 / MEGABYTE as f32,
+                                )));
                             });
                         });
                         (undo_clicked, redo_clicked, return_clicked)
@@ -2156,8 +2439,8 @@ impl eframe::App for MyApp {
 
                 egui::CentralPanel::default().show(ctx, |ui| {
                     let available_size = ui.available_size();
-                    let canvas_width = paint_app.current_state.width as f32;
-                    let canvas_height = paint_app.current_state.height as f32;
+                    let canvas_width = paint_app.document.canvas.width as f32;
+                    let canvas_height = paint_app.document.canvas.height as f32;
                     let scale = (available_size.x / canvas_width).min(available_size.y / canvas_height);
                     let scaled_size = Vec2::new(canvas_width * scale * paint_app.zoom, canvas_height * scale * paint_app.zoom);
                     let canvas_rect = Rect::from_center_size(
@@ -2180,7 +2463,46 @@ impl eframe::App for MyApp {
                         paint_app.pan += response.drag_delta();
                     }
 
-                    if paint_app.current_tool == Tool::Line {
+                    let to_screen = to_canvas.inverse();
+                    paint_app.selection_tool.paint_outline(&painter, &to_screen, &paint_app.document);
+
+                    if let Some(floating) = &paint_app.document.floating {
+                        let bounds = Rect::from_min_size(
+                            Pos2::new(floating.x as f32, floating.y as f32),
+                            Vec2::new(floating.image.width as f32, floating.image.height as f32),
+                        );
+                        painter.rect_stroke(to_screen.transform_rect(bounds), 0.0, Stroke::new(1.0, RustiqueTheme::ACCENT_SECONDARY));
+                        
+                        if response.dragged_by(egui::PointerButton::Primary) {
+                            paint_app.floating_drag += response.drag_delta() * (canvas_width / canvas_rect.width());
+                            let step = Vec2::new(paint_app.floating_drag.x.trunc(), paint_app.floating_drag.y.trunc());
+                            if step != Vec2::ZERO {
+                                paint_app.document.move_floating(step.x as i32, step.y as i32);
+                                paint_app.floating_drag -= step;
+                                paint_app.texture_dirty = true;
+                            }
+                        }
+                        if response.drag_released() {
+                            paint_app.floating_drag = Vec2::ZERO;
+                        }
+                    } else if matches!(paint_app.current_tool, Tool::Select | Tool::MagicWand) {
+                        let magic_wand = paint_app.current_tool == Tool::MagicWand;
+                        if paint_app.selection_tool.handle_input(&response, &to_canvas, &mut paint_app.document, magic_wand) {
+                            paint_app.selection_tool.selection_changed();
+                        }
+                        paint_app.selection_tool.paint_preview(&painter, &to_screen, response.hover_pos());
+                    } else if paint_app.current_tool == Tool::Transform {
+                        paint_app.transform_tool.sync(&paint_app.document);
+                        paint_app.transform_tool.handle_input(&response, &to_canvas);
+                        if paint_app.transform_tool.update_preview(&paint_app.document) {
+                            paint_app.texture_dirty = true;
+                            ctx.request_repaint();
+                        }
+                        paint_app.transform_tool.paint_handles(&painter, &to_screen);
+                    } else if paint_app.current_tool == Tool::Crop {
+                        paint_app.crop_tool.handle_input(&response, &to_canvas, &paint_app.document);
+                        paint_app.crop_tool.paint(&painter, &to_screen);
+                    } else if paint_app.current_tool == Tool::Line {
                         if response.clicked() && !response.clicked_by(egui::PointerButton::Middle) {
                             let is_secondary = response.clicked_by(egui::PointerButton::Secondary);
                             if let Some(pos) = response.interact_pointer_pos() {
@@ -2266,7 +2588,7 @@ impl eframe::App for MyApp {
                                 let is_secondary = response.dragged_by(egui::PointerButton::Secondary) || 
                                                  response.clicked_by(egui::PointerButton::Secondary);
                                 
-                                if x < paint_app.current_state.width && y < paint_app.current_state.height {
+                                if x < paint_app.document.canvas.width && y < paint_app.document.canvas.height {
                                     match paint_app.current_tool {
                                         Tool::PaintBucket => paint_app.paint_bucket(x, y, is_secondary),
                                         Tool::ColorPicker => paint_app.pick_color(x, y, is_secondary),
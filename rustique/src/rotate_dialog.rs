@@ -0,0 +1,68 @@
+use eframe::egui;
+
+use rustique_core::{Document, Interpolation};
+
+use crate::localization::{Language, get_text};
+use crate::size_dialog::DialogOutcome;
+use crate::transform_tool::interpolation_name_key;
+use crate::ui_theme::RustiqueTheme;
+
+/// Arbitrary rotation of the whole image, or of the active layer only.
+pub struct RotateDialog {
+    whole_image: bool,
+    degrees: f32,
+    interpolation: Interpolation,
+}
+
+impl RotateDialog {
+    pub fn new(whole_image: bool) -> Self {
+        Self {
+            whole_image,
+            degrees: 0.0,
+            interpolation: Interpolation::Bicubic,
+        }
+    }
+
+    pub fn show(&mut self, ctx: &egui::Context, language: Language, document: &mut Document) -> DialogOutcome {
+        let mut outcome = DialogOutcome::Open;
+
+        egui::Window::new(get_text("rotate_arbitrary", language))
+            .collapsible(false)
+            .resizable(false)
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    ui.label(get_text("angle", language));
+                    ui.add(egui::DragValue::new(&mut self.degrees).speed(0.5).clamp_range(-360.0..=360.0).suffix("°"));
+                });
+                ui.label(RustiqueTheme::muted_text(&get_text("angle_hint", language)));
+
+                ui.add_space(RustiqueTheme::SPACING_SM);
+                ui.label(RustiqueTheme::muted_text(&get_text("resampling", language)));
+                ui.horizontal_wrapped(|ui| {
+                    for interpolation in Interpolation::all() {
+                        let label = get_text(interpolation_name_key(interpolation), language);
+                        if ui.selectable_label(self.interpolation == interpolation, label).clicked() {
+                            self.interpolation = interpolation;
+                        }
+                    }
+                });
+
+                ui.add_space(RustiqueTheme::SPACING_SM);
+                ui.horizontal(|ui| {
+                    if ui.button("OK").clicked() {
+                        let angle = self.degrees.to_radians();
+                        let changed = if self.whole_image {
+                            document.rotate_image_by(angle, self.interpolation)
+                        } else {
+                            document.rotate_layer_by(document.canvas.active_layer_index, angle, self.interpolation)
+                        };
+                        outcome = if changed { DialogOutcome::Applied } else { DialogOutcome::Cancelled };
+                    }
+                    if ui.button(get_text("cancel", language)).clicked() {
+                        outcome = DialogOutcome::Cancelled;
+                    }
+                });
+            });
+        outcome
+    }
+}
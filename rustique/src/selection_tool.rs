@@ -0,0 +1,291 @@
+use eframe::egui;
+use egui::{Color32, Modifiers, Painter, PointerButton, Pos2, Rect, Response, Stroke, Ui};
+use egui::emath::RectTransform;
+
+use rustique_core::{Document, Selection, SelectionMode};
+
+use crate::localization::{Language, get_text};
+use crate::ui_theme::RustiqueTheme;
+
+/// Length in screen pixels of each dash of the selection outline.
+const ANTS_DASH: f32 = 4.0;
+/// Distance in screen pixels at which a click closes a polygonal lasso.
+const CLOSE_DISTANCE: f32 = 8.0;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum SelectionShape {
+    Rectangle,
+    Ellipse,
+    Lasso,
+    Polygon,
+}
+
+impl SelectionShape {
+    pub fn all() -> [SelectionShape; 4] {
+        [SelectionShape::Rectangle, SelectionShape::Ellipse, SelectionShape::Lasso, SelectionShape::Polygon]
+    }
+
+    fn name_key(self) -> &'static str {
+        match self {
+            SelectionShape::Rectangle => "select_rectangle",
+            SelectionShape::Ellipse => "select_ellipse",
+            SelectionShape::Lasso => "select_lasso",
+            SelectionShape::Polygon => "select_polygon",
+        }
+    }
+}
+
+fn mode_name_key(mode: SelectionMode) -> &'static str {
+    match mode {
+        SelectionMode::Replace => "selection_replace",
+        SelectionMode::Add => "selection_add",
+        SelectionMode::Subtract => "selection_subtract",
+        SelectionMode::Intersect => "selection_intersect",
+    }
+}
+
+/// Selection tools: marquees, lassos and magic wand, with the shape being
+/// drawn and the marching ants of the current selection.
+pub struct SelectionTool {
+    pub shape: SelectionShape,
+    pub mode: SelectionMode,
+    pub tolerance: u8,
+    drag_start: Option<Pos2>,
+    points: Vec<Pos2>,
+    /// Outline of the document selection in canvas coordinates, rebuilt
+    /// after [`SelectionTool::selection_changed`].
+    outline: Option<Vec<[Pos2; 2]>>,
+}
+
+impl Default for SelectionTool {
+    fn default() -> Self {
+        Self {
+            shape: SelectionShape::Rectangle,
+            mode: SelectionMode::Replace,
+            tolerance: 32,
+            drag_start: None,
+            points: Vec::new(),
+            outline: None,
+        }
+    }
+}
+
+impl SelectionTool {
+    pub fn selection_changed(&mut self) {
+        self.outline = None;
+    }
+
+    pub fn cancel(&mut self) {
+        self.drag_start = None;
+        self.points.clear();
+    }
+
+    /// Shift adds to the selection, Alt subtracts and both intersect.
+    fn effective_mode(&self, modifiers: Modifiers) -> SelectionMode {
+        match (modifiers.shift, modifiers.alt) {
+            (true, true) => SelectionMode::Intersect,
+            (true, false) => SelectionMode::Add,
+            (false, true) => SelectionMode::Subtract,
+            (false, false) => self.mode,
+        }
+    }
+
+    /// Handles the pointer on the canvas. Returns `true` when the selection
+    /// of `document` changed.
+    pub fn handle_input(&mut self, response: &Response, to_canvas: &RectTransform, document: &mut Document, magic_wand: bool) -> bool {
+        let (width, height) = (document.width(), document.height());
+        let modifiers = response.ctx.input(|i| i.modifiers);
+        let pointer = response.interact_pointer_pos().map(|pos| to_canvas.transform_pos(pos));
+        let mode = self.effective_mode(modifiers);
+
+        let shape = if magic_wand {
+            match pointer {
+                Some(pos) if response.clicked_by(PointerButton::Primary) && pos.x >= 0.0 && pos.y >= 0.0 => {
+                    Some(Selection::magic_wand(&document.canvas, pos.x as usize, pos.y as usize, self.tolerance))
+                },
+                _ => None,
+            }
+        } else {
+            match self.shape {
+                SelectionShape::Rectangle | SelectionShape::Ellipse => {
+                    if response.drag_started_by(PointerButton::Primary) {
+                        self.drag_start = pointer;
+                    }
+                    if response.clicked_by(PointerButton::Primary) && mode == SelectionMode::Replace {
+                        self.drag_start = None;
+                        let changed = document.selection.is_some();
+                        document.deselect();
+                        return changed;
+                    }
+                    match (self.drag_start, pointer) {
+                        (Some(start), Some(end)) if response.drag_released() => {
+                            self.drag_start = None;
+                            let (a, b) = ((start.x, start.y), (end.x, end.y));
+                            Some(match self.shape {
+                                SelectionShape::Ellipse => Selection::ellipse(width, height, a, b),
+                                _ => Selection::rectangle(width, height, a, b),
+                            })
+                        },
+                        _ => None,
+                    }
+                },
+                SelectionShape::Lasso => {
+                    if response.drag_started_by(PointerButton::Primary) {
+                        self.points.clear();
+                    }
+                    if let Some(pos) = pointer
+                        && response.dragged_by(PointerButton::Primary)
+                        && self.points.last() != Some(&pos)
+                    {
+                        self.points.push(pos);
+                    }
+                    if response.drag_released() && !self.points.is_empty() {
+                        Some(self.take_polygon(width, height))
+                    } else {
+                        None
+                    }
+                },
+                SelectionShape::Polygon => {
+                    let enter = response.ctx.input(|i| i.key_pressed(egui::Key::Enter));
+                    let mut close = enter || response.double_clicked_by(PointerButton::Primary);
+                    if let Some(pos) = pointer
+                        && response.clicked_by(PointerButton::Primary)
+                        && !close
+                    {
+                        let to_screen = to_canvas.inverse();
+                        let near_first = self.points.first().is_some_and(|first| {
+                            to_screen.transform_pos(*first).distance(to_screen.transform_pos(pos)) < CLOSE_DISTANCE
+                        });
+                        if near_first && self.points.len() >= 3 {
+                            close = true;
+                        } else {
+                            self.points.push(pos);
+                        }
+                    }
+                    if close && !self.points.is_empty() {
+                        Some(self.take_polygon(width, height))
+                    } else {
+                        None
+                    }
+                },
+            }
+        };
+
+        if response.ctx.input(|i| i.key_pressed(egui::Key::Escape)) {
+            self.cancel();
+        }
+
+        match shape {
+            Some(shape) => {
+                document.select(shape, mode);
+                true
+            },
+            None => false,
+        }
+    }
+
+    fn take_polygon(&mut self, width: usize, height: usize) -> Selection {
+        let points: Vec<(f32, f32)> = self.points.drain(..).map(|pos| (pos.x, pos.y)).collect();
+        Selection::polygon(width, height, &points)
+    }
+
+    /// Draws the shape being made by the user.
+    pub fn paint_preview(&self, painter: &Painter, to_screen: &RectTransform, hover: Option<Pos2>) {
+        let stroke = Stroke::new(1.0, RustiqueTheme::ACCENT_SECONDARY);
+        let hover = hover.map(|pos| to_screen.inverse().transform_pos(pos));
+
+        if let (Some(start), Some(end)) = (self.drag_start, hover) {
+            let rect = Rect::from_two_pos(to_screen.transform_pos(start), to_screen.transform_pos(end));
+            match self.shape {
+                SelectionShape::Ellipse => {
+                    let points = (0..64)
+                        .map(|i| {
+                            let angle = i as f32 / 64.0 * std::f32::consts::TAU;
+                            rect.center() + egui::vec2(angle.cos() * rect.width() / 2.0, angle.sin() * rect.height() / 2.0)
+                        })
+                        .collect();
+                    painter.add(egui::Shape::closed_line(points, stroke));
+                },
+                _ => {
+                    painter.rect_stroke(rect, 0.0, stroke);
+                },
+            }
+        }
+
+        if !self.points.is_empty() {
+            let mut points: Vec<Pos2> = self.points.iter().map(|pos| to_screen.transform_pos(*pos)).collect();
+            if self.shape == SelectionShape::Polygon
+                && let Some(hover) = hover
+            {
+                points.push(to_screen.transform_pos(hover));
+            }
+            painter.add(egui::Shape::line(points, stroke));
+        }
+    }
+
+    /// Draws the marching ants around the selection of `document`.
+    pub fn paint_outline(&mut self, painter: &Painter, to_screen: &RectTransform, document: &Document) {
+        let Some(selection) = &document.selection else {
+            self.outline = None;
+            return;
+        };
+        let outline = self.outline.get_or_insert_with(|| {
+            selection
+                .outline()
+                .into_iter()
+                .map(|[a, b]| [Pos2::new(a.0 as f32, a.1 as f32), Pos2::new(b.0 as f32, b.1 as f32)])
+                .collect()
+        });
+
+        let time = painter.ctx().input(|i| i.time);
+        let phase = (time * 8.0) as f32 % (ANTS_DASH * 2.0);
+        let clip = painter.clip_rect();
+        for segment in outline.iter() {
+            let (a, b) = (to_screen.transform_pos(segment[0]), to_screen.transform_pos(segment[1]));
+            if !clip.intersects(Rect::from_two_pos(a, b).expand(1.0)) {
+                continue;
+            }
+
+            painter.line_segment([a, b], Stroke::new(1.0, Color32::WHITE));
+            let length = a.distance(b);
+            let direction = (b - a) / length.max(f32::EPSILON);
+            let mut t = -phase;
+            while t < length {
+                let (start, end) = (t.max(0.0), (t + ANTS_DASH).min(length));
+                if end > start {
+                    painter.line_segment([a + direction * start, a + direction * end], Stroke::new(1.0, Color32::BLACK));
+                }
+                t += ANTS_DASH * 2.0;
+            }
+        }
+        painter.ctx().request_repaint_after(std::time::Duration::from_millis(100));
+    }
+
+    /// Shape, mode and tolerance settings shown in the properties panel.
+    pub fn settings_ui(&mut self, ui: &mut Ui, language: Language, magic_wand: bool) {
+        if magic_wand {
+            ui.horizontal(|ui| {
+                ui.label(RustiqueTheme::muted_text(&get_text("tolerance", language)));
+                ui.add(egui::Slider::new(&mut self.tolerance, 0..=255));
+            });
+        } else {
+            ui.horizontal_wrapped(|ui| {
+                for shape in SelectionShape::all() {
+                    if ui.selectable_label(self.shape == shape, get_text(shape.name_key(), language)).clicked() {
+                        self.shape = shape;
+                        self.cancel();
+                    }
+                }
+            });
+        }
+
+        ui.add_space(RustiqueTheme::SPACING_XS);
+        ui.horizontal_wrapped(|ui| {
+            for mode in SelectionMode::all() {
+                if ui.selectable_label(self.mode == mode, get_text(mode_name_key(mode), language)).clicked() {
+                    self.mode = mode;
+                }
+            }
+        });
+    }
+}
@@ -0,0 +1,149 @@
+use eframe::egui;
+use egui::Vec2;
+
+use rustique_core::{Anchor, Document, Interpolation};
+
+use crate::localization::{Language, get_text};
+use crate::transform_tool::interpolation_name_key;
+use crate::ui_theme::RustiqueTheme;
+
+/// Largest width or height the dialogs allow, as for new documents.
+const MAX_SIZE: usize = 4000;
+
+fn anchor_symbol(anchor: Anchor) -> &'static str {
+    match anchor {
+        Anchor::TopLeft => "↖",
+        Anchor::Top => "↑",
+        Anchor::TopRight => "↗",
+        Anchor::Left => "←",
+        Anchor::Center => "•",
+        Anchor::Right => "→",
+        Anchor::BottomLeft => "↙",
+        Anchor::Bottom => "↓",
+        Anchor::BottomRight => "↘",
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum SizeDialogKind {
+    /// Adds or removes room around the content.
+    Canvas,
+    /// Scales the content.
+    Image,
+}
+
+pub enum DialogOutcome {
+    Open,
+    Cancelled,
+    Applied,
+}
+
+/// Canvas Size and Image Size dialogs.
+pub struct SizeDialog {
+    kind: SizeDialogKind,
+    original: (usize, usize),
+    width: usize,
+    height: usize,
+    keep_ratio: bool,
+    anchor: Anchor,
+    interpolation: Interpolation,
+}
+
+impl SizeDialog {
+    pub fn new(kind: SizeDialogKind, document: &Document) -> Self {
+        let original = (document.width(), document.height());
+        Self {
+            kind,
+            original,
+            width: original.0,
+            height: original.1,
+            keep_ratio: kind == SizeDialogKind::Image,
+            anchor: Anchor::Center,
+            interpolation: Interpolation::Bicubic,
+        }
+    }
+
+    pub fn show(&mut self, ctx: &egui::Context, language: Language, document: &mut Document) -> DialogOutcome {
+        let title = match self.kind {
+            SizeDialogKind::Canvas => get_text("canvas_size", language),
+            SizeDialogKind::Image => get_text("image_size", language),
+        };
+        let mut outcome = DialogOutcome::Open;
+
+        egui::Window::new(title)
+            .collapsible(false)
+            .resizable(false)
+            .show(ctx, |ui| {
+                ui.label(RustiqueTheme::muted_text(&format!(
+                    "{}: {} × {} px",
+                    get_text("current_size", language),
+                    self.original.0,
+                    self.original.1,
+                )));
+                ui.add_space(RustiqueTheme::SPACING_SM);
+
+                let (old_width, old_height) = (self.width, self.height);
+                egui::Grid::new("size_dialog_fields").num_columns(2).show(ui, |ui| {
+                    ui.label(get_text("width", language));
+                    ui.add(egui::DragValue::new(&mut self.width).speed(1).clamp_range(1..=MAX_SIZE).suffix(" px"));
+                    ui.end_row();
+                    ui.label(get_text("height", language));
+                    ui.add(egui::DragValue::new(&mut self.height).speed(1).clamp_range(1..=MAX_SIZE).suffix(" px"));
+                    ui.end_row();
+                });
+                ui.checkbox(&mut self.keep_ratio, get_text("keep_ratio", language));
+                if self.keep_ratio {
+                    let ratio = self.original.0 as f32 / self.original.1 as f32;
+                    if self.width != old_width {
+                        self.height = ((self.width as f32 / ratio).round() as usize).clamp(1, MAX_SIZE);
+                    } else if self.height != old_height {
+                        self.width = ((self.height as f32 * ratio).round() as usize).clamp(1, MAX_SIZE);
+                    }
+                }
+
+                ui.add_space(RustiqueTheme::SPACING_SM);
+                match self.kind {
+                    SizeDialogKind::Canvas => {
+                        ui.label(RustiqueTheme::muted_text(&get_text("anchor", language)));
+                        egui::Grid::new("size_dialog_anchor").spacing(Vec2::splat(2.0)).show(ui, |ui| {
+                            for (i, anchor) in Anchor::all().into_iter().enumerate() {
+                                let button = egui::SelectableLabel::new(self.anchor == anchor, anchor_symbol(anchor));
+                                if ui.add_sized(Vec2::splat(24.0), button).clicked() {
+                                    self.anchor = anchor;
+                                }
+                                if i % 3 == 2 {
+                                    ui.end_row();
+                                }
+                            }
+                        });
+                    },
+                    SizeDialogKind::Image => {
+                        ui.label(RustiqueTheme::muted_text(&get_text("resampling", language)));
+                        ui.horizontal_wrapped(|ui| {
+                            for interpolation in Interpolation::all() {
+                                let label = get_text(interpolation_name_key(interpolation), language);
+                                if ui.selectable_label(self.interpolation == interpolation, label).clicked() {
+                                    self.interpolation = interpolation;
+                                }
+                            }
+                        });
+                    },
+                }
+
+                ui.add_space(RustiqueTheme::SPACING_SM);
+                ui.horizontal(|ui| {
+                    if ui.button("OK").clicked() {
+                        let changed = match self.kind {
+                            SizeDialogKind::Canvas => document.resize_canvas(self.width, self.height, self.anchor),
+                            SizeDialogKind::Image => document.resize_image(self.width, self.height, self.interpolation),
+                        };
+                        outcome = if changed { DialogOutcome::Applied } else { DialogOutcome::Cancelled };
+                    }
+                    if ui.button(get_text("cancel", language)).clicked() {
+                        outcome = DialogOutcome::Cancelled;
+                    }
+                });
+            });
+        outcome
+    }
+}
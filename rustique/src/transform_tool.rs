@@ -0,0 +1,329 @@
+use eframe::egui;
+use egui::{Color32, PointerButton, Pos2, Rect, Response, Stroke, Ui, Vec2};
+use egui::emath::RectTransform;
+
+use rustique_core::{Affine, CanvasState, Document, Interpolation, LayerId};
+
+use crate::localization::{Language, get_text};
+use crate::ui_theme::RustiqueTheme;
+
+/// Size in screen pixels of the square handles around the transformed box.
+pub const HANDLE_SIZE: f32 = 8.0;
+/// Smallest scale factor a handle can shrink the layer to.
+const MIN_SCALE: f32 = 0.01;
+/// Rotation step while Shift is held.
+const ROTATION_SNAP: f32 = std::f32::consts::PI / 12.0;
+
+pub fn interpolation_name_key(interpolation: Interpolation) -> &'static str {
+    match interpolation {
+        Interpolation::Nearest => "interpolation_nearest",
+        Interpolation::Bilinear => "interpolation_bilinear",
+        Interpolation::Bicubic => "interpolation_bicubic",
+        Interpolation::Lanczos => "interpolation_lanczos",
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Drag {
+    Move,
+    Rotate { start_angle: f32, start_rotation: f32 },
+    /// Scaling from the handle at `(hx, hy)`, each -1, 0 or 1 across the box.
+    Scale { hx: f32, hy: f32 },
+    Skew { hx: f32, hy: f32 },
+}
+
+/// Transform being set up on a layer. Its content is scaled, skewed and
+/// rotated around the center of `bounds`, then moved by `offset`.
+struct Session {
+    layer: LayerId,
+    bounds: Rect,
+    offset: Vec2,
+    scale: Vec2,
+    skew: Vec2,
+    rotation: f32,
+}
+
+impl Session {
+    /// Transform after scaling, around the origin.
+    fn after_scale(&self) -> Affine {
+        let center = self.bounds.center() + self.offset;
+        Affine::skew(self.skew.x, self.skew.y)
+            .then(&Affine::rotate(self.rotation))
+            .then(&Affine::translate(center.x, center.y))
+    }
+
+    fn matrix(&self) -> Affine {
+        let center = self.bounds.center();
+        Affine::translate(-center.x, -center.y)
+            .then(&Affine::scale(self.scale.x, self.scale.y))
+            .then(&self.after_scale())
+    }
+
+    fn is_identity(&self) -> bool {
+        self.offset == Vec2::ZERO && self.scale == Vec2::splat(1.0) && self.skew == Vec2::ZERO && self.rotation == 0.0
+    }
+
+    /// Where the point of the original box at `(hx, hy)` ends up on the canvas.
+    fn handle_pos(&self, hx: f32, hy: f32) -> Pos2 {
+        let point = self.bounds.center() + Vec2::new(hx, hy) * self.bounds.size() / 2.0;
+        let (x, y) = self.matrix().apply(point.x, point.y);
+        Pos2::new(x, y)
+    }
+
+    /// Moves the handle at `(hx, hy)` to `pointer`, keeping the opposite one
+    /// in place.
+    fn scale_to(&mut self, hx: f32, hy: f32, pointer: Pos2, keep_ratio: bool) {
+        let Some(inverse) = self.after_scale().inverse() else {
+            return;
+        };
+        let (qx, qy) = inverse.apply(pointer.x, pointer.y);
+        let size = self.bounds.size();
+
+        let axis_scale = |h: f32, q: f32, length: f32, scale: f32| {
+            if h == 0.0 || length == 0.0 {
+                return scale;
+            }
+            let anchor = -h * length / 2.0 * scale;
+            let new_scale = (q - anchor) / (h * length);
+            if new_scale.abs() < MIN_SCALE { MIN_SCALE.copysign(new_scale) } else { new_scale }
+        };
+        let mut scale = Vec2::new(axis_scale(hx, qx, size.x, self.scale.x), axis_scale(hy, qy, size.y, self.scale.y));
+        if keep_ratio && hx != 0.0 && hy != 0.0 {
+            let (fx, fy) = (scale.x / self.scale.x, scale.y / self.scale.y);
+            let factor = if fx.abs() > fy.abs() { fx } else { fy };
+            scale = self.scale * factor;
+        }
+
+        // Shift of the box center, in the frame right after scaling.
+        let shift = Vec2::new(hx * size.x * (scale.x - self.scale.x), hy * size.y * (scale.y - self.scale.y)) / 2.0;
+        let linear = Affine { tx: 0.0, ty: 0.0, ..self.after_scale() };
+        let (dx, dy) = linear.apply(shift.x, shift.y);
+        self.offset += Vec2::new(dx, dy);
+        self.scale = scale;
+    }
+
+    /// Shears the box so that the edge handle at `(hx, hy)` follows `pointer`.
+    fn skew_to(&mut self, hx: f32, hy: f32, pointer: Pos2) {
+        let center = self.bounds.center() + self.offset;
+        let Some(inverse) = Affine::rotate(self.rotation).then(&Affine::translate(center.x, center.y)).inverse() else {
+            return;
+        };
+        let (qx, qy) = inverse.apply(pointer.x, pointer.y);
+        let edge = Vec2::new(hx, hy) * self.bounds.size() * self.scale / 2.0;
+        if hy != 0.0 && edge.y.abs() > f32::EPSILON {
+            self.skew.x = qx / edge.y;
+        }
+        if hx != 0.0 && edge.x.abs() > f32::EPSILON {
+            self.skew.y = qy / edge.x;
+        }
+    }
+}
+
+/// Free transform of the active layer with on-canvas handles: drag inside
+/// the box to move, the handles to scale (Shift keeps the ratio), Ctrl and
+/// an edge handle to skew, and outside the box to rotate.
+#[derive(Default)]
+pub struct TransformTool {
+    pub interpolation: Interpolation,
+    session: Option<Session>,
+    drag: Option<Drag>,
+    preview: Option<CanvasState>,
+    preview_dirty: bool,
+}
+
+impl TransformTool {
+    pub fn is_active(&self) -> bool {
+        self.session.is_some()
+    }
+
+    /// Canvas with the transform applied, shown instead of the document.
+    pub fn preview(&self) -> Option<&CanvasState> {
+        self.preview.as_ref()
+    }
+
+    pub fn cancel(&mut self) {
+        self.session = None;
+        self.drag = None;
+        self.preview_dirty |= self.preview.take().is_some();
+    }
+
+    /// Applies the transform to `document`. Returns `true` when the layer
+    /// changed.
+    pub fn apply(&mut self, document: &mut Document) -> bool {
+        let session = self.session.take();
+        self.cancel();
+        match session {
+            Some(session) if !session.is_identity() => match document.canvas.layer_index(session.layer) {
+                Some(index) => document.transform_layer(index, &session.matrix(), self.interpolation),
+                None => false,
+            },
+            _ => false,
+        }
+    }
+
+    /// Follows the active layer of `document`, starting over when it changes.
+    pub fn sync(&mut self, document: &Document) {
+        let index = document.canvas.active_layer_index;
+        let Some(layer) = document.canvas.layers.get(index) else {
+            self.cancel();
+            return;
+        };
+        if self.session.as_ref().is_some_and(|session| session.layer == layer.id) {
+            return;
+        }
+
+        self.cancel();
+        if document.floating.is_some() || !document.canvas.can_transform_layer(index) {
+            return;
+        }
+        let (x, y, width, height) = document
+            .canvas
+            .content_bounds(index)
+            .unwrap_or((0, 0, document.width(), document.height()));
+        self.session = Some(Session {
+            layer: layer.id,
+            bounds: Rect::from_min_size(Pos2::new(x as f32, y as f32), Vec2::new(width as f32, height as f32)),
+            offset: Vec2::ZERO,
+            scale: Vec2::splat(1.0),
+            skew: Vec2::ZERO,
+            rotation: 0.0,
+        });
+    }
+
+    /// Handles the pointer on the canvas.
+    pub fn handle_input(&mut self, response: &Response, to_canvas: &RectTransform) {
+        if response.drag_released() && self.drag.take().is_some() {
+            self.preview_dirty = true;
+        }
+        let Some(session) = &mut self.session else {
+            return;
+        };
+        let modifiers = response.ctx.input(|i| i.modifiers);
+        let Some(screen_pos) = response.interact_pointer_pos() else {
+            return;
+        };
+        let pointer = to_canvas.transform_pos(screen_pos);
+        let center = session.bounds.center() + session.offset;
+        let angle = (pointer - center).angle();
+
+        if response.drag_started_by(PointerButton::Primary) {
+            let to_screen = to_canvas.inverse();
+            let handle = handles().find(|&(hx, hy)| {
+                to_screen.transform_pos(session.handle_pos(hx, hy)).distance(screen_pos) <= HANDLE_SIZE
+            });
+            let inside = session
+                .matrix()
+                .inverse()
+                .map(|inverse| inverse.apply(pointer.x, pointer.y))
+                .is_some_and(|(x, y)| session.bounds.contains(Pos2::new(x, y)));
+
+            self.drag = Some(match handle {
+                Some((hx, hy)) if modifiers.command && (hx == 0.0) != (hy == 0.0) => Drag::Skew { hx, hy },
+                Some((hx, hy)) => Drag::Scale { hx, hy },
+                None if inside => Drag::Move,
+                None => Drag::Rotate { start_angle: angle, start_rotation: session.rotation },
+            });
+        }
+
+        if response.dragged_by(PointerButton::Primary)
+            && let Some(drag) = self.drag
+        {
+            match drag {
+                Drag::Move => session.offset += response.drag_delta() * to_canvas.scale(),
+                Drag::Rotate { start_angle, start_rotation } => {
+                    let mut rotation = start_rotation + angle - start_angle;
+                    if modifiers.shift {
+                        rotation = (rotation / ROTATION_SNAP).round() * ROTATION_SNAP;
+                    }
+                    session.rotation = rotation;
+                },
+                Drag::Scale { hx, hy } => session.scale_to(hx, hy, pointer, modifiers.shift),
+                Drag::Skew { hx, hy } => session.skew_to(hx, hy, pointer),
+            }
+            self.preview_dirty = true;
+        }
+    }
+
+    /// Rebuilds the preview after a change. It uses nearest neighbor while
+    /// dragging to stay responsive. Returns `true` when it was rebuilt.
+    pub fn update_preview(&mut self, document: &Document) -> bool {
+        if !self.preview_dirty {
+            return false;
+        }
+        self.preview_dirty = false;
+        self.preview = self.session.as_ref().and_then(|session| {
+            let index = document.canvas.layer_index(session.layer)?;
+            let interpolation = if self.drag.is_some() { Interpolation::Nearest } else { self.interpolation };
+            (!session.is_identity()).then(|| document.preview_transform(index, &session.matrix(), interpolation))
+        });
+        true
+    }
+
+    /// Draws the transformed box and its handles.
+    pub fn paint_handles(&self, painter: &egui::Painter, to_screen: &RectTransform) {
+        let Some(session) = &self.session else {
+            return;
+        };
+        let stroke = Stroke::new(1.0, RustiqueTheme::ACCENT_SECONDARY);
+        let corners = [(-1.0, -1.0), (1.0, -1.0), (1.0, 1.0), (-1.0, 1.0)]
+            .map(|(hx, hy)| to_screen.transform_pos(session.handle_pos(hx, hy)));
+        painter.add(egui::Shape::closed_line(corners.to_vec(), stroke));
+
+        for (hx, hy) in handles() {
+            let pos = to_screen.transform_pos(session.handle_pos(hx, hy));
+            painter.rect(Rect::from_center_size(pos, Vec2::splat(HANDLE_SIZE)), 0.0, Color32::WHITE, stroke);
+        }
+        painter.circle_stroke(to_screen.transform_pos(session.handle_pos(0.0, 0.0)), HANDLE_SIZE / 2.0, stroke);
+    }
+
+    /// Interpolation, flips and the buttons ending the transform. Returns
+    /// `true` when the document changed.
+    pub fn settings_ui(&mut self, ui: &mut Ui, language: Language, document: &mut Document) -> bool {
+        ui.horizontal_wrapped(|ui| {
+            for interpolation in Interpolation::all() {
+                let label = get_text(interpolation_name_key(interpolation), language);
+                if ui.selectable_label(self.interpolation == interpolation, label).clicked() {
+                    self.interpolation = interpolation;
+                    self.preview_dirty = true;
+                }
+            }
+        });
+
+        let Some(session) = &mut self.session else {
+            ui.label(RustiqueTheme::muted_text(&get_text("transform_unavailable", language)));
+            return false;
+        };
+
+        ui.add_space(RustiqueTheme::SPACING_XS);
+        ui.horizontal(|ui| {
+            if ui.button(get_text("flip_horizontal", language)).clicked() {
+                session.scale.x = -session.scale.x;
+                self.preview_dirty = true;
+            }
+            if ui.button(get_text("flip_vertical", language)).clicked() {
+                session.scale.y = -session.scale.y;
+                self.preview_dirty = true;
+            }
+        });
+
+        ui.add_space(RustiqueTheme::SPACING_XS);
+        let mut changed = false;
+        ui.horizontal(|ui| {
+            if ui.button(get_text("apply", language)).clicked() {
+                changed = self.apply(document);
+            }
+            if ui.button(get_text("cancel", language)).clicked() {
+                self.cancel();
+            }
+        });
+        changed
+    }
+}
+
+/// The eight handles around a box, as `(hx, hy)` positions across it.
+pub fn handles() -> impl Iterator<Item = (f32, f32)> {
+    [-1.0, 0.0, 1.0]
+        .into_iter()
+        .flat_map(|hy| [-1.0, 0.0, 1.0].into_iter().map(move |hx| (hx, hy)))
+        .filter(|&(hx, hy)| (hx, hy) != (0.0, 0.0))
+}
@@ -101,6 +101,22 @@ impl ToolIcons {
         IconWidget::new("line_tool", "📏", Vec2::new(24.0, 24.0))
     }
     
+    pub fn select() -> IconWidget {
+        IconWidget::new("select_tool", "⬚", Vec2::new(24.0, 24.0))
+    }
+    
+    pub fn magic_wand() -> IconWidget {
+        IconWidget::new("magic_wand_tool", "✨", Vec2::new(24.0, 24.0))
+    }
+    
+    pub fn transform() -> IconWidget {
+        IconWidget::new("transform_tool", "⤡", Vec2::new(24.0, 24.0))
+    }
+    
+    pub fn crop() -> IconWidget {
+        IconWidget::new("crop_tool", "⛶", Vec2::new(24.0, 24.0))
+    }
+    
     pub fn undo() -> IconWidget {
         IconWidget::new("undo_icon", "↶", Vec2::new(20.0, 20.0)).with_color(Color32::WHITE)
     }
@@ -144,6 +160,38 @@ impl ToolIcons {
     pub fn edit() -> IconWidget {
         IconWidget::new("layer_edit", "✏️", Vec2::new(16.0, 16.0))
     }
+    
+    pub fn group() -> IconWidget {
+        IconWidget::new("layer_group", "📁", Vec2::new(18.0, 18.0))
+    }
+    
+    pub fn group_expanded() -> IconWidget {
+        IconWidget::new("group_expanded", "⏷", Vec2::new(14.0, 14.0))
+    }
+    
+    pub fn group_collapsed() -> IconWidget {
+        IconWidget::new("group_collapsed", "⏵", Vec2::new(14.0, 14.0))
+    }
+    
+    pub fn layer_mask() -> IconWidget {
+        IconWidget::new("layer_mask", "◐", Vec2::new(16.0, 16.0))
+    }
+    
+    pub fn clipped() -> IconWidget {
+        IconWidget::new("layer_clipped", "↳", Vec2::new(16.0, 16.0))
+    }
+    
+    pub fn lock_pixels() -> IconWidget {
+        IconWidget::new("lock_pixels", "🔒", Vec2::new(16.0, 16.0))
+    }
+    
+    pub fn lock_alpha() -> IconWidget {
+        IconWidget::new("lock_alpha", "▦", Vec2::new(16.0, 16.0))
+    }
+    
+    pub fn lock_position() -> IconWidget {
+        IconWidget::new("lock_position", "✥", Vec2::new(16.0, 16.0))
+    }
 
     pub fn brush_text() -> RichText {
         RichText::new("🖌️").size(20.0)